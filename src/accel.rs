@@ -0,0 +1,306 @@
+//! DXR acceleration structures
+
+use crate::buffers;
+use crate::caps::RaytracingTier;
+use crate::commands;
+use crate::d3d12_ext::*;
+use crate::os_helpers::{self, DxError};
+
+use winapi::shared::dxgiformat::*;
+use winapi::shared::winerror::*;
+use winapi::um::d3d12::*;
+
+use std::mem::{size_of_val, zeroed};
+
+/// A built acceleration structure, and the buffers its build read from.
+/// `scratch` and `instances` have to stay alive until the command list that built `result` finishes on the GPU.
+pub struct AccelerationStructure {
+    pub result: d3d12::Resource,
+    pub scratch: d3d12::Resource,
+    /// Instance descriptions for a top-level structure. Null for bottom-level ones.
+    pub instances: d3d12::Resource,
+}
+
+impl AccelerationStructure {
+    /// Release the build's inputs once it has finished, keeping `result`
+    pub fn release_build_buffers(&mut self) {
+        for buffer in [&mut self.scratch, &mut self.instances].iter_mut() {
+            if !buffer.is_null() {
+                unsafe {
+                    buffer.destroy();
+                }
+                **buffer = d3d12::Resource::null();
+            }
+        }
+    }
+}
+
+fn not_supported(call: &str) -> DxError {
+    DxError {
+        hr: DXGI_ERROR_UNSUPPORTED,
+        location: format!("{}:{}", file!(), line!()),
+        call: call.to_string(),
+    }
+}
+
+/// Acceleration structures live in default-heap UAV buffers, in their own resource state
+fn create_accel_buffer(
+    device: &d3d12::Device,
+    size: u64,
+    initial_state: D3D12_RESOURCE_STATES,
+    name: &str,
+) -> Result<d3d12::Resource, DxError> {
+    let size = size.div_ceil(D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BYTE_ALIGNMENT)
+        * D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BYTE_ALIGNMENT;
+    let buffer = buffers::create_buffer(
+        device,
+        size,
+        D3D12_HEAP_TYPE_DEFAULT,
+        D3D12_RESOURCE_FLAG_ALLOW_UNORDERED_ACCESS,
+        initial_state,
+    )?;
+    os_helpers::set_name(&buffer, name);
+
+    Ok(buffer)
+}
+
+/// Size the buffers for `inputs`, then record a build of them into `cmd_list`
+fn build(
+    device: &d3d12::Device,
+    cmd_list: &d3d12::GraphicsCommandList,
+    inputs: &D3D12_BUILD_RAYTRACING_ACCELERATION_STRUCTURE_INPUTS,
+    name: &str,
+) -> Result<AccelerationStructure, DxError> {
+    // Device5 and CommandList4 only exist on runtimes and drivers that know DXR
+    let device5 = match unsafe { device.cast::<ID3D12Device5>() } {
+        (device5, hr) if SUCCEEDED(hr) => device5,
+        _ => return Err(not_supported("ID3D12Device::QueryInterface(ID3D12Device5)")),
+    };
+
+    let mut prebuild_info: D3D12_RAYTRACING_ACCELERATION_STRUCTURE_PREBUILD_INFO =
+        unsafe { zeroed() };
+    unsafe {
+        device5.GetRaytracingAccelerationStructurePrebuildInfo(inputs, &mut prebuild_info);
+        device5.destroy();
+    }
+    log::debug!(
+        "{}: {} bytes, with {} bytes of scratch",
+        name,
+        prebuild_info.ResultDataMaxSizeInBytes,
+        prebuild_info.ScratchDataSizeInBytes
+    );
+
+    let result = create_accel_buffer(
+        device,
+        prebuild_info.ResultDataMaxSizeInBytes,
+        D3D12_RESOURCE_STATE_RAYTRACING_ACCELERATION_STRUCTURE,
+        name,
+    )?;
+    let scratch = match create_accel_buffer(
+        device,
+        prebuild_info.ScratchDataSizeInBytes,
+        D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+        &format!("{} Scratch", name),
+    ) {
+        Ok(scratch) => scratch,
+        Err(err) => {
+            unsafe {
+                result.destroy();
+            }
+            return Err(err);
+        }
+    };
+
+    let cmd_list4 = match unsafe { cmd_list.cast::<ID3D12GraphicsCommandList4>() } {
+        (cmd_list4, hr) if SUCCEEDED(hr) => cmd_list4,
+        _ => {
+            unsafe {
+                result.destroy();
+                scratch.destroy();
+            }
+            return Err(not_supported(
+                "ID3D12GraphicsCommandList::QueryInterface(ID3D12GraphicsCommandList4)",
+            ));
+        }
+    };
+
+    let build_desc = D3D12_BUILD_RAYTRACING_ACCELERATION_STRUCTURE_DESC {
+        DestAccelerationStructureData: result.gpu_virtual_address(),
+        Inputs: *inputs,
+        SourceAccelerationStructureData: 0,
+        ScratchAccelerationStructureData: scratch.gpu_virtual_address(),
+    };
+    unsafe {
+        cmd_list4.BuildRaytracingAccelerationStructure(&build_desc, 0, std::ptr::null());
+        cmd_list4.destroy();
+    }
+
+    // Nothing can read the structure until the build's writes land
+    commands::uav_barrier(cmd_list, result);
+
+    Ok(AccelerationStructure {
+        result,
+        scratch,
+        instances: d3d12::Resource::null(),
+    })
+}
+
+/// An indexed triangle list in GPU memory, to build a BLAS over
+#[derive(Copy, Clone)]
+pub struct TriangleGeometry {
+    /// Starts each vertex with its `float3` position
+    pub vertex_buffer: d3d12::Resource,
+    pub vertex_count: u32,
+    pub vertex_stride: u64,
+    /// `u32` indices, three per triangle
+    pub index_buffer: d3d12::Resource,
+    pub index_count: u32,
+}
+
+/// Record a bottom-level build over one triangle list.
+/// It isn't marked opaque, so any-hit shaders get to alpha test it.
+pub fn build_blas(
+    device: &d3d12::Device,
+    raytracing_tier: RaytracingTier,
+    cmd_list: &d3d12::GraphicsCommandList,
+    geometry: &TriangleGeometry,
+) -> Result<AccelerationStructure, DxError> {
+    if raytracing_tier == RaytracingTier::NotSupported {
+        return Err(not_supported("build_blas"));
+    }
+
+    let geometry_desc = D3D12_RAYTRACING_GEOMETRY_DESC {
+        Type: D3D12_RAYTRACING_GEOMETRY_TYPE_TRIANGLES,
+        Flags: D3D12_RAYTRACING_GEOMETRY_FLAG_NONE,
+        Triangles: D3D12_RAYTRACING_GEOMETRY_TRIANGLES_DESC {
+            Transform3x4: 0,
+            IndexFormat: DXGI_FORMAT_R32_UINT,
+            VertexFormat: DXGI_FORMAT_R32G32B32_FLOAT,
+            IndexCount: geometry.index_count,
+            VertexCount: geometry.vertex_count,
+            IndexBuffer: geometry.index_buffer.gpu_virtual_address(),
+            VertexBuffer: D3D12_GPU_VIRTUAL_ADDRESS_AND_STRIDE {
+                StartAddress: geometry.vertex_buffer.gpu_virtual_address(),
+                StrideInBytes: geometry.vertex_stride,
+            },
+        },
+    };
+    let inputs = D3D12_BUILD_RAYTRACING_ACCELERATION_STRUCTURE_INPUTS {
+        Type: D3D12_RAYTRACING_ACCELERATION_STRUCTURE_TYPE_BOTTOM_LEVEL,
+        Flags: D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAG_PREFER_FAST_TRACE,
+        NumDescs: 1,
+        DescsLayout: D3D12_ELEMENTS_LAYOUT_ARRAY,
+        InstanceDescsOrGeometryDescs: &geometry_desc as *const _ as u64,
+    };
+
+    build(device, cmd_list, &inputs, "BLAS")
+}
+
+/// A row-major 3x4 transform that leaves an instance where its BLAS put it
+pub const IDENTITY_3X4: [f32; 12] = [
+    1.0, 0.0, 0.0, 0.0, //
+    0.0, 1.0, 0.0, 0.0, //
+    0.0, 0.0, 1.0, 0.0, //
+];
+
+/// One placement of a BLAS in a top-level structure
+#[derive(Copy, Clone)]
+pub struct Instance {
+    /// GPU address of the BLAS
+    pub blas: u64,
+    /// Row-major 3x4, from the BLAS's space into the world
+    pub transform: [f32; 12],
+    /// What `InstanceID()` returns in hit shaders. Only the low 24 bits are kept.
+    pub instance_id: u32,
+    /// Which hit group record this instance's hits start at, before the ray type's index is added. Also 24 bits.
+    pub hit_group_index: u32,
+}
+
+/// `count` copies of `blas` on a square grid in the z = 0 plane, `spacing` apart and centered on the origin.
+/// Instance IDs count up from 0 in row order, and every instance uses the first hit group records.
+pub fn grid_instances(blas: u64, count: u32, spacing: f32) -> Vec<Instance> {
+    let columns = (count as f32).sqrt().ceil().max(1.0) as u32;
+    let rows = count.div_ceil(columns);
+
+    (0..count)
+        .map(|id| {
+            let column = id % columns;
+            let row = id / columns;
+            // Row 0 at the top, like reading order
+            let x = (column as f32 - (columns - 1) as f32 / 2.0) * spacing;
+            let y = ((rows - 1) as f32 / 2.0 - row as f32) * spacing;
+
+            let mut transform = IDENTITY_3X4;
+            transform[3] = x;
+            transform[7] = y;
+
+            Instance {
+                blas,
+                transform,
+                instance_id: id,
+                hit_group_index: 0,
+            }
+        })
+        .collect()
+}
+
+/// Record a top-level build over `instances`
+pub fn build_tlas(
+    device: &d3d12::Device,
+    raytracing_tier: RaytracingTier,
+    cmd_list: &d3d12::GraphicsCommandList,
+    instances: &[Instance],
+) -> Result<AccelerationStructure, DxError> {
+    if raytracing_tier == RaytracingTier::NotSupported {
+        return Err(not_supported("build_tlas"));
+    }
+
+    let instance_descs: Vec<D3D12_RAYTRACING_INSTANCE_DESC> = instances
+        .iter()
+        .map(|instance| D3D12_RAYTRACING_INSTANCE_DESC {
+            Transform: instance.transform,
+            InstanceID_InstanceMask: (instance.instance_id & 0x00ff_ffff) | (0xff << 24),
+            InstanceContributionToHitGroupIndex_Flags: (instance.hit_group_index & 0x00ff_ffff)
+                | (D3D12_RAYTRACING_INSTANCE_FLAG_NONE << 24),
+            AccelerationStructure: instance.blas,
+        })
+        .collect();
+
+    // Upload buffers are placed well past the 16 byte alignment instance descs need
+    let instance_bytes = unsafe {
+        std::slice::from_raw_parts(
+            instance_descs.as_ptr() as *const u8,
+            size_of_val(instance_descs.as_slice()),
+        )
+    };
+    let instance_buffer =
+        buffers::create_upload_buffer(device, (instance_bytes.len() as u64).max(1))?;
+    os_helpers::set_name(&instance_buffer, "TLAS Instances");
+    if let Err(err) = buffers::upload_bytes(&instance_buffer, instance_bytes) {
+        unsafe {
+            instance_buffer.destroy();
+        }
+        return Err(err);
+    }
+
+    let inputs = D3D12_BUILD_RAYTRACING_ACCELERATION_STRUCTURE_INPUTS {
+        Type: D3D12_RAYTRACING_ACCELERATION_STRUCTURE_TYPE_TOP_LEVEL,
+        Flags: D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAG_PREFER_FAST_TRACE,
+        NumDescs: instance_descs.len() as u32,
+        DescsLayout: D3D12_ELEMENTS_LAYOUT_ARRAY,
+        InstanceDescsOrGeometryDescs: instance_buffer.gpu_virtual_address(),
+    };
+
+    match build(device, cmd_list, &inputs, "TLAS") {
+        Ok(mut tlas) => {
+            tlas.instances = instance_buffer;
+            Ok(tlas)
+        }
+        Err(err) => {
+            unsafe {
+                instance_buffer.destroy();
+            }
+            Err(err)
+        }
+    }
+}
@@ -0,0 +1,422 @@
+//! Adapter enumeration, selection, and reporting
+
+use crate::caps;
+use crate::check_hr_err;
+use crate::os_helpers::{self, DxError};
+use crate::status;
+use crate::MiB;
+
+use serde::Serialize;
+use winapi::shared::dxgi::*;
+use winapi::shared::ntdef::LUID;
+use winapi::shared::winerror::*;
+use winapi::um::d3dcommon::D3D_FEATURE_LEVEL;
+use winapi::Interface;
+
+use std::mem::zeroed;
+
+/// What we report about each adapter, for both the text and JSON listings
+#[derive(Serialize)]
+pub struct AdapterInfo {
+    pub index: u32,
+    pub description: String,
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub subsys_id: u32,
+    pub revision: u32,
+    pub software: bool,
+    /// Identifier for `--adapter-luid`, as "high:low"
+    pub luid: String,
+
+    // Memory sizes are in bytes
+    pub dedicated_video_memory: usize,
+    pub dedicated_system_memory: usize,
+    pub shared_system_memory: usize,
+
+    /// Highest supported feature level, spelled like `--feature-level`
+    pub feature_level: Option<String>,
+    pub raytracing_tier: Option<String>,
+}
+
+/// A DXGI adapter, released when it's dropped
+pub struct Adapter(d3d12::Adapter1);
+
+impl Adapter {
+    pub fn from_raw(adapter: d3d12::Adapter1) -> Self {
+        Adapter(adapter)
+    }
+
+    /// The underlying pointer, for creating devices on the adapter. Only valid while `self` is.
+    pub fn raw(&self) -> &d3d12::Adapter1 {
+        &self.0
+    }
+
+    fn desc1(&self) -> DXGI_ADAPTER_DESC1 {
+        unsafe {
+            let mut desc: DXGI_ADAPTER_DESC1 = zeroed();
+            self.0.GetDesc1(&mut desc);
+            desc
+        }
+    }
+
+    /// What `GetDesc1` tells us about the adapter. Only the enumeration that found it knows its `index`,
+    /// so that's left 0, and the caps stay `None` until `probe_caps` fills them in.
+    pub fn desc(&self) -> AdapterInfo {
+        let desc = self.desc1();
+
+        AdapterInfo {
+            index: 0,
+            description: os_helpers::string_from_wide(&desc.Description),
+            vendor_id: desc.VendorId,
+            device_id: desc.DeviceId,
+            subsys_id: desc.SubSysId,
+            revision: desc.Revision,
+            software: (desc.Flags & DXGI_ADAPTER_FLAG_SOFTWARE) != 0,
+            luid: luid_string(&desc.AdapterLuid),
+
+            dedicated_video_memory: desc.DedicatedVideoMemory,
+            dedicated_system_memory: desc.DedicatedSystemMemory,
+            shared_system_memory: desc.SharedSystemMemory,
+
+            feature_level: None,
+            raytracing_tier: None,
+        }
+    }
+
+    /// Create a device on the adapter to fill in `info`'s feature level and raytracing tier.
+    /// They stay `None` if the adapter can't create one.
+    pub fn probe_caps(&self, info: &mut AdapterInfo) {
+        let probe = caps::with_probe_device(&self.0, |device| {
+            (
+                caps::query_max_feature_level(device),
+                caps::query_raytracing_tier(device),
+            )
+        });
+
+        info.feature_level = probe
+            .and_then(|(level, _tier)| level)
+            .map(caps::feature_level_name);
+        info.raytracing_tier = probe.map(|(_level, tier)| tier.to_string());
+    }
+
+    /// The adapter's name, like "NVIDIA GeForce RTX 2080"
+    pub fn description(&self) -> String {
+        os_helpers::string_from_wide(&self.desc1().Description)
+    }
+
+    /// Whether this is a software adapter, like WARP or the Basic Render Driver
+    pub fn is_software(&self) -> bool {
+        (self.desc1().Flags & DXGI_ADAPTER_FLAG_SOFTWARE) != 0
+    }
+
+    /// The adapter's LUID, packed like `luid_to_i64` packs it
+    pub fn luid(&self) -> i64 {
+        luid_to_i64(&self.desc1().AdapterLuid)
+    }
+
+    fn dedicated_video_memory(&self) -> usize {
+        self.desc1().DedicatedVideoMemory
+    }
+
+    /// Live memory usage and budget. Needs IDXGIAdapter3, which older systems may not have.
+    pub fn local_memory_info(
+        &self,
+    ) -> Option<winapi::shared::dxgi1_4::DXGI_QUERY_VIDEO_MEMORY_INFO> {
+        use winapi::shared::dxgi1_4::*;
+
+        unsafe {
+            let (adapter3, hr) = self.0.cast::<IDXGIAdapter3>();
+            if FAILED(hr) {
+                return None;
+            }
+
+            let mut mem_info: DXGI_QUERY_VIDEO_MEMORY_INFO = zeroed();
+            let hr =
+                adapter3.QueryVideoMemoryInfo(0, DXGI_MEMORY_SEGMENT_GROUP_LOCAL, &mut mem_info);
+            adapter3.destroy();
+
+            if SUCCEEDED(hr) {
+                Some(mem_info)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+impl Drop for Adapter {
+    fn drop(&mut self) {
+        unsafe {
+            self.0.destroy();
+        }
+    }
+}
+
+pub fn luid_string(luid: &LUID) -> String {
+    format!("{:08x}:{:08x}", luid.HighPart, luid.LowPart)
+}
+
+/// A LUID as one number, high part first, so LUIDs compare with `==`
+pub fn luid_to_i64(luid: &LUID) -> i64 {
+    ((luid.HighPart as i64) << 32) | luid.LowPart as i64
+}
+
+/// Names for the PCI vendor IDs we're likely to see
+pub fn vendor_name(vendor_id: u32) -> Option<&'static str> {
+    match vendor_id {
+        0x10de => Some("NVIDIA"),
+        0x1002 => Some("AMD"),
+        0x8086 => Some("Intel"),
+        0x1414 => Some("Microsoft"),
+        _ => None,
+    }
+}
+
+/// Print `info` about `adapter`, probing its caps first
+pub fn display_adapter(adapter: &Adapter, info: &mut AdapterInfo, label: &str) {
+    adapter.probe_caps(info);
+
+    let vendor_id = match vendor_name(info.vendor_id) {
+        Some(name) => format!("{:>10} ({})", format!("0x{:x}", info.vendor_id), name),
+        None => format!("{:>10}", format!("0x{:x}", info.vendor_id)),
+    };
+    let device_id = format!("0x{:x}", info.device_id);
+    let subsys_id = format!("0x{:x}", info.subsys_id);
+    let revision = format!("0x{:x}", info.revision);
+    let feature_level = info.feature_level.as_deref().unwrap_or("n/a");
+    let raytracing_tier = info.raytracing_tier.as_deref().unwrap_or("n/a");
+
+    let video_mem = info.dedicated_video_memory / MiB;
+    let system_mem = info.dedicated_system_memory / MiB;
+    let shared_mem = info.shared_system_memory / MiB;
+
+    let (usage_mem, budget_mem) = match adapter.local_memory_info() {
+        Some(mem_info) => (
+            format!("{:>6} MiB", mem_info.CurrentUsage / MiB as u64),
+            format!("{:>6} MiB", mem_info.Budget / MiB as u64),
+        ),
+        None => (format!("{:>6}", "n/a"), format!("{:>6}", "n/a")),
+    };
+
+    println!("{}: {}", label, info.description);
+    println!("    VendorId:      {}", vendor_id);
+    println!("    DeviceId:      {:>10}", device_id);
+    println!("    SubSysId:      {:>10}", subsys_id);
+    println!("    Revision:      {:>10}", revision);
+    println!("    LUID:          {:>10}", info.luid);
+    println!("    Feature Level: {:>10}", feature_level);
+    println!("    Raytracing:    {:>10}", raytracing_tier);
+    println!("    Video Memory:  {:>6} MiB", video_mem);
+    println!("    System Memory: {:>6} MiB", system_mem);
+    println!("    Shared Memory: {:>6} MiB", shared_mem);
+    println!("    Memory Usage:  {}", usage_mem);
+    println!("    Memory Budget: {}", budget_mem);
+    display_outputs(adapter);
+    println!();
+}
+
+/// Every adapter the factory knows about, in DXGI's order
+pub fn adapters(factory: &d3d12::Factory4) -> impl Iterator<Item = Adapter> {
+    let factory = *factory;
+    (0..).map_while(move |i| {
+        let (adapter, hr) = factory.enumerate_adapters(i);
+        if SUCCEEDED(hr) {
+            return Some(Adapter(adapter));
+        }
+
+        // DXGI_ERROR_NOT_FOUND just means we're past the last one. Anything else is worth reporting.
+        if hr != DXGI_ERROR_NOT_FOUND {
+            let location = format!("{}:{}", file!(), line!());
+            os_helpers::log_hr(hr, &location, &format!("factory.enumerate_adapters({})", i));
+        }
+        None
+    })
+}
+
+/// Every display attached to `adapter`. The caller destroys each one.
+pub fn outputs(adapter: &Adapter) -> impl Iterator<Item = d3d12::WeakPtr<IDXGIOutput>> + '_ {
+    (0..).map_while(move |i| {
+        let mut output = d3d12::WeakPtr::<IDXGIOutput>::null();
+        let hr = unsafe { adapter.0.EnumOutputs(i, output.mut_void() as *mut *mut _) };
+        // DXGI_ERROR_NOT_FOUND - we're past the last one.
+        if SUCCEEDED(hr) {
+            Some(output)
+        } else {
+            None
+        }
+    })
+}
+
+pub fn display_outputs(adapter: &Adapter) {
+    let outputs: Vec<DXGI_OUTPUT_DESC> = outputs(adapter)
+        .map(|output| unsafe {
+            let mut desc: DXGI_OUTPUT_DESC = zeroed();
+            output.GetDesc(&mut desc);
+            output.destroy();
+            desc
+        })
+        .collect();
+
+    if outputs.is_empty() {
+        println!("    Outputs:       {:>10}", "none");
+        return;
+    }
+
+    println!("    Outputs:");
+    for desc in &outputs {
+        let name = os_helpers::string_from_wide(&desc.DeviceName);
+        let rect = desc.DesktopCoordinates;
+        println!(
+            "        {}: ({}, {}) - ({}, {})",
+            name, rect.left, rect.top, rect.right, rect.bottom
+        );
+    }
+}
+
+/// Find the first adapter whose description contains `name`, ignoring case
+pub fn find_adapter_by_name(factory: &d3d12::Factory4, name: &str) -> Result<Adapter, DxError> {
+    let needle = name.to_lowercase();
+    let mut descriptions = vec![];
+
+    for (i, adapter) in adapters(factory).enumerate() {
+        let description = adapter.description();
+        if description.to_lowercase().contains(&needle) {
+            status!("Using adapter {}: {}", i, description);
+            return Ok(adapter);
+        }
+
+        descriptions.push(description);
+    }
+
+    status!("No adapter matches \"{}\". Available adapters:", name);
+    for description in &descriptions {
+        status!("    {}", description);
+    }
+
+    Err(DxError {
+        hr: DXGI_ERROR_NOT_FOUND,
+        location: format!("{}:{}", file!(), line!()),
+        call: format!("find_adapter_by_name(factory, {:?})", name),
+    })
+}
+
+/// Find the hardware adapter with the most dedicated video memory
+pub fn find_adapter_with_most_memory(factory: &d3d12::Factory4) -> Result<Adapter, DxError> {
+    let mut best: Option<(usize, Adapter)> = None;
+
+    for (i, adapter) in adapters(factory).enumerate() {
+        let is_better = match &best {
+            Some((_, best_adapter)) => {
+                adapter.dedicated_video_memory() > best_adapter.dedicated_video_memory()
+            }
+            None => true,
+        };
+
+        if !adapter.is_software() && is_better {
+            best = Some((i, adapter));
+        }
+    }
+
+    match best {
+        Some((i, adapter)) => {
+            status!(
+                "Auto-selected adapter {}: {} ({} MiB of dedicated video memory, the most of any hardware adapter)",
+                i,
+                adapter.description(),
+                adapter.dedicated_video_memory() / MiB
+            );
+            Ok(adapter)
+        }
+        None => {
+            status!("No hardware adapters found. Try --warp instead.");
+            Err(DxError {
+                hr: DXGI_ERROR_NOT_FOUND,
+                location: format!("{}:{}", file!(), line!()),
+                call: "find_adapter_with_most_memory(factory)".to_string(),
+            })
+        }
+    }
+}
+
+/// Find the adapter with this LUID. LUIDs are unique per boot, so copy them from the adapter list
+pub fn find_adapter_by_luid(factory: &d3d12::Factory4, luid: LUID) -> Result<Adapter, DxError> {
+    let adapter = check_hr_err! {
+        unsafe {
+            let mut adapter = d3d12::Adapter1::null();
+            let hr = factory.EnumAdapterByLuid(luid, &IDXGIAdapter1::uuidof(), adapter.mut_void());
+
+            (adapter, hr)
+        }
+    };
+
+    let adapter = Adapter(adapter);
+
+    status!(
+        "Using adapter with LUID {}: {}",
+        luid_string(&luid),
+        adapter.description()
+    );
+    Ok(adapter)
+}
+
+/// Description of the adapter with `luid`, as the adapter list shows it
+pub fn description_by_luid(factory: &d3d12::Factory4, luid: LUID) -> Option<String> {
+    unsafe {
+        let mut adapter = d3d12::Adapter1::null();
+        let hr = factory.EnumAdapterByLuid(luid, &IDXGIAdapter1::uuidof(), adapter.mut_void());
+        if FAILED(hr) {
+            return None;
+        }
+
+        Some(Adapter(adapter).description())
+    }
+}
+
+/// Index of the adapter after the one with `luid`, wrapping around past the last.
+/// An adapter that isn't listed, like WARP on some systems, is followed by adapter 0.
+pub fn next_adapter_index(factory: &d3d12::Factory4, luid: LUID) -> Option<u32> {
+    let luids: Vec<i64> = adapters(factory).map(|adapter| adapter.luid()).collect();
+    if luids.is_empty() {
+        return None;
+    }
+
+    let luid = luid_to_i64(&luid);
+    let current = luids.iter().position(|&other| other == luid);
+    let next = current.map_or(0, |i| (i + 1) % luids.len());
+    Some(next as u32)
+}
+
+/// Create a device on `adapter` at `feature_level`.
+/// Unlike `d3d12::Device::create`, this accepts levels the d3d12 crate doesn't know about, like 12_2.
+pub fn create_device<I: Interface>(
+    adapter: &d3d12::WeakPtr<I>,
+    feature_level: D3D_FEATURE_LEVEL,
+) -> d3d12::D3DResult<d3d12::Device> {
+    use winapi::um::d3d12::{D3D12CreateDevice, ID3D12Device};
+
+    let mut device = d3d12::Device::null();
+    let hr = unsafe {
+        D3D12CreateDevice(
+            adapter.as_unknown() as *const _ as *mut _,
+            feature_level,
+            &ID3D12Device::uuidof(),
+            device.mut_void(),
+        )
+    };
+
+    (device, hr)
+}
+
+/// The WARP adapter: Microsoft's software rasterizer, which is always there
+pub fn warp_adapter(factory: &d3d12::Factory4) -> Result<Adapter, DxError> {
+    let adapter = check_hr_err! {
+        unsafe {
+            let mut warp_adapter = d3d12::Adapter1::null();
+            let hr = factory.EnumWarpAdapter(&IDXGIAdapter1::uuidof(), warp_adapter.mut_void());
+
+            (warp_adapter, hr)
+        }
+    };
+
+    Ok(Adapter(adapter))
+}
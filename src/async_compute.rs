@@ -0,0 +1,223 @@
+//! A compute queue beside the direct one, for `--async-compute`
+//!
+//! Each frame, a trivial compute shader fills the raytracing output on the compute queue before
+//! the direct queue traces into it. The rays overwrite every pixel, so the fill itself rarely shows.
+//! What it exercises is the handoff: the compute queue waits for the direct queue to finish with
+//! the output, signals its own fence, and the direct queue waits on that before running the frame.
+
+use crate::check_hr_err;
+use crate::check_hr_only;
+use crate::commands;
+use crate::os_helpers::{self, DxError};
+use crate::raytrace::Raytracer;
+use crate::root_sig::RootSignatureBuilder;
+use crate::shaders;
+use crate::sync::QueueFence;
+
+use d3d12::{Binding, DescriptorRange, DescriptorRangeType};
+
+use winapi::shared::winerror::*;
+use winapi::um::d3d12::*;
+use winapi::Interface;
+
+use std::mem::zeroed;
+use std::path::Path;
+
+const FILL_HLSL: &str = r#"
+RWTexture2D<float4> Output : register(u0);
+
+cbuffer FillConstants : register(b0) {
+    float4 FillColor;
+};
+
+[numthreads(8, 8, 1)]
+void CSMain(uint2 id : SV_DispatchThreadID) {
+    uint width, height;
+    Output.GetDimensions(width, height);
+    if (id.x < width && id.y < height) {
+        Output[id] = FillColor;
+    }
+}
+"#;
+
+/// Matches `numthreads` in FILL_HLSL
+const GROUP_SIZE: u32 = 8;
+
+const ROOT_PARAM_OUTPUT: u32 = 0;
+const ROOT_PARAM_FILL_COLOR: u32 = 1;
+
+fn create_root_signature(device: &d3d12::Device) -> Result<d3d12::RootSignature, DxError> {
+    // Same table layout as the raytracing root signature, so the output's view can be shared
+    RootSignatureBuilder::new()
+        .add_descriptor_table(vec![DescriptorRange::new(
+            DescriptorRangeType::UAV,
+            1,
+            Binding {
+                register: 0,
+                space: 0,
+            },
+            0,
+        )])
+        .add_constants(0, 4)
+        .build(device, "Async Compute Root Signature")
+}
+
+fn create_pipeline(
+    device: &d3d12::Device,
+    shader_dir: Option<&Path>,
+    root_sig: d3d12::RootSignature,
+) -> Result<d3d12::PipelineState, DxError> {
+    let target = shaders::default_target("cs");
+    let blob = shaders::load_or_compile(
+        shader_dir,
+        "async_fill_cs",
+        "async_fill",
+        FILL_HLSL,
+        "CSMain",
+        &target,
+    )
+    .map_err(|err| {
+        log::error!("{}", err);
+        DxError {
+            hr: E_FAIL,
+            location: format!("{}:{}", file!(), line!()),
+            call: format!("shaders::load_or_compile(\"async_fill_cs\", {:?})", target),
+        }
+    })?;
+
+    let desc = D3D12_COMPUTE_PIPELINE_STATE_DESC {
+        pRootSignature: root_sig.as_mut_ptr(),
+        CS: D3D12_SHADER_BYTECODE {
+            pShaderBytecode: blob.as_ptr() as *const _,
+            BytecodeLength: blob.len(),
+        },
+        NodeMask: 0,
+        CachedPSO: unsafe { zeroed() },
+        Flags: D3D12_PIPELINE_STATE_FLAG_NONE,
+    };
+
+    let pso = check_hr_err! {
+        unsafe {
+            let mut pso = d3d12::PipelineState::null();
+            let hr = device.CreateComputePipelineState(
+                &desc,
+                &ID3D12PipelineState::uuidof(),
+                pso.mut_void(),
+            );
+
+            (pso, hr)
+        }
+    };
+    os_helpers::set_name(&pso, "Async Fill PSO");
+
+    Ok(pso)
+}
+
+pub struct AsyncCompute {
+    pub queue: d3d12::CommandQueue,
+    root_sig: d3d12::RootSignature,
+    pso: d3d12::PipelineState,
+    /// One per frame in flight, indexed like the direct queue's frame contexts
+    cmd_lists: Vec<(d3d12::CommandAllocator, d3d12::GraphicsCommandList)>,
+    /// Signaled on the compute queue, and waited on by the direct queue
+    fence: QueueFence,
+    fill_color: [f32; 4],
+}
+
+impl AsyncCompute {
+    /// Create the compute queue and the fill pipeline, with commands for `frame_count` frames in flight
+    pub fn new(
+        device: &d3d12::Device,
+        shader_dir: Option<&Path>,
+        frame_count: u32,
+        fill_color: [f32; 4],
+    ) -> Result<Self, DxError> {
+        let queue = check_hr_err!(device.create_command_queue(
+            d3d12::CmdListType::Compute,
+            d3d12::Priority::Normal,
+            d3d12::CommandQueueFlags::empty(),
+            0,
+        ));
+        os_helpers::set_name(&queue, "Compute Queue");
+
+        let root_sig = create_root_signature(device)?;
+        let pso = create_pipeline(device, shader_dir, root_sig)?;
+        let cmd_lists = (0..frame_count)
+            .map(|i| {
+                commands::create_compute_command_list(
+                    device,
+                    &format!("Frame {} Compute Commands", i),
+                )
+            })
+            .collect::<Result<Vec<_>, DxError>>()?;
+        let fence = QueueFence::new(device)?;
+
+        Ok(AsyncCompute {
+            queue,
+            root_sig,
+            pso,
+            cmd_lists,
+            fence,
+            fill_color,
+        })
+    }
+
+    /// Fill `raytracer`'s output on the compute queue, and make `direct_queue` wait for it.
+    /// Call this between `FrameContext::begin` and `submit` for `frame_index`: the direct queue's
+    /// wait means that frame's fence also covers this work, so its commands are free to reset.
+    pub fn fill_output(
+        &mut self,
+        frame_index: u32,
+        raytracer: &Raytracer,
+        direct_queue: &d3d12::CommandQueue,
+        direct_fence: &QueueFence,
+    ) {
+        let (allocator, cmd_list) = self.cmd_lists[frame_index as usize];
+        allocator.reset();
+        check_hr_only!(cmd_list.reset(allocator, self.pso));
+
+        let (width, height) = raytracer.output_size();
+        cmd_list.set_descriptor_heaps(&[raytracer.uav_heap()]);
+        cmd_list.set_compute_root_signature(self.root_sig);
+        cmd_list.set_compute_root_descriptor_table(
+            ROOT_PARAM_OUTPUT,
+            raytracer.uav_heap().start_gpu_descriptor(),
+        );
+        unsafe {
+            cmd_list.SetComputeRoot32BitConstants(
+                ROOT_PARAM_FILL_COLOR,
+                self.fill_color.len() as u32,
+                self.fill_color.as_ptr() as *const _,
+                0,
+            );
+        }
+        cmd_list.dispatch([
+            (width + GROUP_SIZE - 1) / GROUP_SIZE,
+            (height + GROUP_SIZE - 1) / GROUP_SIZE,
+            1,
+        ]);
+        check_hr_only!(cmd_list.close());
+
+        // The last frame's trace and copy read the output, so the fill waits for them
+        direct_fence.gpu_wait(&self.queue);
+        self.queue.execute_command_lists(&[cmd_list.as_list()]);
+        let filled = self.fence.signal(&self.queue);
+        self.fence.gpu_wait_for_value(direct_queue, filled);
+    }
+
+    /// Release the queue and everything on it. Nothing in flight can still be using them.
+    pub fn destroy(&mut self) {
+        for (allocator, cmd_list) in &mut self.cmd_lists {
+            unsafe {
+                cmd_list.destroy();
+                allocator.destroy();
+            }
+        }
+        self.fence.destroy();
+        unsafe {
+            self.pso.destroy();
+            self.root_sig.destroy();
+            self.queue.destroy();
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Buffer creation, for upload (CPU-writable) and default (GPU-local) heaps
+
+use crate::check_hr_err;
+use crate::commands;
+use crate::os_helpers::DxError;
+
+use winapi::shared::dxgiformat::*;
+use winapi::shared::dxgitype::*;
+use winapi::shared::winerror::*;
+use winapi::um::d3d12::*;
+use winapi::Interface;
+
+use std::ptr;
+
+/// `value` rounded up to a multiple of `alignment`
+pub fn align_up(value: u64, alignment: u64) -> u64 {
+    value.div_ceil(alignment) * alignment
+}
+
+/// Create a committed buffer of `size` bytes
+pub fn create_buffer(
+    device: &d3d12::Device,
+    size: u64,
+    heap_type: D3D12_HEAP_TYPE,
+    flags: D3D12_RESOURCE_FLAGS,
+    initial_state: D3D12_RESOURCE_STATES,
+) -> Result<d3d12::Resource, DxError> {
+    let heap_props = D3D12_HEAP_PROPERTIES {
+        Type: heap_type,
+        CPUPageProperty: D3D12_CPU_PAGE_PROPERTY_UNKNOWN,
+        MemoryPoolPreference: D3D12_MEMORY_POOL_UNKNOWN,
+        CreationNodeMask: 0,
+        VisibleNodeMask: 0,
+    };
+    let resource_desc = D3D12_RESOURCE_DESC {
+        Dimension: D3D12_RESOURCE_DIMENSION_BUFFER,
+        Alignment: 0,
+        Width: size,
+        Height: 1,
+        DepthOrArraySize: 1,
+        MipLevels: 1,
+        Format: DXGI_FORMAT_UNKNOWN,
+        SampleDesc: DXGI_SAMPLE_DESC {
+            Count: 1,
+            Quality: 0,
+        },
+        Layout: D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+        Flags: flags,
+    };
+
+    let buffer = check_hr_err! {
+        unsafe {
+            let mut buffer = d3d12::Resource::null();
+            let hr = device.CreateCommittedResource(
+                &heap_props,
+                D3D12_HEAP_FLAG_NONE,
+                &resource_desc,
+                initial_state,
+                ptr::null(),
+                &ID3D12Resource::uuidof(),
+                buffer.mut_void(),
+            );
+
+            (buffer, hr)
+        }
+    };
+
+    Ok(buffer)
+}
+
+/// A buffer the CPU can write, and the GPU can read directly or copy from
+pub fn create_upload_buffer(device: &d3d12::Device, size: u64) -> Result<d3d12::Resource, DxError> {
+    // Upload heap resources must stay in GENERIC_READ
+    create_buffer(
+        device,
+        size,
+        D3D12_HEAP_TYPE_UPLOAD,
+        D3D12_RESOURCE_FLAG_NONE,
+        D3D12_RESOURCE_STATE_GENERIC_READ,
+    )
+}
+
+/// A buffer in GPU-local memory. Fill it by copying from an upload buffer.
+pub fn create_default_buffer(
+    device: &d3d12::Device,
+    size: u64,
+) -> Result<d3d12::Resource, DxError> {
+    create_buffer(
+        device,
+        size,
+        D3D12_HEAP_TYPE_DEFAULT,
+        D3D12_RESOURCE_FLAG_NONE,
+        D3D12_RESOURCE_STATE_COMMON,
+    )
+}
+
+/// Copy `bytes` to the start of an upload buffer. Fails if they don't fit.
+pub fn upload_bytes(resource: &d3d12::Resource, bytes: &[u8]) -> Result<(), DxError> {
+    let capacity = unsafe { resource.GetDesc() }.Width;
+    if bytes.len() as u64 > capacity {
+        return Err(DxError {
+            hr: E_INVALIDARG,
+            location: format!("{}:{}", file!(), line!()),
+            call: format!(
+                "upload_bytes({} bytes into a {} byte buffer)",
+                bytes.len(),
+                capacity
+            ),
+        });
+    }
+
+    // We never read back, so tell Map the read range is empty
+    let mapped = check_hr_err!(resource.map(0, Some(0..0)));
+    unsafe {
+        ptr::copy_nonoverlapping(bytes.as_ptr(), mapped as *mut u8, bytes.len());
+    }
+    resource.unmap(0, None);
+
+    Ok(())
+}
+
+/// A default-heap buffer holding `bytes`, left in `final_state`. The copy is recorded into `cmd_list`,
+/// and the upload buffer returned alongside has to stay alive until it finishes on the GPU.
+pub fn create_default_buffer_with_data(
+    device: &d3d12::Device,
+    cmd_list: &d3d12::GraphicsCommandList,
+    bytes: &[u8],
+    final_state: D3D12_RESOURCE_STATES,
+) -> Result<(d3d12::Resource, d3d12::Resource), DxError> {
+    let size = bytes.len() as u64;
+    let upload = create_upload_buffer(device, size)?;
+    if let Err(err) = upload_bytes(&upload, bytes) {
+        unsafe {
+            upload.destroy();
+        }
+        return Err(err);
+    }
+    let buffer = match create_default_buffer(device, size) {
+        Ok(buffer) => buffer,
+        Err(err) => {
+            unsafe {
+                upload.destroy();
+            }
+            return Err(err);
+        }
+    };
+
+    commands::transition(
+        cmd_list,
+        buffer,
+        D3D12_RESOURCE_STATE_COMMON,
+        D3D12_RESOURCE_STATE_COPY_DEST,
+    );
+    unsafe {
+        cmd_list.CopyBufferRegion(buffer.as_mut_ptr(), 0, upload.as_mut_ptr(), 0, size);
+    }
+    commands::transition(
+        cmd_list,
+        buffer,
+        D3D12_RESOURCE_STATE_COPY_DEST,
+        final_state,
+    );
+
+    Ok((buffer, upload))
+}
@@ -0,0 +1,178 @@
+//! An orbit camera around the origin, and just enough matrix math to feed it to shaders
+//!
+//! Matrices are row-major and multiply column vectors, so shaders declare them `row_major`
+//! and transform with `mul(m, v)`. The view space is left-handed, like the rest of D3D.
+
+use std::f32::consts::FRAC_PI_2;
+
+pub type Mat4 = [[f32; 4]; 4];
+
+pub const IDENTITY: Mat4 = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = dot(v, v).sqrt();
+    [v[0] / length, v[1] / length, v[2] / length]
+}
+
+pub fn mul(a: &Mat4, b: &Mat4) -> Mat4 {
+    let mut out = [[0.0; 4]; 4];
+    for (row, out_row) in out.iter_mut().enumerate() {
+        for (col, out) in out_row.iter_mut().enumerate() {
+            *out = (0..4).map(|i| a[row][i] * b[i][col]).sum();
+        }
+    }
+    out
+}
+
+/// Gauss-Jordan elimination with partial pivoting. Returns `None` for singular matrices.
+pub fn inverse(m: &Mat4) -> Option<Mat4> {
+    let mut m = *m;
+    let mut inv = IDENTITY;
+
+    for col in 0..4 {
+        let pivot = (col..4).max_by(|&a, &b| m[a][col].abs().total_cmp(&m[b][col].abs()))?;
+        if m[pivot][col].abs() < f32::EPSILON {
+            return None;
+        }
+        m.swap(col, pivot);
+        inv.swap(col, pivot);
+
+        let scale = 1.0 / m[col][col];
+        for i in 0..4 {
+            m[col][i] *= scale;
+            inv[col][i] *= scale;
+        }
+
+        for row in 0..4 {
+            if row == col {
+                continue;
+            }
+            let factor = m[row][col];
+            for i in 0..4 {
+                m[row][i] -= factor * m[col][i];
+                inv[row][i] -= factor * inv[col][i];
+            }
+        }
+    }
+
+    Some(inv)
+}
+
+/// View matrix for a camera at `eye` looking at `target`
+pub fn look_at(eye: [f32; 3], target: [f32; 3], up: [f32; 3]) -> Mat4 {
+    let forward = normalize(sub(target, eye));
+    let right = normalize(cross(up, forward));
+    let up = cross(forward, right);
+
+    [
+        [right[0], right[1], right[2], -dot(right, eye)],
+        [up[0], up[1], up[2], -dot(up, eye)],
+        [forward[0], forward[1], forward[2], -dot(forward, eye)],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+/// Perspective projection onto D3D's 0..1 depth range
+pub fn perspective(fov_y: f32, aspect: f32, near: f32, far: f32) -> Mat4 {
+    let y_scale = 1.0 / (fov_y / 2.0).tan();
+    let x_scale = y_scale / aspect;
+    let z_scale = far / (far - near);
+
+    [
+        [x_scale, 0.0, 0.0, 0.0],
+        [0.0, y_scale, 0.0, 0.0],
+        [0.0, 0.0, z_scale, -near * z_scale],
+        [0.0, 0.0, 1.0, 0.0],
+    ]
+}
+
+const FOV_Y: f32 = std::f32::consts::FRAC_PI_3;
+const NEAR: f32 = 0.01;
+const FAR: f32 = 100.0;
+
+/// With FOV_Y, this puts the triangle's corners at ±0.5 halfway to the edges of the view
+const DEFAULT_DISTANCE: f32 = 1.732;
+const MIN_DISTANCE: f32 = 0.1;
+const MAX_DISTANCE: f32 = 50.0;
+
+/// Radians per pixel of mouse drag
+const DRAG_SPEED: f32 = 0.01;
+/// Scale on the distance per line of scroll
+const ZOOM_SPEED: f32 = 0.9;
+
+/// Circles the origin: dragging turns it, scrolling moves it in and out
+#[derive(Copy, Clone, Debug)]
+pub struct OrbitCamera {
+    /// Radians around +Y. At zero, the camera is on -Z looking down +Z.
+    yaw: f32,
+    /// Radians above the XZ plane
+    pitch: f32,
+    distance: f32,
+}
+
+impl Default for OrbitCamera {
+    fn default() -> Self {
+        OrbitCamera {
+            yaw: 0.0,
+            pitch: 0.0,
+            distance: DEFAULT_DISTANCE,
+        }
+    }
+}
+
+impl OrbitCamera {
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Turn by a mouse drag of `dx`, `dy` pixels
+    pub fn drag(&mut self, dx: f32, dy: f32) {
+        self.yaw -= dx * DRAG_SPEED;
+        // Stop short of straight up or down, where look_at's up vector falls apart
+        let limit = FRAC_PI_2 - 0.01;
+        self.pitch = (self.pitch + dy * DRAG_SPEED).clamp(-limit, limit);
+    }
+
+    /// Move in by `lines` of scroll, or out when it's negative
+    pub fn zoom(&mut self, lines: f32) {
+        self.distance = (self.distance * ZOOM_SPEED.powf(lines)).clamp(MIN_DISTANCE, MAX_DISTANCE);
+    }
+
+    pub fn eye(&self) -> [f32; 3] {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        [
+            self.distance * cos_pitch * sin_yaw,
+            self.distance * sin_pitch,
+            -self.distance * cos_pitch * cos_yaw,
+        ]
+    }
+
+    /// Projection times view, for a `width` x `height` target
+    pub fn view_proj(&self, width: u32, height: u32) -> Mat4 {
+        let aspect = width as f32 / height.max(1) as f32;
+        let view = look_at(self.eye(), [0.0; 3], [0.0, 1.0, 0.0]);
+        mul(&perspective(FOV_Y, aspect, NEAR, FAR), &view)
+    }
+}
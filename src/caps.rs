@@ -0,0 +1,474 @@
+//! Device capability queries, via `ID3D12Device::CheckFeatureSupport`
+
+use crate::d3d12_ext::*;
+use crate::status;
+
+use winapi::shared::dxgiformat::*;
+use winapi::shared::winerror::*;
+use winapi::um::d3d12::*;
+use winapi::um::d3dcommon::*;
+use winapi::Interface;
+
+use std::fmt;
+use std::mem::{size_of, zeroed};
+
+/// Fill in `data` for `feature`. `T` must be the matching `D3D12_FEATURE_DATA_*` struct.
+fn check_feature_support<T>(
+    device: &d3d12::Device,
+    feature: D3D12_FEATURE,
+    data: &mut T,
+) -> d3d12::HRESULT {
+    unsafe { device.CheckFeatureSupport(feature, data as *mut T as *mut _, size_of::<T>() as u32) }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RaytracingTier {
+    NotSupported,
+    Tier1_0,
+    Tier1_1,
+}
+
+impl fmt::Display for RaytracingTier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            RaytracingTier::NotSupported => "None",
+            RaytracingTier::Tier1_0 => "Tier 1.0",
+            RaytracingTier::Tier1_1 => "Tier 1.1",
+        };
+        f.pad(text)
+    }
+}
+
+/// What level of DXR the device supports, if any
+pub fn query_raytracing_tier(device: &d3d12::Device) -> RaytracingTier {
+    let mut options5: D3D12_FEATURE_DATA_D3D12_OPTIONS5 = unsafe { zeroed() };
+    let hr = check_feature_support(device, D3D12_FEATURE_D3D12_OPTIONS5, &mut options5);
+
+    // Runtimes that predate DXR don't know about OPTIONS5 at all
+    if FAILED(hr) {
+        return RaytracingTier::NotSupported;
+    }
+
+    match options5.RaytracingTier {
+        D3D12_RAYTRACING_TIER_NOT_SUPPORTED => RaytracingTier::NotSupported,
+        D3D12_RAYTRACING_TIER_1_0 => RaytracingTier::Tier1_0,
+        // Anything newer is a superset of 1.1
+        _ => RaytracingTier::Tier1_1,
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ResourceBindingTier {
+    Tier1,
+    Tier2,
+    Tier3,
+}
+
+impl fmt::Display for ResourceBindingTier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            ResourceBindingTier::Tier1 => "Tier 1",
+            ResourceBindingTier::Tier2 => "Tier 2",
+            ResourceBindingTier::Tier3 => "Tier 3",
+        };
+        f.pad(text)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ConservativeRasterTier {
+    NotSupported,
+    Tier1,
+    Tier2,
+    Tier3,
+}
+
+impl fmt::Display for ConservativeRasterTier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            ConservativeRasterTier::NotSupported => "None",
+            ConservativeRasterTier::Tier1 => "Tier 1",
+            ConservativeRasterTier::Tier2 => "Tier 2",
+            ConservativeRasterTier::Tier3 => "Tier 3",
+        };
+        f.pad(text)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MeshShaderTier {
+    NotSupported,
+    Tier1,
+}
+
+impl fmt::Display for MeshShaderTier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            MeshShaderTier::NotSupported => "None",
+            MeshShaderTier::Tier1 => "Tier 1",
+        };
+        f.pad(text)
+    }
+}
+
+/// Whether mesh and amplification shaders are available
+pub fn query_mesh_shader_tier(device: &d3d12::Device) -> MeshShaderTier {
+    let mut options7: D3D12_FEATURE_DATA_D3D12_OPTIONS7 = unsafe { zeroed() };
+    let hr = check_feature_support(device, D3D12_FEATURE_D3D12_OPTIONS7, &mut options7);
+
+    // Like OPTIONS5, older runtimes don't know OPTIONS7 exists
+    if FAILED(hr) || options7.MeshShaderTier == D3D12_MESH_SHADER_TIER_NOT_SUPPORTED {
+        MeshShaderTier::NotSupported
+    } else {
+        MeshShaderTier::Tier1
+    }
+}
+
+/// Wave intrinsics support, and how many lanes a wave can have
+#[derive(Copy, Clone, Debug)]
+pub struct WaveOps {
+    pub min_lanes: u32,
+    pub max_lanes: u32,
+}
+
+impl fmt::Display for WaveOps {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(&format!("{}-{} lanes", self.min_lanes, self.max_lanes))
+    }
+}
+
+/// Wave intrinsic support, or `None` when the device doesn't have them
+pub fn query_wave_ops(device: &d3d12::Device) -> Option<WaveOps> {
+    let mut options1: D3D12_FEATURE_DATA_D3D12_OPTIONS1 = unsafe { zeroed() };
+    let hr = check_feature_support(device, D3D12_FEATURE_D3D12_OPTIONS1, &mut options1);
+
+    if SUCCEEDED(hr) && options1.WaveOps != 0 {
+        Some(WaveOps {
+            min_lanes: options1.WaveLaneCountMin,
+            max_lanes: options1.WaveLaneCountMax,
+        })
+    } else {
+        None
+    }
+}
+
+/// Format a shader model like DXC profiles spell it, e.g. "6_0"
+pub fn shader_model_name(model: D3D_SHADER_MODEL) -> String {
+    format!("{}_{}", model >> 4, model & 0xf)
+}
+
+/// Every shader model we know the name of, newest first
+const SHADER_MODELS: &[D3D_SHADER_MODEL] = &[
+    D3D_SHADER_MODEL_6_7,
+    D3D_SHADER_MODEL_6_6,
+    D3D_SHADER_MODEL_6_5,
+    D3D_SHADER_MODEL_6_4,
+    D3D_SHADER_MODEL_6_3,
+    D3D_SHADER_MODEL_6_2,
+    D3D_SHADER_MODEL_6_1,
+    D3D_SHADER_MODEL_6_0,
+    D3D_SHADER_MODEL_5_1,
+];
+
+/// The highest shader model the device can run
+pub fn query_shader_model(device: &d3d12::Device) -> Option<D3D_SHADER_MODEL> {
+    // The device answers with the highest model up to the one we ask for,
+    // but asking for one the runtime has never heard of fails outright. So walk down until it answers.
+    SHADER_MODELS.iter().find_map(|&requested| {
+        let mut shader_model = D3D12_FEATURE_DATA_SHADER_MODEL {
+            HighestShaderModel: requested,
+        };
+        let hr = check_feature_support(device, D3D12_FEATURE_SHADER_MODEL, &mut shader_model);
+
+        if SUCCEEDED(hr) {
+            Some(shader_model.HighestShaderModel)
+        } else {
+            None
+        }
+    })
+}
+
+/// Capabilities of a device, gathered in one place so pipeline code can check what it needs
+#[derive(Copy, Clone, Debug)]
+pub struct DeviceCaps {
+    pub raytracing_tier: RaytracingTier,
+    pub resource_binding_tier: ResourceBindingTier,
+    pub conservative_raster_tier: ConservativeRasterTier,
+    pub feature_level: Option<D3D_FEATURE_LEVEL>,
+    pub shader_model: Option<D3D_SHADER_MODEL>,
+    pub mesh_shader_tier: MeshShaderTier,
+    pub wave_ops: Option<WaveOps>,
+}
+
+/// One row per capability, lined up for the startup log
+impl fmt::Display for DeviceCaps {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let or_unknown = |name: Option<String>| name.unwrap_or_else(|| "Unknown".to_string());
+
+        writeln!(f, "Raytracing Tier:          {}", self.raytracing_tier)?;
+        writeln!(
+            f,
+            "Resource Binding Tier:    {}",
+            self.resource_binding_tier
+        )?;
+        writeln!(
+            f,
+            "Conservative Raster Tier: {}",
+            self.conservative_raster_tier
+        )?;
+        writeln!(
+            f,
+            "Feature Level:            {}",
+            or_unknown(self.feature_level.map(feature_level_name))
+        )?;
+        writeln!(
+            f,
+            "Shader Model:             {}",
+            or_unknown(self.shader_model.map(shader_model_name))
+        )?;
+        writeln!(f, "Mesh Shader Tier:         {}", self.mesh_shader_tier)?;
+        match &self.wave_ops {
+            Some(wave_ops) => writeln!(f, "Wave Ops:                 {}", wave_ops),
+            None => writeln!(f, "Wave Ops:                 None"),
+        }
+    }
+}
+
+impl DeviceCaps {
+    pub fn query(device: &d3d12::Device) -> Self {
+        let mut options: D3D12_FEATURE_DATA_D3D12_OPTIONS = unsafe { zeroed() };
+        let hr = check_feature_support(device, D3D12_FEATURE_D3D12_OPTIONS, &mut options);
+
+        // Every D3D12 device answers OPTIONS, so a failure leaves us at the minimums
+        let (resource_binding_tier, conservative_raster_tier) = if SUCCEEDED(hr) {
+            let binding = match options.ResourceBindingTier {
+                D3D12_RESOURCE_BINDING_TIER_1 => ResourceBindingTier::Tier1,
+                D3D12_RESOURCE_BINDING_TIER_2 => ResourceBindingTier::Tier2,
+                _ => ResourceBindingTier::Tier3,
+            };
+            let conservative = match options.ConservativeRasterizationTier {
+                D3D12_CONSERVATIVE_RASTERIZATION_TIER_NOT_SUPPORTED => {
+                    ConservativeRasterTier::NotSupported
+                }
+                D3D12_CONSERVATIVE_RASTERIZATION_TIER_1 => ConservativeRasterTier::Tier1,
+                D3D12_CONSERVATIVE_RASTERIZATION_TIER_2 => ConservativeRasterTier::Tier2,
+                _ => ConservativeRasterTier::Tier3,
+            };
+            (binding, conservative)
+        } else {
+            (
+                ResourceBindingTier::Tier1,
+                ConservativeRasterTier::NotSupported,
+            )
+        };
+
+        DeviceCaps {
+            raytracing_tier: query_raytracing_tier(device),
+            resource_binding_tier,
+            conservative_raster_tier,
+            feature_level: query_max_feature_level(device),
+            shader_model: query_shader_model(device),
+            mesh_shader_tier: query_mesh_shader_tier(device),
+            wave_ops: query_wave_ops(device),
+        }
+    }
+}
+
+/// Every feature level that `parse_feature_level` understands
+pub const ALL_FEATURE_LEVELS: &[D3D_FEATURE_LEVEL] = &[
+    D3D_FEATURE_LEVEL_9_1,
+    D3D_FEATURE_LEVEL_9_2,
+    D3D_FEATURE_LEVEL_9_3,
+    D3D_FEATURE_LEVEL_10_0,
+    D3D_FEATURE_LEVEL_10_1,
+    D3D_FEATURE_LEVEL_11_0,
+    D3D_FEATURE_LEVEL_11_1,
+    D3D_FEATURE_LEVEL_12_0,
+    D3D_FEATURE_LEVEL_12_1,
+    D3D_FEATURE_LEVEL_12_2,
+];
+
+/// Format a feature level the same way `--feature-level` spells it, e.g. "12_1"
+pub fn feature_level_name(level: D3D_FEATURE_LEVEL) -> String {
+    format!("{}_{}", level >> 12, (level >> 8) & 0xf)
+}
+
+/// The highest feature level the device supports
+pub fn query_max_feature_level(device: &d3d12::Device) -> Option<D3D_FEATURE_LEVEL> {
+    let query = |requested: &[D3D_FEATURE_LEVEL]| {
+        let mut levels = D3D12_FEATURE_DATA_FEATURE_LEVELS {
+            NumFeatureLevels: requested.len() as u32,
+            pFeatureLevelsRequested: requested.as_ptr(),
+            MaxSupportedFeatureLevel: 0,
+        };
+        let hr = check_feature_support(device, D3D12_FEATURE_FEATURE_LEVELS, &mut levels);
+
+        if SUCCEEDED(hr) {
+            Some(levels.MaxSupportedFeatureLevel)
+        } else {
+            None
+        }
+    };
+
+    // Runtimes older than Windows 10 2004 reject the whole query if it mentions 12_2
+    query(ALL_FEATURE_LEVELS).or_else(|| {
+        let before_12_2 = ALL_FEATURE_LEVELS
+            .iter()
+            .position(|&level| level == D3D_FEATURE_LEVEL_12_2)
+            .unwrap();
+        query(&ALL_FEATURE_LEVELS[..before_12_2])
+    })
+}
+
+/// Run `f` against a temporary device on `adapter`, for capability queries.
+/// The device is created at the D3D12 minimum level, and released afterwards.
+pub fn with_probe_device<I: Interface, T>(
+    adapter: &d3d12::WeakPtr<I>,
+    f: impl FnOnce(&d3d12::Device) -> T,
+) -> Option<T> {
+    let (device, hr) = d3d12::Device::create(*adapter, d3d12::FeatureLevel::L11_0);
+    if FAILED(hr) {
+        return None;
+    }
+
+    let result = f(&device);
+    unsafe {
+        device.destroy();
+    }
+
+    Some(result)
+}
+
+/// Whether DXGI can present with tearing, for variable refresh rate displays
+pub fn query_tearing_support(factory: &d3d12::Factory4) -> bool {
+    use winapi::shared::dxgi1_5::*;
+    use winapi::shared::minwindef::BOOL;
+
+    let factory5 = match unsafe { factory.cast::<IDXGIFactory5>() } {
+        (factory5, hr) if SUCCEEDED(hr) => factory5,
+        // Only DXGI 1.5 and up (Windows 10) can tear
+        _ => return false,
+    };
+
+    let mut allow_tearing: BOOL = 0;
+    let hr = unsafe {
+        factory5.CheckFeatureSupport(
+            DXGI_FEATURE_PRESENT_ALLOW_TEARING,
+            &mut allow_tearing as *mut BOOL as *mut _,
+            size_of::<BOOL>() as u32,
+        )
+    };
+    unsafe {
+        factory5.destroy();
+    }
+
+    SUCCEEDED(hr) && allow_tearing != 0
+}
+
+/// How many quality levels `format` has at `sample_count` samples. Zero means it can't be multisampled that way.
+pub fn query_msaa_quality_levels(
+    device: &d3d12::Device,
+    format: winapi::shared::dxgiformat::DXGI_FORMAT,
+    sample_count: u32,
+) -> u32 {
+    let mut levels = D3D12_FEATURE_DATA_MULTISAMPLE_QUALITY_LEVELS {
+        Format: format,
+        SampleCount: sample_count,
+        Flags: D3D12_MULTISAMPLE_QUALITY_LEVELS_FLAG_NONE,
+        NumQualityLevels: 0,
+    };
+    let hr = check_feature_support(
+        device,
+        D3D12_FEATURE_MULTISAMPLE_QUALITY_LEVELS,
+        &mut levels,
+    );
+
+    if SUCCEEDED(hr) {
+        levels.NumQualityLevels
+    } else {
+        0
+    }
+}
+
+/// Formats `--list-formats` reports on, with the `--swapchain-format` name for the ones that have one
+const LISTED_FORMATS: &[(&str, DXGI_FORMAT)] = &[
+    ("R8G8B8A8_UNORM (rgba8)", DXGI_FORMAT_R8G8B8A8_UNORM),
+    ("R8G8B8A8_UNORM_SRGB", DXGI_FORMAT_R8G8B8A8_UNORM_SRGB),
+    ("B8G8R8A8_UNORM (bgra8)", DXGI_FORMAT_B8G8R8A8_UNORM),
+    ("B8G8R8A8_UNORM_SRGB", DXGI_FORMAT_B8G8R8A8_UNORM_SRGB),
+    ("R10G10B10A2_UNORM (rgb10a2)", DXGI_FORMAT_R10G10B10A2_UNORM),
+    ("R11G11B10_FLOAT", DXGI_FORMAT_R11G11B10_FLOAT),
+    ("R16G16B16A16_UNORM", DXGI_FORMAT_R16G16B16A16_UNORM),
+    (
+        "R16G16B16A16_FLOAT (rgba16f)",
+        DXGI_FORMAT_R16G16B16A16_FLOAT,
+    ),
+    ("R32G32B32A32_FLOAT", DXGI_FORMAT_R32G32B32A32_FLOAT),
+    ("R9G9B9E5_SHAREDEXP", DXGI_FORMAT_R9G9B9E5_SHAREDEXP),
+];
+
+/// What the device can do with one format
+pub struct FormatSupport {
+    pub render_target: bool,
+    pub blendable: bool,
+    /// Can be scanned out, so it can back a swapchain
+    pub display: bool,
+    pub typed_uav: bool,
+    pub typed_load: bool,
+    pub typed_store: bool,
+}
+
+/// `None` if the device doesn't know `format` at all
+pub fn query_format_support(device: &d3d12::Device, format: DXGI_FORMAT) -> Option<FormatSupport> {
+    let mut support = D3D12_FEATURE_DATA_FORMAT_SUPPORT {
+        Format: format,
+        Support1: D3D12_FORMAT_SUPPORT1_NONE,
+        Support2: D3D12_FORMAT_SUPPORT2_NONE,
+    };
+    let hr = check_feature_support(device, D3D12_FEATURE_FORMAT_SUPPORT, &mut support);
+    if FAILED(hr) {
+        return None;
+    }
+
+    let support1 = |flag| support.Support1 & flag != 0;
+    let support2 = |flag| support.Support2 & flag != 0;
+    Some(FormatSupport {
+        render_target: support1(D3D12_FORMAT_SUPPORT1_RENDER_TARGET),
+        blendable: support1(D3D12_FORMAT_SUPPORT1_BLENDABLE),
+        display: support1(D3D12_FORMAT_SUPPORT1_DISPLAY),
+        typed_uav: support1(D3D12_FORMAT_SUPPORT1_TYPED_UNORDERED_ACCESS_VIEW),
+        typed_load: support2(D3D12_FORMAT_SUPPORT2_UAV_TYPED_LOAD),
+        typed_store: support2(D3D12_FORMAT_SUPPORT2_UAV_TYPED_STORE),
+    })
+}
+
+/// Print a table of what `device` supports for each of `LISTED_FORMATS`
+pub fn display_format_support(device: &d3d12::Device) {
+    let mark = |supported: bool| if supported { "yes" } else { "-" };
+
+    status!(
+        "{:<30} {:>6} {:>6} {:>8} {:>6} {:>6} {:>6}",
+        "Format",
+        "RTV",
+        "Blend",
+        "Display",
+        "UAV",
+        "Load",
+        "Store"
+    );
+    for &(name, format) in LISTED_FORMATS {
+        match query_format_support(device, format) {
+            Some(support) => status!(
+                "{:<30} {:>6} {:>6} {:>8} {:>6} {:>6} {:>6}",
+                name,
+                mark(support.render_target),
+                mark(support.blendable),
+                mark(support.display),
+                mark(support.typed_uav),
+                mark(support.typed_load),
+                mark(support.typed_store)
+            ),
+            None => status!("{:<30} not supported", name),
+        }
+    }
+}
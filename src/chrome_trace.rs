@@ -0,0 +1,132 @@
+//! Per-frame CPU phases and GPU frame times, in Chrome's trace event JSON
+//!
+//! Open the file in chrome://tracing or ui.perfetto.dev to see frames on a timeline.
+//! The CPU and GPU each get a track, and every phase is one complete ("X") event on it.
+
+use crate::check_hr_err;
+use crate::os_helpers::DxError;
+use crate::status;
+
+use serde_json::{json, Value};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+const PROCESS_ID: u32 = 1;
+const CPU_TRACK: u32 = 1;
+const GPU_TRACK: u32 = 2;
+
+pub struct ChromeTrace {
+    path: PathBuf,
+    events: Vec<Value>,
+    /// Time 0 in the trace
+    start: Instant,
+    /// A GPU timestamp taken at the same moment as `calibrated_at`, to put GPU work on the CPU's timeline
+    gpu_origin: u64,
+    calibrated_at: Instant,
+    /// Timestamp ticks per second on the queue the GPU timestamps come from
+    gpu_frequency: u64,
+}
+
+fn track_name(track: u32, name: &str) -> Value {
+    json!({
+        "name": "thread_name",
+        "ph": "M",
+        "pid": PROCESS_ID,
+        "tid": track,
+        "args": { "name": name },
+    })
+}
+
+impl ChromeTrace {
+    /// Start a trace that `save` writes to `path`, timing GPU work on `cmd_queue`
+    pub fn new(path: &Path, cmd_queue: &d3d12::CommandQueue) -> Result<Self, DxError> {
+        let now = Instant::now();
+        let mut trace = ChromeTrace {
+            path: path.to_owned(),
+            events: vec![track_name(CPU_TRACK, "CPU"), track_name(GPU_TRACK, "GPU")],
+            start: now,
+            gpu_origin: 0,
+            calibrated_at: now,
+            gpu_frequency: 1,
+        };
+        trace.calibrate(cmd_queue)?;
+
+        Ok(trace)
+    }
+
+    /// Line GPU timestamps up with the CPU clock again. Needed whenever the queue changes.
+    pub fn calibrate(&mut self, cmd_queue: &d3d12::CommandQueue) -> Result<(), DxError> {
+        self.gpu_frequency = check_hr_err! {
+            unsafe {
+                let mut frequency = 0u64;
+                let hr = cmd_queue.GetTimestampFrequency(&mut frequency);
+
+                (frequency, hr)
+            }
+        };
+        self.gpu_origin = check_hr_err! {
+            unsafe {
+                let mut gpu_timestamp = 0u64;
+                let mut cpu_timestamp = 0u64;
+                let hr = cmd_queue.GetClockCalibration(&mut gpu_timestamp, &mut cpu_timestamp);
+
+                (gpu_timestamp, hr)
+            }
+        };
+        self.calibrated_at = Instant::now();
+
+        Ok(())
+    }
+
+    fn push(&mut self, track: u32, name: &str, frame: u32, start_us: f64, duration_us: f64) {
+        self.events.push(json!({
+            "name": name,
+            "ph": "X",
+            "pid": PROCESS_ID,
+            "tid": track,
+            "ts": start_us,
+            "dur": duration_us,
+            "args": { "frame": frame },
+        }));
+    }
+
+    /// Record a CPU phase of `frame` that started at `start` and ends now
+    pub fn cpu_phase(&mut self, name: &str, frame: u32, start: Instant) {
+        let start_us = (start - self.start).as_secs_f64() * 1e6;
+        let duration_us = start.elapsed().as_secs_f64() * 1e6;
+        self.push(CPU_TRACK, name, frame, start_us, duration_us);
+    }
+
+    /// Record `frame`'s GPU work, from its begin and end timestamps
+    pub fn gpu_frame(&mut self, frame: u32, begin: u64, end: u64) {
+        let ticks_to_us = |ticks: f64| ticks * 1e6 / self.gpu_frequency as f64;
+        // Timestamps from before the calibration come out negative, which is fine
+        let since_origin_us = ticks_to_us(begin as f64 - self.gpu_origin as f64);
+        let start_us = (self.calibrated_at - self.start).as_secs_f64() * 1e6 + since_origin_us;
+        let duration_us = ticks_to_us(end.saturating_sub(begin) as f64);
+        self.push(GPU_TRACK, "frame", frame, start_us, duration_us);
+    }
+
+    /// Write every event so far to the trace file
+    pub fn save(&self) {
+        let trace = json!({
+            "traceEvents": self.events,
+            "displayTimeUnit": "ms",
+        });
+        let text = serde_json::to_string(&trace).expect("Failed to serialize the trace");
+        match fs::write(&self.path, text) {
+            Ok(()) => status!(
+                "Saved a trace of {} events to {}",
+                self.events.len(),
+                self.path.display()
+            ),
+            Err(err) => status!(
+                "⚠️ Couldn't write the trace to {}: {}",
+                self.path.display(),
+                err
+            ),
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Command allocators and lists
+
+use crate::check_hr_err;
+use crate::check_hr_only;
+use crate::os_helpers::{self, DxError};
+
+use winapi::um::d3d12::*;
+
+/// Create a direct command allocator and a command list recording into it.
+/// The list comes back closed, so every frame can start with `reset`.
+pub fn create_direct_command_list(
+    device: &d3d12::Device,
+    name: &str,
+) -> Result<(d3d12::CommandAllocator, d3d12::GraphicsCommandList), DxError> {
+    create_command_list(device, d3d12::CmdListType::Direct, name)
+}
+
+/// Like `create_direct_command_list`, but for a compute queue
+pub fn create_compute_command_list(
+    device: &d3d12::Device,
+    name: &str,
+) -> Result<(d3d12::CommandAllocator, d3d12::GraphicsCommandList), DxError> {
+    create_command_list(device, d3d12::CmdListType::Compute, name)
+}
+
+fn create_command_list(
+    device: &d3d12::Device,
+    list_type: d3d12::CmdListType,
+    name: &str,
+) -> Result<(d3d12::CommandAllocator, d3d12::GraphicsCommandList), DxError> {
+    let allocator = check_hr_err!(device.create_command_allocator(list_type));
+    let cmd_list = check_hr_err!(device.create_graphics_command_list(
+        list_type,
+        allocator,
+        d3d12::PipelineState::null(),
+        0,
+    ));
+    check_hr_only!(cmd_list.close());
+
+    os_helpers::set_name(&allocator, &format!("{} Allocator", name));
+    os_helpers::set_name(&cmd_list, name);
+
+    Ok((allocator, cmd_list))
+}
+
+/// Transition all of `resource` from `before` to `after`
+pub fn transition(
+    cmd_list: &d3d12::GraphicsCommandList,
+    resource: d3d12::Resource,
+    before: D3D12_RESOURCE_STATES,
+    after: D3D12_RESOURCE_STATES,
+) {
+    transition_all(cmd_list, &[(resource, before, after)]);
+}
+
+/// Like `transition`, but batches every `(resource, before, after)` into one `ResourceBarrier` call
+pub fn transition_all(
+    cmd_list: &d3d12::GraphicsCommandList,
+    transitions: &[(
+        d3d12::Resource,
+        D3D12_RESOURCE_STATES,
+        D3D12_RESOURCE_STATES,
+    )],
+) {
+    let barriers: Vec<d3d12::ResourceBarrier> = transitions
+        .iter()
+        .map(|&(resource, before, after)| {
+            d3d12::ResourceBarrier::transition(
+                resource,
+                D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+                before,
+                after,
+                D3D12_RESOURCE_BARRIER_FLAG_NONE,
+            )
+        })
+        .collect();
+
+    cmd_list.resource_barrier(&barriers);
+}
+
+/// Make later work on `resource` wait for earlier UAV writes to it
+pub fn uav_barrier(cmd_list: &d3d12::GraphicsCommandList, resource: d3d12::Resource) {
+    let mut barrier = D3D12_RESOURCE_BARRIER {
+        Type: D3D12_RESOURCE_BARRIER_TYPE_UAV,
+        Flags: D3D12_RESOURCE_BARRIER_FLAG_NONE,
+        u: unsafe { std::mem::zeroed() },
+    };
+    unsafe {
+        barrier.u.UAV_mut().pResource = resource.as_mut_ptr();
+        cmd_list.ResourceBarrier(1, &barrier);
+    }
+}
@@ -0,0 +1,98 @@
+//! Default options from a TOML file, so a fixed setup doesn't need retyping every run
+//!
+//! Keys are the long flag names, e.g. `feature-level = "12_1"` or `no-vsync = true`.
+//! Each value is turned back into a flag and parsed with the rest of the command line,
+//! so the file accepts exactly what the flags do.
+
+use structopt::StructOpt;
+use toml::Value;
+
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Looked for next to the executable when there's no `--config`
+pub const DEFAULT_CONFIG_NAME: &str = "itsy-bitsy.toml";
+
+/// `itsy-bitsy.toml` in the same directory as the executable
+fn default_config_path() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    Some(exe.parent()?.join(DEFAULT_CONFIG_NAME))
+}
+
+/// Turn the config file into flags, skipping anything `matches` already got from the command line
+fn config_args(
+    path: &Path,
+    matches: &structopt::clap::ArgMatches,
+) -> Result<Vec<OsString>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| format!("Couldn't read {}: {}", path.display(), err))?;
+    let table: toml::value::Table =
+        toml::from_str(&text).map_err(|err| format!("{}: {}", path.display(), err))?;
+
+    let mut args = vec![];
+    for (key, value) in table {
+        // Accept field_name spellings too, since that's how the options read in the source
+        let name = key.replace('_', "-");
+        if name == "config" {
+            return Err(format!(
+                "{}: a config file can't point at another one",
+                path.display()
+            ));
+        }
+
+        // The command line always wins
+        if matches.occurrences_of(&name) > 0 {
+            continue;
+        }
+
+        let flag = OsString::from(format!("--{}", name));
+        match value {
+            Value::Boolean(true) => args.push(flag),
+            Value::Boolean(false) => {}
+            // --verbose counts how often it's repeated, rather than taking a value
+            Value::Integer(count) if name == "verbose" => {
+                args.extend((0..count).map(|_| flag.clone()));
+            }
+            Value::Integer(number) => args.extend(vec![flag, number.to_string().into()]),
+            Value::Float(number) => args.extend(vec![flag, number.to_string().into()]),
+            Value::String(text) => args.extend(vec![flag, text.into()]),
+            _ => {
+                return Err(format!(
+                    "{}: \"{}\" should be a string, number, or boolean",
+                    path.display(),
+                    key
+                ))
+            }
+        }
+    }
+
+    Ok(args)
+}
+
+/// Parse the command line like `T::from_args()`, filling in anything it leaves out from the config file.
+/// The file is `--config <path>` if given, or else `itsy-bitsy.toml` next to the executable if it exists.
+/// Returns the path of the file it loaded too, for the caller to report.
+pub fn from_args_and_config<T: StructOpt>() -> (T, Option<PathBuf>) {
+    let args: Vec<OsString> = std::env::args_os().collect();
+
+    // This also handles --help and bad arguments, before we go looking for a file
+    let matches = T::clap().get_matches_from(&args);
+
+    let (path, explicit) = match matches.value_of_os("config") {
+        Some(path) => (Some(PathBuf::from(path)), true),
+        None => (default_config_path(), false),
+    };
+    let (file_args, loaded) = match path {
+        Some(path) if explicit || path.exists() => match config_args(&path, &matches) {
+            Ok(file_args) => (file_args, Some(path)),
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                std::process::exit(1);
+            }
+        },
+        _ => (vec![], None),
+    };
+
+    (T::from_iter(args.into_iter().chain(file_args)), loaded)
+}
@@ -0,0 +1,85 @@
+//! Per-frame constant data, sub-allocated out of one persistently mapped upload buffer
+
+use crate::buffers::{self, align_up};
+use crate::check_hr_err;
+use crate::os_helpers::{self, DxError};
+
+use winapi::um::d3d12::D3D12_CONSTANT_BUFFER_DATA_PLACEMENT_ALIGNMENT;
+
+use std::mem::size_of;
+use std::ptr;
+
+const CBV_ALIGNMENT: u64 = D3D12_CONSTANT_BUFFER_DATA_PLACEMENT_ALIGNMENT as u64;
+
+/// One upload buffer split into a region per frame in flight.
+/// A frame only writes into its own region, so it never clobbers constants the GPU is still reading.
+pub struct ConstantBufferRing {
+    buffer: d3d12::Resource,
+    mapped: *mut u8,
+    frame_size: u64,
+    frame_count: u32,
+    frame_start: u64,
+    offset: u64,
+}
+
+impl ConstantBufferRing {
+    /// Reserve `frame_size` bytes of constants for each of `frame_count` frames
+    pub fn new(device: &d3d12::Device, frame_count: u32, frame_size: u64) -> Result<Self, DxError> {
+        let frame_size = align_up(frame_size, CBV_ALIGNMENT);
+        let buffer = buffers::create_upload_buffer(device, frame_size * frame_count as u64)?;
+        os_helpers::set_name(&buffer, "Constant Buffer Ring");
+
+        // Upload heaps can stay mapped for their whole life, so we map once and never unmap
+        let mapped = check_hr_err!(buffer.map(0, Some(0..0))) as *mut u8;
+
+        Ok(ConstantBufferRing {
+            buffer,
+            mapped,
+            frame_size,
+            frame_count,
+            frame_start: 0,
+            offset: 0,
+        })
+    }
+
+    /// Start writing into the region for `frame_index`.
+    /// Only call this after `FrameContext::begin` for this frame, or the GPU may still be reading it.
+    pub fn begin_frame(&mut self, frame_index: u32) {
+        assert!(frame_index < self.frame_count);
+        self.frame_start = frame_index as u64 * self.frame_size;
+        self.offset = 0;
+    }
+
+    /// Copy `value` into this frame's region, returning a GPU address to bind as a root CBV
+    pub fn write<T: Copy>(&mut self, value: &T) -> u64 {
+        let size = size_of::<T>() as u64;
+        let offset = align_up(self.offset, CBV_ALIGNMENT);
+        assert!(
+            offset + size <= self.frame_size,
+            "Constant buffer ring is out of space: {} + {} > {} bytes per frame",
+            offset,
+            size,
+            self.frame_size
+        );
+        self.offset = offset + size;
+
+        let start = self.frame_start + offset;
+        unsafe {
+            ptr::copy_nonoverlapping(
+                value as *const T as *const u8,
+                self.mapped.add(start as usize),
+                size as usize,
+            );
+        }
+
+        self.buffer.gpu_virtual_address() + start
+    }
+
+    /// Release the buffer. Nothing in flight can still be reading constants out of it.
+    pub fn destroy(&mut self) {
+        self.buffer.unmap(0, None);
+        unsafe {
+            self.buffer.destroy();
+        }
+    }
+}
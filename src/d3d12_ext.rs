@@ -0,0 +1,647 @@
+//! Bits of the D3D12 headers that are newer than what winapi ships.
+//! Names and layouts match d3d12.h so they read like the rest of winapi.
+
+#![allow(non_camel_case_types, non_snake_case, dead_code)]
+// COM methods take as many arguments as the header says
+#![allow(clippy::too_many_arguments)]
+
+use winapi::ctypes::{c_char, c_void, wchar_t};
+use winapi::shared::basetsd::SIZE_T;
+use winapi::shared::dxgiformat::DXGI_FORMAT;
+use winapi::shared::guiddef::{REFGUID, REFIID};
+use winapi::shared::minwindef::BOOL;
+use winapi::um::d3d12::*;
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use winapi::um::winnt::{HANDLE, HRESULT, LPCWSTR};
+use winapi::RIDL;
+
+pub const D3D12_FEATURE_D3D12_OPTIONS5: D3D12_FEATURE = 27;
+
+// dxgi.h, for IDXGIFactory::MakeWindowAssociation
+pub const DXGI_MWA_NO_ALT_ENTER: u32 = 1 << 1;
+
+/// "DX12 Ultimate": DXR 1.1, mesh shaders, sampler feedback, and VRS tier 2
+pub const D3D_FEATURE_LEVEL_12_2: winapi::um::d3dcommon::D3D_FEATURE_LEVEL = 0xc200;
+
+pub type D3D12_RENDER_PASS_TIER = u32;
+pub const D3D12_RENDER_PASS_TIER_0: D3D12_RENDER_PASS_TIER = 0;
+pub const D3D12_RENDER_PASS_TIER_1: D3D12_RENDER_PASS_TIER = 1;
+pub const D3D12_RENDER_PASS_TIER_2: D3D12_RENDER_PASS_TIER = 2;
+
+pub type D3D12_RAYTRACING_TIER = u32;
+pub const D3D12_RAYTRACING_TIER_NOT_SUPPORTED: D3D12_RAYTRACING_TIER = 0;
+pub const D3D12_RAYTRACING_TIER_1_0: D3D12_RAYTRACING_TIER = 10;
+pub const D3D12_RAYTRACING_TIER_1_1: D3D12_RAYTRACING_TIER = 11;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct D3D12_FEATURE_DATA_D3D12_OPTIONS5 {
+    pub SRVOnlyTiledResourceTier3: BOOL,
+    pub RenderPassesTier: D3D12_RENDER_PASS_TIER,
+    pub RaytracingTier: D3D12_RAYTRACING_TIER,
+}
+
+pub const D3D12_FEATURE_D3D12_OPTIONS7: D3D12_FEATURE = 32;
+
+// winapi stops at 6_0
+pub const D3D_SHADER_MODEL_6_1: D3D_SHADER_MODEL = 0x61;
+pub const D3D_SHADER_MODEL_6_2: D3D_SHADER_MODEL = 0x62;
+pub const D3D_SHADER_MODEL_6_3: D3D_SHADER_MODEL = 0x63;
+pub const D3D_SHADER_MODEL_6_4: D3D_SHADER_MODEL = 0x64;
+pub const D3D_SHADER_MODEL_6_5: D3D_SHADER_MODEL = 0x65;
+pub const D3D_SHADER_MODEL_6_6: D3D_SHADER_MODEL = 0x66;
+pub const D3D_SHADER_MODEL_6_7: D3D_SHADER_MODEL = 0x67;
+
+pub type D3D12_MESH_SHADER_TIER = u32;
+pub const D3D12_MESH_SHADER_TIER_NOT_SUPPORTED: D3D12_MESH_SHADER_TIER = 0;
+pub const D3D12_MESH_SHADER_TIER_1: D3D12_MESH_SHADER_TIER = 10;
+
+pub type D3D12_SAMPLER_FEEDBACK_TIER = u32;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct D3D12_FEATURE_DATA_D3D12_OPTIONS7 {
+    pub MeshShaderTier: D3D12_MESH_SHADER_TIER,
+    pub SamplerFeedbackTier: D3D12_SAMPLER_FEEDBACK_TIER,
+}
+
+// Device Removed Extended Data (DRED)
+
+pub type D3D12_DRED_ENABLEMENT = u32;
+pub const D3D12_DRED_ENABLEMENT_SYSTEM_CONTROLLED: D3D12_DRED_ENABLEMENT = 0;
+pub const D3D12_DRED_ENABLEMENT_FORCED_OFF: D3D12_DRED_ENABLEMENT = 1;
+pub const D3D12_DRED_ENABLEMENT_FORCED_ON: D3D12_DRED_ENABLEMENT = 2;
+
+RIDL! {#[uuid(0x82bc481c, 0x6b9b, 0x4030, 0xae, 0xdb, 0x7e, 0xe3, 0xd1, 0xdf, 0x1e, 0x63)]
+interface ID3D12DeviceRemovedExtendedDataSettings(ID3D12DeviceRemovedExtendedDataSettingsVtbl):
+    IUnknown(IUnknownVtbl) {
+    fn SetAutoBreadcrumbsEnablement(
+        Enablement: D3D12_DRED_ENABLEMENT,
+    ) -> (),
+    fn SetPageFaultEnablement(
+        Enablement: D3D12_DRED_ENABLEMENT,
+    ) -> (),
+    fn SetWatsonDumpEnablement(
+        Enablement: D3D12_DRED_ENABLEMENT,
+    ) -> (),
+}}
+
+pub type D3D12_AUTO_BREADCRUMB_OP = u32;
+
+/// Names for `D3D12_AUTO_BREADCRUMB_OP`, indexed by value
+pub const D3D12_AUTO_BREADCRUMB_OP_NAMES: &[&str] = &[
+    "SETMARKER",
+    "BEGINEVENT",
+    "ENDEVENT",
+    "DRAWINSTANCED",
+    "DRAWINDEXEDINSTANCED",
+    "EXECUTEINDIRECT",
+    "DISPATCH",
+    "COPYBUFFERREGION",
+    "COPYTEXTUREREGION",
+    "COPYRESOURCE",
+    "COPYTILES",
+    "RESOLVESUBRESOURCE",
+    "CLEARRENDERTARGETVIEW",
+    "CLEARUNORDEREDACCESSVIEW",
+    "CLEARDEPTHSTENCILVIEW",
+    "RESOURCEBARRIER",
+    "EXECUTEBUNDLE",
+    "PRESENT",
+    "RESOLVEQUERYDATA",
+    "BEGINSUBMISSION",
+    "ENDSUBMISSION",
+    "DECODEFRAME",
+    "PROCESSFRAMES",
+    "ATOMICCOPYBUFFERUINT",
+    "ATOMICCOPYBUFFERUINT64",
+    "RESOLVESUBRESOURCEREGION",
+    "WRITEBUFFERIMMEDIATE",
+    "DECODEFRAME1",
+    "SETPROTECTEDRESOURCESESSION",
+    "DECODEFRAME2",
+    "PROCESSFRAMES1",
+    "BUILDRAYTRACINGACCELERATIONSTRUCTURE",
+    "EMITRAYTRACINGACCELERATIONSTRUCTUREPOSTBUILDINFO",
+    "COPYRAYTRACINGACCELERATIONSTRUCTURE",
+    "DISPATCHRAYS",
+    "INITIALIZEMETACOMMAND",
+    "EXECUTEMETACOMMAND",
+    "ESTIMATEMOTION",
+    "RESOLVEMOTIONVECTORHEAP",
+    "SETPIPELINESTATE1",
+    "INITIALIZEEXTENSIONCOMMAND",
+    "EXECUTEEXTENSIONCOMMAND",
+];
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct D3D12_AUTO_BREADCRUMB_NODE {
+    pub pCommandListDebugNameA: *const c_char,
+    pub pCommandListDebugNameW: *const wchar_t,
+    pub pCommandQueueDebugNameA: *const c_char,
+    pub pCommandQueueDebugNameW: *const wchar_t,
+    pub pCommandList: *mut ID3D12GraphicsCommandList,
+    pub pCommandQueue: *mut ID3D12CommandQueue,
+    pub BreadcrumbCount: u32,
+    pub pLastBreadcrumbValue: *const u32,
+    pub pCommandHistory: *const D3D12_AUTO_BREADCRUMB_OP,
+    pub pNext: *const D3D12_AUTO_BREADCRUMB_NODE,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct D3D12_DRED_AUTO_BREADCRUMBS_OUTPUT {
+    pub pHeadAutoBreadcrumbNode: *const D3D12_AUTO_BREADCRUMB_NODE,
+}
+
+pub type D3D12_DRED_ALLOCATION_TYPE = u32;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct D3D12_DRED_ALLOCATION_NODE {
+    pub ObjectNameA: *const c_char,
+    pub ObjectNameW: *const wchar_t,
+    pub AllocationType: D3D12_DRED_ALLOCATION_TYPE,
+    pub pNext: *const D3D12_DRED_ALLOCATION_NODE,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct D3D12_DRED_PAGE_FAULT_OUTPUT {
+    pub PageFaultVA: D3D12_GPU_VIRTUAL_ADDRESS,
+    pub pHeadExistingAllocationNode: *const D3D12_DRED_ALLOCATION_NODE,
+    pub pHeadRecentFreedAllocationNode: *const D3D12_DRED_ALLOCATION_NODE,
+}
+
+RIDL! {#[uuid(0x98931d33, 0x5ae8, 0x4791, 0xaa, 0x3c, 0x1a, 0x73, 0xa2, 0x93, 0x4e, 0x71)]
+interface ID3D12DeviceRemovedExtendedData(ID3D12DeviceRemovedExtendedDataVtbl):
+    IUnknown(IUnknownVtbl) {
+    fn GetAutoBreadcrumbsOutput(
+        pOutput: *mut D3D12_DRED_AUTO_BREADCRUMBS_OUTPUT,
+    ) -> HRESULT,
+    fn GetPageFaultAllocationOutput(
+        pOutput: *mut D3D12_DRED_PAGE_FAULT_OUTPUT,
+    ) -> HRESULT,
+}}
+
+// DXR: acceleration structures, and the newer device and command list interfaces that use them
+
+pub const D3D12_RESOURCE_STATE_RAYTRACING_ACCELERATION_STRUCTURE: D3D12_RESOURCE_STATES = 0x40_0000;
+pub const D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BYTE_ALIGNMENT: u64 = 256;
+
+pub type D3D12_RAYTRACING_GEOMETRY_TYPE = u32;
+pub const D3D12_RAYTRACING_GEOMETRY_TYPE_TRIANGLES: D3D12_RAYTRACING_GEOMETRY_TYPE = 0;
+pub const D3D12_RAYTRACING_GEOMETRY_TYPE_PROCEDURAL_PRIMITIVE_AABBS:
+    D3D12_RAYTRACING_GEOMETRY_TYPE = 1;
+
+pub type D3D12_RAYTRACING_GEOMETRY_FLAGS = u32;
+pub const D3D12_RAYTRACING_GEOMETRY_FLAG_NONE: D3D12_RAYTRACING_GEOMETRY_FLAGS = 0;
+pub const D3D12_RAYTRACING_GEOMETRY_FLAG_OPAQUE: D3D12_RAYTRACING_GEOMETRY_FLAGS = 0x1;
+pub const D3D12_RAYTRACING_GEOMETRY_FLAG_NO_DUPLICATE_ANYHIT_INVOCATION:
+    D3D12_RAYTRACING_GEOMETRY_FLAGS = 0x2;
+
+pub type D3D12_RAYTRACING_ACCELERATION_STRUCTURE_TYPE = u32;
+pub const D3D12_RAYTRACING_ACCELERATION_STRUCTURE_TYPE_TOP_LEVEL:
+    D3D12_RAYTRACING_ACCELERATION_STRUCTURE_TYPE = 0;
+pub const D3D12_RAYTRACING_ACCELERATION_STRUCTURE_TYPE_BOTTOM_LEVEL:
+    D3D12_RAYTRACING_ACCELERATION_STRUCTURE_TYPE = 1;
+
+pub type D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAGS = u32;
+pub const D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAG_NONE:
+    D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAGS = 0;
+pub const D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAG_ALLOW_UPDATE:
+    D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAGS = 0x1;
+pub const D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAG_ALLOW_COMPACTION:
+    D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAGS = 0x2;
+pub const D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAG_PREFER_FAST_TRACE:
+    D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAGS = 0x4;
+pub const D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAG_PREFER_FAST_BUILD:
+    D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAGS = 0x8;
+pub const D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAG_MINIMIZE_MEMORY:
+    D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAGS = 0x10;
+pub const D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAG_PERFORM_UPDATE:
+    D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAGS = 0x20;
+
+pub type D3D12_ELEMENTS_LAYOUT = u32;
+pub const D3D12_ELEMENTS_LAYOUT_ARRAY: D3D12_ELEMENTS_LAYOUT = 0;
+pub const D3D12_ELEMENTS_LAYOUT_ARRAY_OF_POINTERS: D3D12_ELEMENTS_LAYOUT = 1;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct D3D12_GPU_VIRTUAL_ADDRESS_AND_STRIDE {
+    pub StartAddress: D3D12_GPU_VIRTUAL_ADDRESS,
+    pub StrideInBytes: u64,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct D3D12_RAYTRACING_GEOMETRY_TRIANGLES_DESC {
+    pub Transform3x4: D3D12_GPU_VIRTUAL_ADDRESS,
+    pub IndexFormat: DXGI_FORMAT,
+    pub VertexFormat: DXGI_FORMAT,
+    pub IndexCount: u32,
+    pub VertexCount: u32,
+    pub IndexBuffer: D3D12_GPU_VIRTUAL_ADDRESS,
+    pub VertexBuffer: D3D12_GPU_VIRTUAL_ADDRESS_AND_STRIDE,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct D3D12_RAYTRACING_GEOMETRY_DESC {
+    pub Type: D3D12_RAYTRACING_GEOMETRY_TYPE,
+    pub Flags: D3D12_RAYTRACING_GEOMETRY_FLAGS,
+    // A union with D3D12_RAYTRACING_GEOMETRY_AABBS_DESC, which is smaller. We only build triangles.
+    pub Triangles: D3D12_RAYTRACING_GEOMETRY_TRIANGLES_DESC,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct D3D12_BUILD_RAYTRACING_ACCELERATION_STRUCTURE_INPUTS {
+    pub Type: D3D12_RAYTRACING_ACCELERATION_STRUCTURE_TYPE,
+    pub Flags: D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAGS,
+    pub NumDescs: u32,
+    pub DescsLayout: D3D12_ELEMENTS_LAYOUT,
+    // A union: `InstanceDescs` (a GPU address) for top-level structures,
+    // or `pGeometryDescs` (a CPU pointer) for bottom-level ones. Both are 64 bits.
+    pub InstanceDescsOrGeometryDescs: u64,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct D3D12_BUILD_RAYTRACING_ACCELERATION_STRUCTURE_DESC {
+    pub DestAccelerationStructureData: D3D12_GPU_VIRTUAL_ADDRESS,
+    pub Inputs: D3D12_BUILD_RAYTRACING_ACCELERATION_STRUCTURE_INPUTS,
+    pub SourceAccelerationStructureData: D3D12_GPU_VIRTUAL_ADDRESS,
+    pub ScratchAccelerationStructureData: D3D12_GPU_VIRTUAL_ADDRESS,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct D3D12_RAYTRACING_ACCELERATION_STRUCTURE_PREBUILD_INFO {
+    pub ResultDataMaxSizeInBytes: u64,
+    pub ScratchDataSizeInBytes: u64,
+    pub UpdateScratchDataSizeInBytes: u64,
+}
+
+pub type D3D12_RAYTRACING_INSTANCE_FLAGS = u32;
+pub const D3D12_RAYTRACING_INSTANCE_FLAG_NONE: D3D12_RAYTRACING_INSTANCE_FLAGS = 0;
+pub const D3D12_RAYTRACING_INSTANCE_FLAG_TRIANGLE_CULL_DISABLE: D3D12_RAYTRACING_INSTANCE_FLAGS =
+    0x1;
+pub const D3D12_RAYTRACING_INSTANCE_FLAG_TRIANGLE_FRONT_COUNTERCLOCKWISE:
+    D3D12_RAYTRACING_INSTANCE_FLAGS = 0x2;
+pub const D3D12_RAYTRACING_INSTANCE_FLAG_FORCE_OPAQUE: D3D12_RAYTRACING_INSTANCE_FLAGS = 0x4;
+pub const D3D12_RAYTRACING_INSTANCE_FLAG_FORCE_NON_OPAQUE: D3D12_RAYTRACING_INSTANCE_FLAGS = 0x8;
+
+pub const D3D12_RAYTRACING_INSTANCE_DESCS_BYTE_ALIGNMENT: u64 = 16;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct D3D12_RAYTRACING_INSTANCE_DESC {
+    pub Transform: [f32; 12],
+    // InstanceID : 24, InstanceMask : 8
+    pub InstanceID_InstanceMask: u32,
+    // InstanceContributionToHitGroupIndex : 24, Flags : 8
+    pub InstanceContributionToHitGroupIndex_Flags: u32,
+    pub AccelerationStructure: D3D12_GPU_VIRTUAL_ADDRESS,
+}
+
+// DXR state objects
+
+pub const D3D12_SHADER_IDENTIFIER_SIZE_IN_BYTES: usize = 32;
+pub const D3D12_RAYTRACING_SHADER_RECORD_BYTE_ALIGNMENT: u64 = 32;
+pub const D3D12_RAYTRACING_SHADER_TABLE_BYTE_ALIGNMENT: u64 = 64;
+
+pub type D3D12_STATE_OBJECT_TYPE = u32;
+pub const D3D12_STATE_OBJECT_TYPE_COLLECTION: D3D12_STATE_OBJECT_TYPE = 0;
+pub const D3D12_STATE_OBJECT_TYPE_RAYTRACING_PIPELINE: D3D12_STATE_OBJECT_TYPE = 3;
+
+pub type D3D12_STATE_SUBOBJECT_TYPE = u32;
+pub const D3D12_STATE_SUBOBJECT_TYPE_STATE_OBJECT_CONFIG: D3D12_STATE_SUBOBJECT_TYPE = 0;
+pub const D3D12_STATE_SUBOBJECT_TYPE_GLOBAL_ROOT_SIGNATURE: D3D12_STATE_SUBOBJECT_TYPE = 1;
+pub const D3D12_STATE_SUBOBJECT_TYPE_LOCAL_ROOT_SIGNATURE: D3D12_STATE_SUBOBJECT_TYPE = 2;
+pub const D3D12_STATE_SUBOBJECT_TYPE_NODE_MASK: D3D12_STATE_SUBOBJECT_TYPE = 3;
+pub const D3D12_STATE_SUBOBJECT_TYPE_DXIL_LIBRARY: D3D12_STATE_SUBOBJECT_TYPE = 5;
+pub const D3D12_STATE_SUBOBJECT_TYPE_EXISTING_COLLECTION: D3D12_STATE_SUBOBJECT_TYPE = 6;
+pub const D3D12_STATE_SUBOBJECT_TYPE_SUBOBJECT_TO_EXPORTS_ASSOCIATION: D3D12_STATE_SUBOBJECT_TYPE =
+    7;
+pub const D3D12_STATE_SUBOBJECT_TYPE_DXIL_SUBOBJECT_TO_EXPORTS_ASSOCIATION:
+    D3D12_STATE_SUBOBJECT_TYPE = 8;
+pub const D3D12_STATE_SUBOBJECT_TYPE_RAYTRACING_SHADER_CONFIG: D3D12_STATE_SUBOBJECT_TYPE = 9;
+pub const D3D12_STATE_SUBOBJECT_TYPE_RAYTRACING_PIPELINE_CONFIG: D3D12_STATE_SUBOBJECT_TYPE = 10;
+pub const D3D12_STATE_SUBOBJECT_TYPE_HIT_GROUP: D3D12_STATE_SUBOBJECT_TYPE = 11;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct D3D12_STATE_SUBOBJECT {
+    pub Type: D3D12_STATE_SUBOBJECT_TYPE,
+    pub pDesc: *const c_void,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct D3D12_STATE_OBJECT_DESC {
+    pub Type: D3D12_STATE_OBJECT_TYPE,
+    pub NumSubobjects: u32,
+    pub pSubobjects: *const D3D12_STATE_SUBOBJECT,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct D3D12_EXPORT_DESC {
+    pub Name: LPCWSTR,
+    pub ExportToRename: LPCWSTR,
+    pub Flags: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct D3D12_DXIL_LIBRARY_DESC {
+    pub DXILLibrary: D3D12_SHADER_BYTECODE,
+    pub NumExports: u32,
+    pub pExports: *const D3D12_EXPORT_DESC,
+}
+
+pub type D3D12_HIT_GROUP_TYPE = u32;
+pub const D3D12_HIT_GROUP_TYPE_TRIANGLES: D3D12_HIT_GROUP_TYPE = 0;
+pub const D3D12_HIT_GROUP_TYPE_PROCEDURAL_PRIMITIVE: D3D12_HIT_GROUP_TYPE = 1;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct D3D12_HIT_GROUP_DESC {
+    pub HitGroupExport: LPCWSTR,
+    pub Type: D3D12_HIT_GROUP_TYPE,
+    pub AnyHitShaderImport: LPCWSTR,
+    pub ClosestHitShaderImport: LPCWSTR,
+    pub IntersectionShaderImport: LPCWSTR,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct D3D12_RAYTRACING_SHADER_CONFIG {
+    pub MaxPayloadSizeInBytes: u32,
+    pub MaxAttributeSizeInBytes: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct D3D12_GLOBAL_ROOT_SIGNATURE {
+    pub pGlobalRootSignature: *mut ID3D12RootSignature,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct D3D12_RAYTRACING_PIPELINE_CONFIG {
+    pub MaxTraceRecursionDepth: u32,
+}
+
+RIDL! {#[uuid(0x47016943, 0xfca8, 0x4594, 0x93, 0xea, 0xaf, 0x25, 0x8b, 0x55, 0x34, 0x6d)]
+interface ID3D12StateObject(ID3D12StateObjectVtbl): ID3D12Pageable(ID3D12PageableVtbl) {
+}}
+
+RIDL! {#[uuid(0xde5fa827, 0x9bf9, 0x4f26, 0x89, 0xff, 0xd7, 0xf5, 0x6f, 0xde, 0x38, 0x60)]
+interface ID3D12StateObjectProperties(ID3D12StateObjectPropertiesVtbl): IUnknown(IUnknownVtbl) {
+    fn GetShaderIdentifier(
+        pExportName: LPCWSTR,
+    ) -> *mut c_void,
+    fn GetShaderStackSize(
+        pExportName: LPCWSTR,
+    ) -> u64,
+    fn GetPipelineStackSize() -> u64,
+    fn SetPipelineStackSize(
+        PipelineStackSizeInBytes: u64,
+    ) -> (),
+}}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct D3D12_GPU_VIRTUAL_ADDRESS_RANGE {
+    pub StartAddress: D3D12_GPU_VIRTUAL_ADDRESS,
+    pub SizeInBytes: u64,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct D3D12_GPU_VIRTUAL_ADDRESS_RANGE_AND_STRIDE {
+    pub StartAddress: D3D12_GPU_VIRTUAL_ADDRESS,
+    pub SizeInBytes: u64,
+    pub StrideInBytes: u64,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct D3D12_DISPATCH_RAYS_DESC {
+    pub RayGenerationShaderRecord: D3D12_GPU_VIRTUAL_ADDRESS_RANGE,
+    pub MissShaderTable: D3D12_GPU_VIRTUAL_ADDRESS_RANGE_AND_STRIDE,
+    pub HitGroupTable: D3D12_GPU_VIRTUAL_ADDRESS_RANGE_AND_STRIDE,
+    pub CallableShaderTable: D3D12_GPU_VIRTUAL_ADDRESS_RANGE_AND_STRIDE,
+    pub Width: u32,
+    pub Height: u32,
+    pub Depth: u32,
+}
+
+// Methods we never call take `c_void` pointers in place of structs we haven't declared.
+// They only need to hold their slot in the vtable.
+
+RIDL! {#[uuid(0x81dadc15, 0x2bad, 0x4392, 0x93, 0xc5, 0x10, 0x13, 0x45, 0xc4, 0xaa, 0x98)]
+interface ID3D12Device3(ID3D12Device3Vtbl): ID3D12Device2(ID3D12Device2Vtbl) {
+    fn OpenExistingHeapFromAddress(
+        pAddress: *const c_void,
+        riid: REFIID,
+        ppvHeap: *mut *mut c_void,
+    ) -> HRESULT,
+    fn OpenExistingHeapFromFileMapping(
+        hFileMapping: HANDLE,
+        riid: REFIID,
+        ppvHeap: *mut *mut c_void,
+    ) -> HRESULT,
+    fn EnqueueMakeResident(
+        Flags: u32,
+        NumObjects: u32,
+        ppObjects: *const *mut ID3D12Pageable,
+        pFenceToSignal: *mut ID3D12Fence,
+        FenceValueToSignal: u64,
+    ) -> HRESULT,
+}}
+
+RIDL! {#[uuid(0xe865df17, 0xa9ee, 0x46f9, 0xa4, 0x63, 0x30, 0x98, 0x31, 0x5a, 0xa2, 0xe5)]
+interface ID3D12Device4(ID3D12Device4Vtbl): ID3D12Device3(ID3D12Device3Vtbl) {
+    fn CreateCommandList1(
+        nodeMask: u32,
+        Type: D3D12_COMMAND_LIST_TYPE,
+        flags: u32,
+        riid: REFIID,
+        ppCommandList: *mut *mut c_void,
+    ) -> HRESULT,
+    fn CreateProtectedResourceSession(
+        pDesc: *const c_void,
+        riid: REFIID,
+        ppSession: *mut *mut c_void,
+    ) -> HRESULT,
+    fn CreateCommittedResource1(
+        pHeapProperties: *const D3D12_HEAP_PROPERTIES,
+        HeapFlags: D3D12_HEAP_FLAGS,
+        pDesc: *const D3D12_RESOURCE_DESC,
+        InitialResourceState: D3D12_RESOURCE_STATES,
+        pOptimizedClearValue: *const D3D12_CLEAR_VALUE,
+        pProtectedSession: *mut IUnknown,
+        riidResource: REFIID,
+        ppvResource: *mut *mut c_void,
+    ) -> HRESULT,
+    fn CreateHeap1(
+        pDesc: *const D3D12_HEAP_DESC,
+        pProtectedSession: *mut IUnknown,
+        riid: REFIID,
+        ppvHeap: *mut *mut c_void,
+    ) -> HRESULT,
+    fn CreateReservedResource1(
+        pDesc: *const D3D12_RESOURCE_DESC,
+        InitialState: D3D12_RESOURCE_STATES,
+        pOptimizedClearValue: *const D3D12_CLEAR_VALUE,
+        pProtectedSession: *mut IUnknown,
+        riid: REFIID,
+        ppvResource: *mut *mut c_void,
+    ) -> HRESULT,
+    // Returns a struct, which the ABI passes back through a hidden first pointer
+    fn GetResourceAllocationInfo1(
+        pResult: *mut D3D12_RESOURCE_ALLOCATION_INFO,
+        visibleMask: u32,
+        numResourceDescs: u32,
+        pResourceDescs: *const D3D12_RESOURCE_DESC,
+        pResourceAllocationInfo1: *mut c_void,
+    ) -> *mut D3D12_RESOURCE_ALLOCATION_INFO,
+}}
+
+RIDL! {#[uuid(0x8b4f173b, 0x2fea, 0x4b80, 0x8f, 0x58, 0x43, 0x07, 0x19, 0x1a, 0xb9, 0x5d)]
+interface ID3D12Device5(ID3D12Device5Vtbl): ID3D12Device4(ID3D12Device4Vtbl) {
+    fn CreateLifetimeTracker(
+        pOwner: *mut IUnknown,
+        riid: REFIID,
+        ppvTracker: *mut *mut c_void,
+    ) -> HRESULT,
+    fn RemoveDevice() -> (),
+    fn EnumerateMetaCommands(
+        pNumMetaCommands: *mut u32,
+        pDescs: *mut c_void,
+    ) -> HRESULT,
+    fn EnumerateMetaCommandParameters(
+        CommandId: REFGUID,
+        Stage: u32,
+        pTotalStructureSizeInBytes: *mut u32,
+        pParameterCount: *mut u32,
+        pParameterDescs: *mut c_void,
+    ) -> HRESULT,
+    fn CreateMetaCommand(
+        CommandId: REFGUID,
+        NodeMask: u32,
+        pCreationParametersData: *const c_void,
+        CreationParametersDataSizeInBytes: SIZE_T,
+        riid: REFIID,
+        ppMetaCommand: *mut *mut c_void,
+    ) -> HRESULT,
+    fn CreateStateObject(
+        pDesc: *const D3D12_STATE_OBJECT_DESC,
+        riid: REFIID,
+        ppStateObject: *mut *mut c_void,
+    ) -> HRESULT,
+    fn GetRaytracingAccelerationStructurePrebuildInfo(
+        pDesc: *const D3D12_BUILD_RAYTRACING_ACCELERATION_STRUCTURE_INPUTS,
+        pInfo: *mut D3D12_RAYTRACING_ACCELERATION_STRUCTURE_PREBUILD_INFO,
+    ) -> (),
+    fn CheckDriverMatchingIdentifier(
+        SerializedDataType: u32,
+        pIdentifierToCheck: *const c_void,
+    ) -> u32,
+}}
+
+RIDL! {#[uuid(0x38c3e585, 0xff17, 0x412c, 0x91, 0x50, 0x4f, 0xc6, 0xf9, 0xd7, 0x2a, 0x28)]
+interface ID3D12GraphicsCommandList2(ID3D12GraphicsCommandList2Vtbl):
+    ID3D12GraphicsCommandList1(ID3D12GraphicsCommandList1Vtbl) {
+    // winapi's ID3D12GraphicsCommandList1 stops one method short, so its last method lives here
+    fn SetViewInstanceMask(
+        Mask: u32,
+    ) -> (),
+    fn WriteBufferImmediate(
+        Count: u32,
+        pParams: *const c_void,
+        pModes: *const u32,
+    ) -> (),
+}}
+
+RIDL! {#[uuid(0x6fda83a7, 0xb84c, 0x4e38, 0x9a, 0xc8, 0xc7, 0xbd, 0x22, 0x01, 0x6b, 0x3d)]
+interface ID3D12GraphicsCommandList3(ID3D12GraphicsCommandList3Vtbl):
+    ID3D12GraphicsCommandList2(ID3D12GraphicsCommandList2Vtbl) {
+    fn SetProtectedResourceSession(
+        pProtectedResourceSession: *mut IUnknown,
+    ) -> (),
+}}
+
+RIDL! {#[uuid(0x8754318e, 0xd3a9, 0x4541, 0x98, 0xcf, 0x64, 0x5b, 0x50, 0xdc, 0x48, 0x74)]
+interface ID3D12GraphicsCommandList4(ID3D12GraphicsCommandList4Vtbl):
+    ID3D12GraphicsCommandList3(ID3D12GraphicsCommandList3Vtbl) {
+    fn BeginRenderPass(
+        NumRenderTargets: u32,
+        pRenderTargets: *const c_void,
+        pDepthStencil: *const c_void,
+        Flags: u32,
+    ) -> (),
+    fn EndRenderPass() -> (),
+    fn InitializeMetaCommand(
+        pMetaCommand: *mut IUnknown,
+        pInitializationParametersData: *const c_void,
+        InitializationParametersDataSizeInBytes: SIZE_T,
+    ) -> (),
+    fn ExecuteMetaCommand(
+        pMetaCommand: *mut IUnknown,
+        pExecutionParametersData: *const c_void,
+        ExecutionParametersDataSizeInBytes: SIZE_T,
+    ) -> (),
+    fn BuildRaytracingAccelerationStructure(
+        pDesc: *const D3D12_BUILD_RAYTRACING_ACCELERATION_STRUCTURE_DESC,
+        NumPostbuildInfoDescs: u32,
+        pPostbuildInfoDescs: *const c_void,
+    ) -> (),
+    fn EmitRaytracingAccelerationStructurePostbuildInfo(
+        pDesc: *const c_void,
+        NumSourceAccelerationStructures: u32,
+        pSourceAccelerationStructureData: *const D3D12_GPU_VIRTUAL_ADDRESS,
+    ) -> (),
+    fn CopyRaytracingAccelerationStructure(
+        DestAccelerationStructureData: D3D12_GPU_VIRTUAL_ADDRESS,
+        SourceAccelerationStructureData: D3D12_GPU_VIRTUAL_ADDRESS,
+        Mode: u32,
+    ) -> (),
+    fn SetPipelineState1(
+        pStateObject: *mut ID3D12StateObject,
+    ) -> (),
+    fn DispatchRays(
+        pDesc: *const D3D12_DISPATCH_RAYS_DESC,
+    ) -> (),
+}}
+
+// Mesh shaders: pipeline stream subobjects for the new stages, and DispatchMesh
+
+pub const D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_AS: D3D12_PIPELINE_STATE_SUBOBJECT_TYPE = 24;
+pub const D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_MS: D3D12_PIPELINE_STATE_SUBOBJECT_TYPE = 25;
+
+RIDL! {#[uuid(0x55050859, 0x4024, 0x474c, 0x87, 0xf5, 0x64, 0x72, 0xea, 0xee, 0x44, 0xea)]
+interface ID3D12GraphicsCommandList5(ID3D12GraphicsCommandList5Vtbl):
+    ID3D12GraphicsCommandList4(ID3D12GraphicsCommandList4Vtbl) {
+    fn RSSetShadingRate(
+        baseShadingRate: u32,
+        combiners: *const u32,
+    ) -> (),
+    fn RSSetShadingRateImage(
+        shadingRateImage: *mut ID3D12Resource,
+    ) -> (),
+}}
+
+RIDL! {#[uuid(0xc3827890, 0xe548, 0x4cfa, 0x96, 0xcf, 0x56, 0x89, 0xa9, 0x37, 0x0f, 0x80)]
+interface ID3D12GraphicsCommandList6(ID3D12GraphicsCommandList6Vtbl):
+    ID3D12GraphicsCommandList5(ID3D12GraphicsCommandList5Vtbl) {
+    fn DispatchMesh(
+        ThreadGroupCountX: u32,
+        ThreadGroupCountY: u32,
+        ThreadGroupCountZ: u32,
+    ) -> (),
+}}
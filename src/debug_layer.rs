@@ -0,0 +1,233 @@
+//! D3D12 debug layer helpers: the info queue and DRED
+
+use crate::check_hr_only;
+use crate::d3d12_ext::*;
+
+use winapi::ctypes::{c_char, wchar_t};
+use winapi::shared::minwindef::TRUE;
+use winapi::shared::winerror::*;
+use winapi::um::d3d12sdklayers::*;
+use winapi::Interface;
+
+use std::ffi::CStr;
+use std::mem::{size_of, zeroed};
+
+pub type InfoQueue = d3d12::WeakPtr<ID3D12InfoQueue>;
+
+/// Messages that are noise for us, and only get in the way of real errors
+const SUPPRESSED_MESSAGES: &[D3D12_MESSAGE_ID] = &[
+    // We clear to whatever --clear-color says, not the optimized clear value
+    D3D12_MESSAGE_ID_CLEARRENDERTARGETVIEW_MISMATCHINGCLEARVALUE,
+    // Passing null to Map/Unmap is the normal way to say "the whole resource"
+    D3D12_MESSAGE_ID_MAP_INVALID_NULLRANGE,
+    D3D12_MESSAGE_ID_UNMAP_INVALID_NULLRANGE,
+];
+
+/// The device's info queue, which only exists when the debug layer is on
+pub fn get_info_queue(device: &d3d12::Device, break_on_error: bool) -> Option<InfoQueue> {
+    let (info_queue, hr) = unsafe { device.cast::<ID3D12InfoQueue>() };
+    if FAILED(hr) {
+        return None;
+    }
+
+    unsafe {
+        let mut deny_ids = SUPPRESSED_MESSAGES.to_vec();
+        let mut filter: D3D12_INFO_QUEUE_FILTER = zeroed();
+        filter.DenyList.NumIDs = deny_ids.len() as u32;
+        filter.DenyList.pIDList = deny_ids.as_mut_ptr();
+        check_hr_only!(info_queue.PushStorageFilter(&mut filter));
+
+        if break_on_error {
+            info_queue.SetBreakOnSeverity(D3D12_MESSAGE_SEVERITY_CORRUPTION, TRUE);
+            info_queue.SetBreakOnSeverity(D3D12_MESSAGE_SEVERITY_ERROR, TRUE);
+        }
+    }
+
+    Some(info_queue)
+}
+
+fn severity_name(severity: D3D12_MESSAGE_SEVERITY) -> &'static str {
+    match severity {
+        D3D12_MESSAGE_SEVERITY_CORRUPTION => "CORRUPTION",
+        D3D12_MESSAGE_SEVERITY_ERROR => "ERROR",
+        D3D12_MESSAGE_SEVERITY_WARNING => "WARNING",
+        D3D12_MESSAGE_SEVERITY_INFO => "INFO",
+        _ => "MESSAGE",
+    }
+}
+
+fn category_name(category: D3D12_MESSAGE_CATEGORY) -> &'static str {
+    match category {
+        D3D12_MESSAGE_CATEGORY_APPLICATION_DEFINED => "APPLICATION_DEFINED",
+        D3D12_MESSAGE_CATEGORY_MISCELLANEOUS => "MISCELLANEOUS",
+        D3D12_MESSAGE_CATEGORY_INITIALIZATION => "INITIALIZATION",
+        D3D12_MESSAGE_CATEGORY_CLEANUP => "CLEANUP",
+        D3D12_MESSAGE_CATEGORY_COMPILATION => "COMPILATION",
+        D3D12_MESSAGE_CATEGORY_STATE_CREATION => "STATE_CREATION",
+        D3D12_MESSAGE_CATEGORY_STATE_SETTING => "STATE_SETTING",
+        D3D12_MESSAGE_CATEGORY_STATE_GETTING => "STATE_GETTING",
+        D3D12_MESSAGE_CATEGORY_RESOURCE_MANIPULATION => "RESOURCE_MANIPULATION",
+        D3D12_MESSAGE_CATEGORY_EXECUTION => "EXECUTION",
+        D3D12_MESSAGE_CATEGORY_SHADER => "SHADER",
+        _ => "UNKNOWN",
+    }
+}
+
+/// Log every message the debug layer has stored since the last call, then clear them
+pub fn drain_info_queue(info_queue: &InfoQueue) {
+    unsafe {
+        for i in 0..info_queue.GetNumStoredMessages() {
+            // The first call tells us how big the message is, the second fills it in
+            let mut byte_length = 0;
+            if FAILED(info_queue.GetMessage(i, std::ptr::null_mut(), &mut byte_length)) {
+                continue;
+            }
+
+            // D3D12_MESSAGE is followed by its description, so allocate with its alignment
+            let count = byte_length.div_ceil(size_of::<u64>());
+            let mut storage = vec![0_u64; count];
+            let message = storage.as_mut_ptr() as *mut D3D12_MESSAGE;
+            if FAILED(info_queue.GetMessage(i, message, &mut byte_length)) {
+                continue;
+            }
+
+            let message = &*message;
+            let description = CStr::from_ptr(message.pDescription).to_string_lossy();
+            let level = match message.Severity {
+                D3D12_MESSAGE_SEVERITY_CORRUPTION | D3D12_MESSAGE_SEVERITY_ERROR => {
+                    log::Level::Error
+                }
+                D3D12_MESSAGE_SEVERITY_WARNING => log::Level::Warn,
+                D3D12_MESSAGE_SEVERITY_INFO => log::Level::Info,
+                _ => log::Level::Debug,
+            };
+
+            log::log!(
+                level,
+                "D3D12 {} [{} #{}]: {}",
+                severity_name(message.Severity),
+                category_name(message.Category),
+                message.ID,
+                description
+            );
+        }
+
+        info_queue.ClearStoredMessages();
+    }
+}
+
+/// Turn on DRED breadcrumbs and page fault reporting. This must happen before the device is created.
+pub fn enable_dred() -> bool {
+    unsafe {
+        let mut settings = d3d12::WeakPtr::<ID3D12DeviceRemovedExtendedDataSettings>::null();
+        let hr = winapi::um::d3d12::D3D12GetDebugInterface(
+            &ID3D12DeviceRemovedExtendedDataSettings::uuidof(),
+            settings.mut_void(),
+        );
+        // Only Windows 10 1903 and newer know about DRED
+        if FAILED(hr) {
+            return false;
+        }
+
+        settings.SetAutoBreadcrumbsEnablement(D3D12_DRED_ENABLEMENT_FORCED_ON);
+        settings.SetPageFaultEnablement(D3D12_DRED_ENABLEMENT_FORCED_ON);
+        settings.destroy();
+    }
+
+    true
+}
+
+/// Debug name of a DRED object, from whichever of the ANSI or wide names it has
+unsafe fn dred_name(name_a: *const c_char, name_w: *const wchar_t) -> String {
+    if !name_a.is_null() {
+        CStr::from_ptr(name_a).to_string_lossy().into_owned()
+    } else if !name_w.is_null() {
+        let len = (0..).take_while(|&i| *name_w.offset(i) != 0).count();
+        String::from_utf16_lossy(std::slice::from_raw_parts(name_w, len))
+    } else {
+        "<unnamed>".to_string()
+    }
+}
+
+fn breadcrumb_op_name(op: D3D12_AUTO_BREADCRUMB_OP) -> &'static str {
+    D3D12_AUTO_BREADCRUMB_OP_NAMES
+        .get(op as usize)
+        .copied()
+        .unwrap_or("UNKNOWN")
+}
+
+/// After a device removal, print what DRED knows: the last GPU operations, and any faulting address
+pub fn report_dred(device: &d3d12::Device) {
+    let (dred, hr) = unsafe { device.cast::<ID3D12DeviceRemovedExtendedData>() };
+    if FAILED(hr) {
+        return;
+    }
+
+    unsafe {
+        let mut breadcrumbs: D3D12_DRED_AUTO_BREADCRUMBS_OUTPUT = zeroed();
+        if SUCCEEDED(dred.GetAutoBreadcrumbsOutput(&mut breadcrumbs)) {
+            log::error!("DRED breadcrumbs:");
+
+            let mut node = breadcrumbs.pHeadAutoBreadcrumbNode;
+            while let Some(this_node) = node.as_ref() {
+                // The GPU finished every op before this value, so the one at it is where it stopped
+                let completed = this_node
+                    .pLastBreadcrumbValue
+                    .as_ref()
+                    .copied()
+                    .unwrap_or(0);
+                log::error!(
+                    "    {} on {}: {} of {} ops completed",
+                    dred_name(
+                        this_node.pCommandListDebugNameA,
+                        this_node.pCommandListDebugNameW
+                    ),
+                    dred_name(
+                        this_node.pCommandQueueDebugNameA,
+                        this_node.pCommandQueueDebugNameW
+                    ),
+                    completed,
+                    this_node.BreadcrumbCount
+                );
+
+                if completed < this_node.BreadcrumbCount {
+                    let history = std::slice::from_raw_parts(
+                        this_node.pCommandHistory,
+                        this_node.BreadcrumbCount as usize,
+                    );
+                    for (i, &op) in history.iter().enumerate() {
+                        let marker = if i as u32 == completed { "-->" } else { "   " };
+                        log::error!("    {} [{:>3}] {}", marker, i, breadcrumb_op_name(op));
+                    }
+                }
+
+                node = this_node.pNext;
+            }
+        }
+
+        let mut page_fault: D3D12_DRED_PAGE_FAULT_OUTPUT = zeroed();
+        if SUCCEEDED(dred.GetPageFaultAllocationOutput(&mut page_fault))
+            && page_fault.PageFaultVA != 0
+        {
+            log::error!("DRED page fault at VA 0x{:016x}", page_fault.PageFaultVA);
+
+            for (label, head) in &[
+                ("Existing", page_fault.pHeadExistingAllocationNode),
+                ("Recently freed", page_fault.pHeadRecentFreedAllocationNode),
+            ] {
+                let mut node = *head;
+                while let Some(this_node) = node.as_ref() {
+                    log::error!(
+                        "    {} allocation: {} (type {})",
+                        label,
+                        dred_name(this_node.ObjectNameA, this_node.ObjectNameW),
+                        this_node.AllocationType
+                    );
+                    node = this_node.pNext;
+                }
+            }
+        }
+
+        dred.destroy();
+    }
+}
@@ -0,0 +1,99 @@
+//! Depth buffer and its DSV heap
+
+use crate::check_hr_err;
+use crate::os_helpers::{self, DxError};
+
+use winapi::shared::dxgiformat::*;
+use winapi::shared::dxgitype::*;
+use winapi::um::d3d12::*;
+use winapi::Interface;
+
+use std::mem::zeroed;
+
+pub const DEPTH_FORMAT: DXGI_FORMAT = DXGI_FORMAT_D32_FLOAT;
+
+/// A depth value of 1.0 is the far plane, so everything drawn passes the first depth test
+pub const DEPTH_CLEAR_VALUE: f32 = 1.0;
+
+/// Create a `width` x `height` depth buffer, plus a one-entry DSV heap holding its view
+pub fn create_depth_buffer(
+    device: &d3d12::Device,
+    width: u32,
+    height: u32,
+) -> Result<(d3d12::Resource, d3d12::DescriptorHeap), DxError> {
+    let heap_props = D3D12_HEAP_PROPERTIES {
+        Type: D3D12_HEAP_TYPE_DEFAULT,
+        CPUPageProperty: D3D12_CPU_PAGE_PROPERTY_UNKNOWN,
+        MemoryPoolPreference: D3D12_MEMORY_POOL_UNKNOWN,
+        CreationNodeMask: 0,
+        VisibleNodeMask: 0,
+    };
+
+    let resource_desc = D3D12_RESOURCE_DESC {
+        Dimension: D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+        Alignment: 0,
+        Width: width as u64,
+        Height: height,
+        DepthOrArraySize: 1,
+        MipLevels: 1,
+        Format: DEPTH_FORMAT,
+        SampleDesc: DXGI_SAMPLE_DESC {
+            Count: 1,
+            Quality: 0,
+        },
+        Layout: D3D12_TEXTURE_LAYOUT_UNKNOWN,
+        Flags: D3D12_RESOURCE_FLAG_ALLOW_DEPTH_STENCIL | D3D12_RESOURCE_FLAG_DENY_SHADER_RESOURCE,
+    };
+
+    // Matching the clear we do each frame lets the driver use its fast-clear path
+    let clear_value = unsafe {
+        let mut clear_value: D3D12_CLEAR_VALUE = zeroed();
+        clear_value.Format = DEPTH_FORMAT;
+        clear_value.u.DepthStencil_mut().Depth = DEPTH_CLEAR_VALUE;
+        clear_value.u.DepthStencil_mut().Stencil = 0;
+
+        clear_value
+    };
+
+    let depth_buffer = check_hr_err! {
+        unsafe {
+            let mut depth_buffer = d3d12::Resource::null();
+            let hr = device.CreateCommittedResource(
+                &heap_props,
+                D3D12_HEAP_FLAG_NONE,
+                &resource_desc,
+                D3D12_RESOURCE_STATE_DEPTH_WRITE,
+                &clear_value,
+                &ID3D12Resource::uuidof(),
+                depth_buffer.mut_void(),
+            );
+
+            (depth_buffer, hr)
+        }
+    };
+
+    os_helpers::set_name(&depth_buffer, "Depth Buffer");
+
+    let dsv_heap = check_hr_err!(device.create_descriptor_heap(
+        1,
+        d3d12::DescriptorHeapType::Dsv,
+        d3d12::DescriptorHeapFlags::empty(),
+        0,
+    ));
+    os_helpers::set_name(&dsv_heap, "DSV Heap");
+
+    unsafe {
+        let mut dsv_desc: D3D12_DEPTH_STENCIL_VIEW_DESC = zeroed();
+        dsv_desc.Format = DEPTH_FORMAT;
+        dsv_desc.ViewDimension = D3D12_DSV_DIMENSION_TEXTURE2D;
+        dsv_desc.Flags = D3D12_DSV_FLAG_NONE;
+
+        device.CreateDepthStencilView(
+            depth_buffer.as_mut_ptr(),
+            &dsv_desc,
+            dsv_heap.start_cpu_descriptor(),
+        );
+    }
+
+    Ok((depth_buffer, dsv_heap))
+}
@@ -0,0 +1,168 @@
+//! The windows `--windows` opens beside the main one, each with its own swapchain on the shared device and queue
+//!
+//! They draw the same scene as the main window, through their own back buffers, depth buffer,
+//! frame contexts, and constants, so each one resizes and presents on its own.
+
+use crate::check_hr_err;
+use crate::check_hr_only;
+use crate::constant_buffers::ConstantBufferRing;
+use crate::depth;
+use crate::os_helpers::{self, DxError};
+use crate::render_targets::RenderTargets;
+use crate::scene::Scene;
+use crate::sync::{FrameContext, QueueFence};
+
+use winapi::shared::dxgiformat::DXGI_FORMAT;
+
+use winit::window::Window;
+
+pub struct ExtraWindow {
+    pub window: Window,
+    swapchain: d3d12::SwapChain3,
+    render_targets: RenderTargets,
+    depth_buffer: d3d12::Resource,
+    dsv_heap: d3d12::DescriptorHeap,
+    /// One per back buffer, like the main window's
+    frames: Vec<FrameContext>,
+    /// The scene writes this window's constants here, so they can't clobber another window's frame in flight
+    constants: ConstantBufferRing,
+    buffer_count: u32,
+    format: DXGI_FORMAT,
+    rtv_format: DXGI_FORMAT,
+    swapchain_flags: u32,
+    width: u32,
+    height: u32,
+    /// Nothing renders into this window while it's minimized
+    pub minimized: bool,
+}
+
+impl ExtraWindow {
+    /// Render into `window` through `swapchain`, which was created from `desc` and has `buffer_count` buffers
+    pub fn new(
+        device: &d3d12::Device,
+        window: Window,
+        swapchain: d3d12::SwapChain3,
+        desc: &d3d12::SwapchainDesc,
+        buffer_count: u32,
+        rtv_format: DXGI_FORMAT,
+        constants_per_frame: u64,
+    ) -> Result<Self, DxError> {
+        let render_targets = RenderTargets::new(device, &swapchain, buffer_count, rtv_format)?;
+        let (depth_buffer, dsv_heap) = depth::create_depth_buffer(device, desc.width, desc.height)?;
+        let frames = (0..buffer_count)
+            .map(|i| FrameContext::new(device, &format!("Extra Window Frame {} Commands", i)))
+            .collect::<Result<Vec<_>, DxError>>()?;
+        let constants = ConstantBufferRing::new(device, buffer_count, constants_per_frame)?;
+
+        Ok(ExtraWindow {
+            window,
+            swapchain,
+            render_targets,
+            depth_buffer,
+            dsv_heap,
+            frames,
+            constants,
+            buffer_count,
+            format: desc.format,
+            rtv_format,
+            swapchain_flags: desc.flags,
+            width: desc.width,
+            height: desc.height,
+            minimized: false,
+        })
+    }
+
+    /// Record the scene into the next back buffer, submit it to `cmd_queue`, and present with
+    /// `sync_interval` and `present_flags`
+    pub fn render(
+        &mut self,
+        device: &d3d12::Device,
+        scene: &mut Scene,
+        cmd_queue: &d3d12::CommandQueue,
+        fence: &mut QueueFence,
+        sync_interval: u32,
+        present_flags: u32,
+    ) {
+        let index = self.swapchain.get_current_back_buffer_index();
+        let frame = &mut self.frames[index as usize];
+        frame.begin(fence);
+        self.constants.begin_frame(index);
+
+        scene.record_view(
+            &mut self.constants,
+            &frame.cmd_list,
+            self.render_targets.buffer(index),
+            self.render_targets.rtv_handle(index),
+            self.dsv_heap.start_cpu_descriptor(),
+            self.width,
+            self.height,
+        );
+        frame.submit(cmd_queue, fence);
+
+        let hr = self
+            .swapchain
+            .as_swapchain0()
+            .present(sync_interval, present_flags);
+        os_helpers::report_device_removed(device, hr);
+        check_hr_only!(hr);
+    }
+
+    /// Resize the swapchain, and everything sized to it, to match the window.
+    /// Minimizing reports 0x0, which only pauses rendering until the window comes back.
+    pub fn resize(
+        &mut self,
+        device: &d3d12::Device,
+        fence: &QueueFence,
+        width: u32,
+        height: u32,
+    ) -> Result<(), DxError> {
+        self.minimized = width == 0 || height == 0;
+        if self.minimized || (width, height) == (self.width, self.height) {
+            return Ok(());
+        }
+
+        // The swapchain can't resize while anything still references its buffers
+        fence.wait_for_last_submission();
+        self.render_targets.destroy();
+        unsafe {
+            self.depth_buffer.destroy();
+            self.dsv_heap.destroy();
+        }
+
+        let hr = unsafe {
+            self.swapchain.ResizeBuffers(
+                self.buffer_count,
+                width,
+                height,
+                self.format,
+                self.swapchain_flags,
+            )
+        };
+        os_helpers::report_device_removed(device, hr);
+        check_hr_err!(((), hr));
+        self.width = width;
+        self.height = height;
+
+        self.render_targets =
+            RenderTargets::new(device, &self.swapchain, self.buffer_count, self.rtv_format)?;
+        let (depth_buffer, dsv_heap) = depth::create_depth_buffer(device, width, height)?;
+        self.depth_buffer = depth_buffer;
+        self.dsv_heap = dsv_heap;
+
+        Ok(())
+    }
+
+    /// Release the swapchain and everything rendering into it. Nothing in flight can still be using them.
+    pub fn destroy(&mut self) {
+        for frame in &mut self.frames {
+            frame.destroy();
+        }
+        self.constants.destroy();
+        self.render_targets.destroy();
+        unsafe {
+            self.depth_buffer.destroy();
+            self.dsv_heap.destroy();
+            self.swapchain.destroy();
+        }
+    }
+}
@@ -0,0 +1,42 @@
+//! A soft frame rate cap, for when vsync is off but running flat out is too much
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// `thread::sleep` can overshoot by a scheduler tick, so the last stretch of each wait spins instead
+const SPIN_TIME: Duration = Duration::from_millis(2);
+
+pub struct FrameLimiter {
+    interval: Duration,
+    next_frame: Instant,
+}
+
+impl FrameLimiter {
+    /// Limit frames to at most `max_fps` per second. `max_fps` must be at least 1.
+    pub fn new(max_fps: u32) -> Self {
+        FrameLimiter {
+            interval: Duration::from_secs(1) / max_fps,
+            next_frame: Instant::now(),
+        }
+    }
+
+    /// Call once per frame, after present. Blocks until the next frame is due.
+    pub fn wait(&mut self) {
+        let now = Instant::now();
+        if now >= self.next_frame {
+            // We're already late. Start counting from now instead of trying to catch up with short frames.
+            self.next_frame = now + self.interval;
+            return;
+        }
+
+        let remaining = self.next_frame - now;
+        if remaining > SPIN_TIME {
+            thread::sleep(remaining - SPIN_TIME);
+        }
+        while Instant::now() < self.next_frame {
+            std::hint::spin_loop();
+        }
+
+        self.next_frame += self.interval;
+    }
+}
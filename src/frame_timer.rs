@@ -0,0 +1,52 @@
+//! CPU frame timing, for the FPS readout in the window title
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How many recent frames the average covers
+const ROLLING_FRAMES: usize = 60;
+
+/// How often the readout changes, so it's slow enough to read
+const UPDATE_INTERVAL: Duration = Duration::from_millis(500);
+
+pub struct FrameTimer {
+    last_frame: Instant,
+    last_update: Instant,
+    frame_times: VecDeque<Duration>,
+}
+
+impl FrameTimer {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        FrameTimer {
+            last_frame: now,
+            last_update: now,
+            frame_times: VecDeque::with_capacity(ROLLING_FRAMES),
+        }
+    }
+
+    /// Mark the end of a frame. Every `UPDATE_INTERVAL`, this returns the average frame time.
+    pub fn tick(&mut self) -> Option<Duration> {
+        let now = Instant::now();
+        if self.frame_times.len() == ROLLING_FRAMES {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(now - self.last_frame);
+        self.last_frame = now;
+
+        if now - self.last_update < UPDATE_INTERVAL {
+            return None;
+        }
+        self.last_update = now;
+
+        let total: Duration = self.frame_times.iter().sum();
+        Some(total / self.frame_times.len() as u32)
+    }
+}
+
+/// e.g. "143 fps (6.9 ms)"
+pub fn fps_text(frame_time: Duration) -> String {
+    let seconds = frame_time.as_secs_f64();
+    let fps = if seconds > 0.0 { 1.0 / seconds } else { 0.0 };
+    format!("{:.0} fps ({:.1} ms)", fps, seconds * 1000.0)
+}
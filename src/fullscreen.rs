@@ -0,0 +1,39 @@
+//! Borderless fullscreen, toggled with Alt+Enter
+//!
+//! Flip-model swapchains present just as fast to a borderless window covering the monitor,
+//! so we never ask DXGI for exclusive fullscreen.
+
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::window::{Fullscreen, Window};
+
+#[derive(Default)]
+pub struct FullscreenToggle {
+    /// Where the window was before going fullscreen. `None` while windowed.
+    windowed_rect: Option<(PhysicalPosition<i32>, PhysicalSize<u32>)>,
+}
+
+impl FullscreenToggle {
+    /// Where the window goes back to when fullscreen ends. `None` while windowed.
+    pub fn windowed_rect(&self) -> Option<(PhysicalPosition<i32>, PhysicalSize<u32>)> {
+        self.windowed_rect
+    }
+
+    /// Cover the monitor the window is on, or put the window back where it was.
+    /// The swapchain catches up through the `Resized` event that follows.
+    pub fn toggle(&mut self, window: &Window) {
+        match self.windowed_rect.take() {
+            Some((position, size)) => {
+                window.set_fullscreen(None);
+                window.set_outer_position(position);
+                window.set_inner_size(size);
+            }
+            None => {
+                let position = window
+                    .outer_position()
+                    .unwrap_or_else(|_| PhysicalPosition::new(0, 0));
+                self.windowed_rect = Some((position, window.inner_size()));
+                window.set_fullscreen(Some(Fullscreen::Borderless(window.current_monitor())));
+            }
+        }
+    }
+}
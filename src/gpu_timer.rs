@@ -0,0 +1,133 @@
+//! GPU frame timing, through timestamp queries resolved into a readback buffer
+
+use crate::buffers;
+use crate::check_hr_err;
+use crate::os_helpers::{self, DxError};
+
+use winapi::shared::winerror::*;
+use winapi::um::d3d12::*;
+
+use std::mem::size_of;
+use std::ops::Range;
+
+/// A begin and an end timestamp for every frame in flight
+pub struct GpuTimer {
+    query_heap: d3d12::QueryHeap,
+    readback: d3d12::Resource,
+    /// Timestamp ticks per second on `cmd_queue`
+    frequency: u64,
+    /// Whether each frame has resolved timestamps that haven't been read yet
+    pending: Vec<bool>,
+}
+
+const QUERIES_PER_FRAME: u32 = 2;
+const BYTES_PER_FRAME: usize = QUERIES_PER_FRAME as usize * size_of::<u64>();
+
+impl GpuTimer {
+    pub fn new(
+        device: &d3d12::Device,
+        cmd_queue: &d3d12::CommandQueue,
+        frame_count: u32,
+    ) -> Result<Self, DxError> {
+        let frequency = check_hr_err! {
+            unsafe {
+                let mut frequency = 0u64;
+                let hr = cmd_queue.GetTimestampFrequency(&mut frequency);
+
+                (frequency, hr)
+            }
+        };
+
+        let query_heap = check_hr_err!(device.create_query_heap(
+            d3d12::QueryHeapType::Timestamp,
+            QUERIES_PER_FRAME * frame_count,
+            0,
+        ));
+        os_helpers::set_name(&query_heap, "Timestamp Queries");
+
+        // Readback heap resources must start, and stay, in COPY_DEST
+        let readback = buffers::create_buffer(
+            device,
+            (BYTES_PER_FRAME * frame_count as usize) as u64,
+            D3D12_HEAP_TYPE_READBACK,
+            D3D12_RESOURCE_FLAG_NONE,
+            D3D12_RESOURCE_STATE_COPY_DEST,
+        )?;
+        os_helpers::set_name(&readback, "Timestamp Readback");
+
+        Ok(GpuTimer {
+            query_heap,
+            readback,
+            frequency,
+            pending: vec![false; frame_count as usize],
+        })
+    }
+
+    fn end_query(&self, cmd_list: &d3d12::GraphicsCommandList, index: u32) {
+        unsafe {
+            cmd_list.EndQuery(
+                self.query_heap.as_mut_ptr(),
+                D3D12_QUERY_TYPE_TIMESTAMP,
+                index,
+            );
+        }
+    }
+
+    /// Record the timestamp before frame `frame_index`'s GPU work
+    pub fn begin(&self, cmd_list: &d3d12::GraphicsCommandList, frame_index: u32) {
+        self.end_query(cmd_list, frame_index * QUERIES_PER_FRAME);
+    }
+
+    /// Record the timestamp after the GPU work, and copy both into the readback buffer
+    pub fn end(&mut self, cmd_list: &d3d12::GraphicsCommandList, frame_index: u32) {
+        let first = frame_index * QUERIES_PER_FRAME;
+        self.end_query(cmd_list, first + 1);
+        unsafe {
+            cmd_list.ResolveQueryData(
+                self.query_heap.as_mut_ptr(),
+                D3D12_QUERY_TYPE_TIMESTAMP,
+                first,
+                QUERIES_PER_FRAME,
+                self.readback.as_mut_ptr(),
+                (frame_index as usize * BYTES_PER_FRAME) as u64,
+            );
+        }
+        self.pending[frame_index as usize] = true;
+    }
+
+    /// Frame `frame_index`'s begin and end timestamps, once per resolved frame.
+    /// Only call this after `FrameContext::begin` for this frame, so the resolve has landed.
+    pub fn read_timestamps(&mut self, frame_index: u32) -> Option<(u64, u64)> {
+        if !std::mem::replace(&mut self.pending[frame_index as usize], false) {
+            return None;
+        }
+
+        let start = frame_index as usize * BYTES_PER_FRAME;
+        let range: Range<usize> = start..start + BYTES_PER_FRAME;
+        let (mapped, hr) = self.readback.map(0, Some(range));
+        if FAILED(hr) {
+            os_helpers::log_hr(hr, &format!("{}:{}", file!(), line!()), "readback.map");
+            return None;
+        }
+        let (begin, end) = unsafe {
+            let timestamps = (mapped as *const u8).add(start) as *const u64;
+            (*timestamps, *timestamps.add(1))
+        };
+        // We wrote nothing, so the written range is empty
+        self.readback.unmap(0, Some(0..0));
+
+        Some((begin, end))
+    }
+
+    /// Milliseconds between two timestamps from `read_timestamps`
+    pub fn elapsed_ms(&self, begin: u64, end: u64) -> f64 {
+        end.saturating_sub(begin) as f64 * 1000.0 / self.frequency as f64
+    }
+
+    pub fn destroy(&mut self) {
+        unsafe {
+            self.readback.destroy();
+            self.query_heap.destroy();
+        }
+    }
+}
@@ -0,0 +1,120 @@
+//! HDR swapchain formats and color spaces
+
+use crate::adapters;
+use crate::check_hr_only;
+use crate::status;
+
+use winapi::shared::dxgi::IDXGIOutput;
+use winapi::shared::dxgi1_4::*;
+use winapi::shared::dxgi1_6::*;
+use winapi::shared::dxgiformat::*;
+use winapi::shared::dxgitype::*;
+use winapi::shared::winerror::*;
+
+use std::mem::zeroed;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HdrMode {
+    /// 10-bit PQ with Rec. 2020 primaries
+    Hdr10,
+    /// Linear FP16 with Rec. 709 primaries
+    ScRgb,
+}
+
+impl HdrMode {
+    pub fn format(self) -> DXGI_FORMAT {
+        match self {
+            HdrMode::Hdr10 => DXGI_FORMAT_R10G10B10A2_UNORM,
+            HdrMode::ScRgb => DXGI_FORMAT_R16G16B16A16_FLOAT,
+        }
+    }
+
+    pub fn color_space(self) -> DXGI_COLOR_SPACE_TYPE {
+        match self {
+            HdrMode::Hdr10 => DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020,
+            HdrMode::ScRgb => DXGI_COLOR_SPACE_RGB_FULL_G10_NONE_P709,
+        }
+    }
+}
+
+/// Describe the output the swapchain is mostly on, including its HDR capabilities
+pub fn containing_output_desc(swapchain: &d3d12::SwapChain3) -> Option<DXGI_OUTPUT_DESC1> {
+    unsafe {
+        let mut output = d3d12::WeakPtr::<IDXGIOutput>::null();
+        let hr = swapchain.GetContainingOutput(output.mut_void() as *mut *mut _);
+        if FAILED(hr) {
+            return None;
+        }
+
+        let desc = output_desc(output);
+        output.destroy();
+        desc
+    }
+}
+
+/// Describe `output`, including its HDR capabilities
+pub fn output_desc(output: d3d12::WeakPtr<IDXGIOutput>) -> Option<DXGI_OUTPUT_DESC1> {
+    unsafe {
+        let (output6, hr) = output.cast::<IDXGIOutput6>();
+        // IDXGIOutput6 needs Windows 10 1703 or newer
+        if FAILED(hr) {
+            return None;
+        }
+
+        let mut desc: DXGI_OUTPUT_DESC1 = zeroed();
+        let hr = output6.GetDesc1(&mut desc);
+        output6.destroy();
+
+        if SUCCEEDED(hr) {
+            Some(desc)
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether any display on any adapter has HDR turned on
+pub fn any_output_supports_hdr(factory: &d3d12::Factory4) -> bool {
+    let mut found = false;
+    for adapter in adapters::adapters(factory) {
+        for output in adapters::outputs(&adapter) {
+            found |= matches!(output_desc(output), Some(desc) if output_supports_hdr(&desc));
+            unsafe {
+                output.destroy();
+            }
+        }
+    }
+    found
+}
+
+/// Windows only reports the PQ color space when "Use HDR" is turned on for the display
+pub fn output_supports_hdr(desc: &DXGI_OUTPUT_DESC1) -> bool {
+    desc.ColorSpace == DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020
+}
+
+pub fn display_hdr_caps(desc: &DXGI_OUTPUT_DESC1) {
+    status!("Output HDR Capabilities");
+    status!("    HDR Enabled:     {:>10}", output_supports_hdr(desc));
+    status!("    Bits Per Color:  {:>10}", desc.BitsPerColor);
+    status!("    Min Luminance:   {:>10.4} nits", desc.MinLuminance);
+    status!("    Max Luminance:   {:>10.1} nits", desc.MaxLuminance);
+    status!(
+        "    Max Full Frame:  {:>10.1} nits",
+        desc.MaxFullFrameLuminance
+    );
+    status!();
+}
+
+/// Switch the swapchain to `mode`'s color space, if it can present in it
+pub fn set_color_space(swapchain: &d3d12::SwapChain3, mode: HdrMode) -> bool {
+    unsafe {
+        let mut support = 0;
+        let hr = swapchain.CheckColorSpaceSupport(mode.color_space(), &mut support);
+        if FAILED(hr) || (support & DXGI_SWAP_CHAIN_COLOR_SPACE_SUPPORT_FLAG_PRESENT) == 0 {
+            return false;
+        }
+
+        let hr = check_hr_only!(swapchain.SetColorSpace1(mode.color_space()));
+        SUCCEEDED(hr)
+    }
+}
@@ -2,45 +2,653 @@ use raw_window_handle::HasRawWindowHandle;
 use winapi::shared::dxgi::*;
 use winapi::shared::dxgiformat::*;
 use winapi::shared::dxgitype::*;
+use winapi::shared::minwindef::TRUE;
+use winapi::shared::ntdef::LUID;
+use winapi::shared::windef::HWND;
 use winapi::shared::winerror::*;
+use winapi::um::d3dcommon::*;
 use winapi::Interface;
 
 use structopt::StructOpt;
 use winit::{
-    event::{Event, WindowEvent},
+    dpi::PhysicalSize,
+    event::{
+        ElementState, Event, KeyboardInput, ModifiersState, MouseButton, MouseScrollDelta,
+        VirtualKeyCode, WindowEvent,
+    },
     event_loop::{ControlFlow, EventLoop},
     window::WindowBuilder,
 };
 
+mod accel;
+mod adapters;
+mod async_compute;
+mod buffers;
+mod camera;
+mod caps;
+mod chrome_trace;
+mod commands;
+mod config;
+mod constant_buffers;
+mod d3d12_ext;
+mod debug_layer;
+mod depth;
+mod extra_window;
+mod frame_limiter;
+mod frame_timer;
+mod fullscreen;
+mod gpu_timer;
+mod hdr;
+mod memory_budget;
+mod mesh;
+mod mesh_shader;
+mod msaa;
 mod os_helpers;
-use os_helpers::hr_string;
+mod pix;
+mod raytrace;
+mod render_targets;
+// The triangle doesn't use every kind of root parameter yet
+#[allow(dead_code)]
+mod root_sig;
+mod rt_pipeline;
+mod scene;
+mod screenshot;
+mod shader_table;
+mod shader_watch;
+mod shaders;
+mod sync;
+mod triangle;
+mod window_state;
+use adapters::AdapterInfo;
+use os_helpers::DxError;
 
+use std::io::Write;
 use std::mem::zeroed;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::time::Instant;
 
 #[allow(non_upper_case_globals)]
-const MiB: usize = 1024 * 1024;
+pub const MiB: usize = 1024 * 1024;
+
+const WINDOW_TITLE: &str = "☀ Itsy Bitsy DXR ☀";
+
+/// Window and swapchain size without --width and --height, before there's a saved window size
+const DEFAULT_WIDTH: u32 = 1024;
+const DEFAULT_HEIGHT: u32 = 1024;
+
+/// Room for every constant buffer a single frame writes
+const CONSTANTS_PER_FRAME: u64 = 64 * 1024;
+
+const PRESENT_EVENT_COLOR: u64 = pix::pix_color(0x40, 0xc0, 0x40);
+const RESIZE_MARKER_COLOR: u64 = pix::pix_color(0xff, 0xc0, 0x00);
+
+#[derive(Copy, Clone)]
+enum FeatureLevelRequest {
+    /// The highest level the adapter supports
+    Auto,
+    /// Exactly this level, failing if the adapter can't do it
+    Exact(D3D_FEATURE_LEVEL),
+}
+
+fn parse_feature_level(text: &str) -> Result<FeatureLevelRequest, String> {
+    use FeatureLevelRequest::*;
 
-fn parse_feature_level(text: &str) -> Result<d3d12::FeatureLevel, String> {
     let text = text.trim();
     match text {
-        "9_1" => Ok(d3d12::FeatureLevel::L9_1),
-        "9_2" => Ok(d3d12::FeatureLevel::L9_2),
-        "9_3" => Ok(d3d12::FeatureLevel::L9_3),
-        "10_0" => Ok(d3d12::FeatureLevel::L10_0),
-        "10_1" => Ok(d3d12::FeatureLevel::L10_1),
-        "11_0" => Ok(d3d12::FeatureLevel::L11_0),
-        "11_1" => Ok(d3d12::FeatureLevel::L11_1),
-        "12_0" => Ok(d3d12::FeatureLevel::L12_0),
-        "12_1" => Ok(d3d12::FeatureLevel::L12_1),
-        // TODO: Update this for "DX12 Ultimate"
-        // "12_2" => Ok(d3d12::FeatureLevel::L12_2),
+        "auto" => Ok(Auto),
+        "9_1" => Ok(Exact(D3D_FEATURE_LEVEL_9_1)),
+        "9_2" => Ok(Exact(D3D_FEATURE_LEVEL_9_2)),
+        "9_3" => Ok(Exact(D3D_FEATURE_LEVEL_9_3)),
+        "10_0" => Ok(Exact(D3D_FEATURE_LEVEL_10_0)),
+        "10_1" => Ok(Exact(D3D_FEATURE_LEVEL_10_1)),
+        "11_0" => Ok(Exact(D3D_FEATURE_LEVEL_11_0)),
+        "11_1" => Ok(Exact(D3D_FEATURE_LEVEL_11_1)),
+        "12_0" => Ok(Exact(D3D_FEATURE_LEVEL_12_0)),
+        "12_1" => Ok(Exact(D3D_FEATURE_LEVEL_12_1)),
+        // "DX12 Ultimate". The d3d12 crate doesn't have this one yet.
+        "12_2" => Ok(Exact(d3d12_ext::D3D_FEATURE_LEVEL_12_2)),
         _ => Err("See MSDN for valid levels: https://docs.microsoft.com/en-us/windows/win32/direct3d12/hardware-feature-levels".to_string()),
     }
 }
 
+fn parse_luid(text: &str) -> Result<LUID, String> {
+    let parse_part = |part: &str| {
+        let part = part.trim();
+        let part = part.trim_start_matches("0x").trim_start_matches("0X");
+        u32::from_str_radix(part, 16).map_err(|err| format!("\"{}\": {}", part, err))
+    };
+
+    let mut parts = text.split(':');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(high), Some(low), None) => Ok(LUID {
+            HighPart: parse_part(high)? as i32,
+            LowPart: parse_part(low)?,
+        }),
+        _ => {
+            Err("Expected a LUID formatted as \"high:low\", e.g. \"00000000:0000c3a1\"".to_string())
+        }
+    }
+}
+
+fn parse_nonzero(text: &str) -> Result<u32, String> {
+    match text.parse::<u32>() {
+        Ok(0) => Err("Must be at least 1".to_string()),
+        Ok(value) => Ok(value),
+        Err(err) => Err(format!("\"{}\": {}", text, err)),
+    }
+}
+
+fn parse_color(text: &str) -> Result<[f32; 4], String> {
+    let hex = text.trim_start_matches('#');
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!(
+            "\"{}\" is not a color. Expected \"rrggbb\" hex, e.g. \"1a3366\"",
+            text
+        ));
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap() as f32 / 255.;
+    Ok([channel(0), channel(2), channel(4), 1.0])
+}
+
+fn parse_format(text: &str) -> Result<DXGI_FORMAT, String> {
+    match text {
+        "rgba8" => Ok(DXGI_FORMAT_R8G8B8A8_UNORM),
+        "bgra8" => Ok(DXGI_FORMAT_B8G8R8A8_UNORM),
+        "rgb10a2" => Ok(DXGI_FORMAT_R10G10B10A2_UNORM),
+        "rgba16f" => Ok(DXGI_FORMAT_R16G16B16A16_FLOAT),
+        _ => Err(format!("Unrecognized swapchain format: {}", text)),
+    }
+}
+
+fn parse_hdr_mode(text: &str) -> Result<hdr::HdrMode, String> {
+    match text {
+        "hdr10" => Ok(hdr::HdrMode::Hdr10),
+        "scrgb" => Ok(hdr::HdrMode::ScRgb),
+        _ => Err(format!("Unrecognized HDR mode: {}", text)),
+    }
+}
+
+fn parse_swap_effect(text: &str) -> Result<d3d12::SwapEffect, String> {
+    match text {
+        "flip-discard" => Ok(d3d12::SwapEffect::FlipDiscard),
+        "flip-sequential" => Ok(d3d12::SwapEffect::FlipSequential),
+        _ => Err(format!("Unrecognized swap effect: {}", text)),
+    }
+}
+
+/// GPU-based validation lives on ID3D12Debug1, which needs the Windows 10 SDK layers
+fn enable_gpu_validation(debug: &d3d12::Debug) {
+    use winapi::um::d3d12sdklayers::ID3D12Debug1;
+
+    let debug1 = unsafe { check_hr!(debug.cast::<ID3D12Debug1>()) };
+    if debug1.is_null() {
+        status!("⚠️ GPU-based validation is not available");
+        return;
+    }
+
+    unsafe {
+        debug1.SetEnableGPUBasedValidation(TRUE);
+        debug1.destroy();
+    }
+    status!("GPU-based validation enabled");
+}
+
+/// Pick the level to create a device on `adapter` with, probing the adapter for `auto`
+fn resolve_feature_level<I: Interface>(
+    request: FeatureLevelRequest,
+    adapter: &d3d12::WeakPtr<I>,
+) -> D3D_FEATURE_LEVEL {
+    match request {
+        FeatureLevelRequest::Exact(level) => level,
+        FeatureLevelRequest::Auto => {
+            match caps::with_probe_device(adapter, caps::query_max_feature_level).flatten() {
+                Some(level) => {
+                    status!(
+                        "Feature level auto: using {}",
+                        caps::feature_level_name(level)
+                    );
+                    level
+                }
+                None => {
+                    // This fails the same way again when we create the real device, with a better error
+                    status!("⚠️ Couldn't query this adapter's feature levels. Trying 11_0.");
+                    D3D_FEATURE_LEVEL_11_0
+                }
+            }
+        }
+    }
+}
+
+/// Undo the sRGB transfer function on the color channels, leaving alpha alone
+fn srgb_to_linear(color: [f32; 4]) -> [f32; 4] {
+    let channel = |c: f32| {
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    [
+        channel(color[0]),
+        channel(color[1]),
+        channel(color[2]),
+        color[3],
+    ]
+}
+
+/// `--hash-frame` steps the animation as if every frame took this long, so it doesn't depend on timing
+const HASH_FRAME_RATE: f32 = 60.0;
+
+/// Print a frame's hash where a test harness can pick it up, or exit if we couldn't read it
+fn print_frame_hash(frame: u32, readback: screenshot::Screenshot) {
+    match readback.hash() {
+        Ok(hash) => println!("Frame {} CRC32: {:08x}", frame, hash),
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Bail out from inside the event loop, which never returns to `main`
+fn exit_with_error(err: DxError) -> ! {
+    eprintln!("Error: {}", err);
+    std::process::exit(1);
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum RenderMode {
+    Raster,
+    Raytrace,
+    Mesh,
+}
+
+fn parse_mode(text: &str) -> Result<RenderMode, String> {
+    match text {
+        "raster" => Ok(RenderMode::Raster),
+        "raytrace" => Ok(RenderMode::Raytrace),
+        "mesh" => Ok(RenderMode::Mesh),
+        _ => Err(format!("Unrecognized render mode: {}", text)),
+    }
+}
+
+fn parse_fill_mode(text: &str) -> Result<triangle::FillMode, String> {
+    match text {
+        "solid" => Ok(triangle::FillMode::Solid),
+        "wireframe" => Ok(triangle::FillMode::Wireframe),
+        _ => Err(format!("Unrecognized fill mode: {}", text)),
+    }
+}
+
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+fn parse_output_format(text: &str) -> Result<OutputFormat, String> {
+    match text.trim() {
+        "text" => Ok(OutputFormat::Text),
+        "json" => Ok(OutputFormat::Json),
+        _ => Err("Expected \"text\" or \"json\"".to_string()),
+    }
+}
+
+fn window_hwnd(window: &winit::window::Window) -> HWND {
+    match window.raw_window_handle() {
+        raw_window_handle::RawWindowHandle::Windows(handle) => handle.hwnd as _,
+        raw_handle => panic!("Unsupported window handle + OS: {:?}", raw_handle),
+    }
+}
+
+fn create_direct_queue(device: &d3d12::Device) -> Result<d3d12::CommandQueue, DxError> {
+    let cmd_queue = check_hr_err!(device.create_command_queue(
+        d3d12::CmdListType::Direct,
+        d3d12::Priority::Normal,
+        d3d12::CommandQueueFlags::empty(),
+        0,
+    ));
+    os_helpers::set_name(&cmd_queue, "Direct Queue");
+
+    Ok(cmd_queue)
+}
+
+/// The flip-model rules `desc` and `hwnd` break, to explain a failed CreateSwapChainForHwnd.
+/// DXGI only says E_INVALIDARG, and the debug layer message is easy to miss.
+fn swapchain_problems(desc: &d3d12::SwapchainDesc, hwnd: HWND) -> Vec<String> {
+    let mut problems = vec![];
+
+    if !matches!(
+        desc.swap_effect,
+        d3d12::SwapEffect::FlipDiscard | d3d12::SwapEffect::FlipSequential
+    ) {
+        problems.push(format!(
+            "{:?} isn't a flip-model swap effect, which D3D12 requires",
+            desc.swap_effect
+        ));
+    }
+    // sRGB is only allowed on the views, not the swapchain itself
+    if !matches!(
+        desc.format,
+        DXGI_FORMAT_R8G8B8A8_UNORM
+            | DXGI_FORMAT_B8G8R8A8_UNORM
+            | DXGI_FORMAT_R10G10B10A2_UNORM
+            | DXGI_FORMAT_R16G16B16A16_FLOAT
+    ) {
+        problems.push(format!(
+            "DXGI_FORMAT {} can't back a flip-model swapchain. Use rgba8, bgra8, rgb10a2, or rgba16f.",
+            desc.format
+        ));
+    }
+    if desc.buffer_count < 2 || desc.buffer_count > DXGI_MAX_SWAP_CHAIN_BUFFERS {
+        problems.push(format!(
+            "Flip-model swapchains need 2 to {} buffers, not {}",
+            DXGI_MAX_SWAP_CHAIN_BUFFERS, desc.buffer_count
+        ));
+    }
+    if desc.sample.count != 1 || desc.sample.quality != 0 {
+        problems.push(format!(
+            "Flip-model swapchains can't be multisampled, but this one asks for {}x",
+            desc.sample.count
+        ));
+    }
+    let max_size = winapi::um::d3d12::D3D12_REQ_TEXTURE2D_U_OR_V_DIMENSION;
+    if desc.width > max_size || desc.height > max_size {
+        problems.push(format!(
+            "{}x{} is bigger than the largest texture, {}x{}",
+            desc.width, desc.height, max_size, max_size
+        ));
+    }
+    if matches!(
+        desc.alpha_mode,
+        d3d12::AlphaMode::Premultiplied | d3d12::AlphaMode::Straight
+    ) {
+        problems.push(format!(
+            "{:?} alpha only works for composition swapchains, not ones on a window",
+            desc.alpha_mode
+        ));
+    }
+    if unsafe { winapi::um::winuser::IsWindow(hwnd) } == 0 {
+        problems.push("The window handle isn't a window".to_string());
+    }
+
+    problems
+}
+
+/// A flip-model swapchain presenting `cmd_queue`'s work into `hwnd`
+fn create_swapchain(
+    factory: &d3d12::Factory4,
+    device: &d3d12::Device,
+    cmd_queue: d3d12::CommandQueue,
+    hwnd: HWND,
+    desc: &d3d12::SwapchainDesc,
+) -> Result<d3d12::SwapChain3, DxError> {
+    let (swapchain, hr) = factory
+        .as_factory2()
+        .create_swapchain_for_hwnd(cmd_queue, hwnd, desc);
+    os_helpers::report_device_removed(device, hr);
+    if FAILED(hr) {
+        let problems = swapchain_problems(desc, hwnd);
+        if hr == E_ACCESSDENIED {
+            status!("Creating the swapchain failed: another swapchain is still presenting to this window");
+        } else if problems.is_empty() {
+            status!("Creating the swapchain failed, and the description follows every flip-model rule we know of");
+        } else {
+            status!("Creating the swapchain failed, most likely because:");
+            for problem in &problems {
+                status!("    {}", problem);
+            }
+        }
+    }
+    let swapchain = check_hr_err!((swapchain, hr));
+    // DXGI would take Alt+Enter for exclusive fullscreen. We handle it ourselves, borderless.
+    check_hr_only!(unsafe {
+        factory.MakeWindowAssociation(hwnd, d3d12_ext::DXGI_MWA_NO_ALT_ENTER)
+    });
+
+    // We need IDXGISwapChain3 to know which back buffer we're rendering into
+    let swapchain3 =
+        unsafe { check_hr_err!(swapchain.cast::<winapi::shared::dxgi1_4::IDXGISwapChain3>()) };
+    // Only one swapchain can present into a window, so switching adapters needs every reference gone
+    unsafe {
+        swapchain.destroy();
+    }
+
+    Ok(swapchain3)
+}
+
+/// How many buffers `swapchain` really has. DXGI can adjust the count it was asked for.
+fn swapchain_buffer_count(swapchain: &d3d12::SwapChain3) -> Option<u32> {
+    unsafe {
+        let mut desc: winapi::shared::dxgi1_2::DXGI_SWAP_CHAIN_DESC1 = zeroed();
+        let hr = check_hr_only!(swapchain.GetDesc1(&mut desc));
+        if SUCCEEDED(hr) {
+            Some(desc.BufferCount)
+        } else {
+            None
+        }
+    }
+}
+
+/// What the scene is built from. Switching adapters builds it again from the same description.
+struct SceneDesc {
+    mode: RenderMode,
+    shader_dir: Option<PathBuf>,
+    fill: triangle::FillMode,
+    mesh: mesh::Mesh,
+    /// The back buffers' format, which the raytraced output is copied into
+    format: DXGI_FORMAT,
+    rtv_format: DXGI_FORMAT,
+    /// Samples per pixel to ask for. Devices that can't do this many render without MSAA.
+    msaa: u32,
+    clear_color: [f32; 4],
+    buffer_count: u32,
+    /// Set with `--conservative-raster`. Only the raster path uses it.
+    conservative_raster: bool,
+    /// Copies of the geometry to trace. Only the raytracing path uses it.
+    instances: u32,
+}
+
+impl SceneDesc {
+    /// The flag asking for something `device_caps` says the device can't do, if there is one
+    fn unsupported_flag(&self, device_caps: &caps::DeviceCaps) -> Option<&'static str> {
+        if self.mode == RenderMode::Raytrace
+            && device_caps.raytracing_tier == caps::RaytracingTier::NotSupported
+        {
+            Some("--mode raytrace")
+        } else if self.mode == RenderMode::Mesh
+            && device_caps.mesh_shader_tier == caps::MeshShaderTier::NotSupported
+        {
+            Some("--mode mesh")
+        } else if self.conservative_raster
+            && device_caps.conservative_raster_tier == caps::ConservativeRasterTier::NotSupported
+        {
+            Some("--conservative-raster")
+        } else {
+            None
+        }
+    }
+}
+
+/// Build the scene on `device`, uploading its geometry with `frame`'s commands.
+/// Returns once the GPU is done with the uploads.
+#[allow(clippy::too_many_arguments)]
+fn create_scene(
+    device: &d3d12::Device,
+    device_caps: &caps::DeviceCaps,
+    cmd_queue: &d3d12::CommandQueue,
+    fence: &mut sync::QueueFence,
+    frame: &mut sync::FrameContext,
+    desc: &SceneDesc,
+    width: u32,
+    height: u32,
+) -> Result<scene::Scene, DxError> {
+    if let Some(flag) = desc.unsupported_flag(device_caps) {
+        return Err(DxError {
+            hr: DXGI_ERROR_UNSUPPORTED,
+            location: format!("{}:{}", file!(), line!()),
+            call: flag.to_string(),
+        });
+    }
+
+    let sample_count = if desc.msaa > 1
+        && caps::query_msaa_quality_levels(device, desc.rtv_format, desc.msaa) == 0
+    {
+        status!(
+            "⚠️ {}x MSAA isn't supported for this swapchain format. Rendering without it.",
+            desc.msaa
+        );
+        1
+    } else {
+        desc.msaa
+    };
+    let msaa = if sample_count > 1 {
+        Some(msaa::MsaaTarget::new(
+            device,
+            desc.format,
+            desc.rtv_format,
+            sample_count,
+            width,
+            height,
+            desc.clear_color,
+        )?)
+    } else {
+        None
+    };
+
+    let constants =
+        constant_buffers::ConstantBufferRing::new(device, desc.buffer_count, CONSTANTS_PER_FRAME)?;
+
+    // Geometry uploads and acceleration structure builds happen once, up front
+    frame.begin(fence);
+    let mut triangle = triangle::Triangle::new(
+        device,
+        desc.shader_dir.as_deref(),
+        desc.rtv_format,
+        sample_count,
+        desc.fill,
+        desc.conservative_raster,
+        &desc.mesh,
+        &frame.cmd_list,
+    )?;
+
+    let mut raytracer = if desc.mode == RenderMode::Raytrace {
+        Some(raytrace::Raytracer::new(
+            device,
+            device_caps.raytracing_tier,
+            desc.shader_dir.as_deref(),
+            &frame.cmd_list,
+            triangle.geometry(),
+            desc.instances,
+            desc.format,
+            width,
+            height,
+        )?)
+    } else {
+        None
+    };
+
+    let mesh_shader = if desc.mode == RenderMode::Mesh {
+        Some(mesh_shader::MeshShaderTriangle::new(
+            device,
+            desc.shader_dir.as_deref(),
+            desc.rtv_format,
+            sample_count,
+            desc.conservative_raster,
+        )?)
+    } else {
+        None
+    };
+
+    frame.submit(cmd_queue, fence);
+    fence.wait_for_last_submission();
+    triangle.release_upload_buffers();
+    if let Some(raytracer) = &mut raytracer {
+        raytracer.release_build_buffers();
+    }
+
+    Ok(scene::Scene::new(
+        triangle,
+        raytracer,
+        mesh_shader,
+        msaa,
+        constants,
+        desc.clear_color,
+    ))
+}
+
+/// Create a device on adapter `index`, for switching to it while running.
+/// Returns the adapter's description with it.
+fn create_device_at_index(
+    factory: &d3d12::Factory4,
+    index: u32,
+    feature_level: FeatureLevelRequest,
+) -> Result<(d3d12::Device, String), DxError> {
+    let adapter = adapters::Adapter::from_raw(check_hr_err!(factory.enumerate_adapters(index)));
+    let description = adapter.description();
+
+    let level = resolve_feature_level(feature_level, adapter.raw());
+    // The device holds on to the adapter itself, so ours is released on return
+    let (device, hr) = adapters::create_device(adapter.raw(), level);
+    let device = check_hr_err!((device, hr));
+    os_helpers::set_name(&device, "Device");
+
+    Ok((device, description))
+}
+
+/// Release everything we render with, in order, so the debug layer's leak report at exit only shows real leaks.
+/// Nothing in flight can still be using them.
+#[allow(clippy::too_many_arguments)]
+fn release_renderer(
+    scene: &mut scene::Scene,
+    gpu_timer: &mut gpu_timer::GpuTimer,
+    frames: &mut [sync::FrameContext],
+    render_targets: &mut render_targets::RenderTargets,
+    (depth_buffer, dsv_heap): (d3d12::Resource, d3d12::DescriptorHeap),
+    budget_watcher: Option<memory_budget::BudgetWatcher>,
+    fence: &mut sync::QueueFence,
+    swapchain: Option<d3d12::SwapChain3>,
+    cmd_queue: d3d12::CommandQueue,
+    info_queue: Option<debug_layer::InfoQueue>,
+    device: d3d12::Device,
+) {
+    scene.destroy();
+    gpu_timer.destroy();
+    for frame in frames {
+        frame.destroy();
+    }
+    render_targets.destroy();
+    unsafe {
+        depth_buffer.destroy();
+        dsv_heap.destroy();
+    }
+    if let Some(mut watcher) = budget_watcher {
+        watcher.destroy();
+    }
+    fence.destroy();
+    unsafe {
+        if let Some(swapchain) = swapchain {
+            swapchain.destroy();
+        }
+        cmd_queue.destroy();
+        if let Some(info_queue) = info_queue {
+            info_queue.destroy();
+        }
+        device.destroy();
+    }
+}
+
 #[derive(StructOpt)]
 struct Opts {
-    /// Index of adapter to use
+    /// Read default options from this TOML file, instead of itsy-bitsy.toml next to the executable.
+    /// Flags on the command line override it.
+    #[structopt(long, parse(from_os_str))]
+    // config::from_args_and_config reads this straight from the command line, before there's an Opts
+    #[allow(dead_code)]
+    config: Option<PathBuf>,
+
+    /// Index of adapter to use. Press Tab while running to switch to the next one.
     #[structopt(short, long, default_value = "0")]
     adapter: u32,
 
@@ -48,17 +656,63 @@ struct Opts {
     #[structopt(short, long, conflicts_with("adapter"))]
     warp: bool,
 
-    /// DX Feature level to request
+    /// If creating a device on the chosen hardware adapter fails, retry on WARP at the same feature level
+    #[structopt(long, conflicts_with("warp"))]
+    warp_fallback: bool,
+
+    /// Use the first adapter whose description contains this text (case-insensitive)
+    #[structopt(long, conflicts_with_all(&["adapter", "warp"]))]
+    adapter_name: Option<String>,
+
+    /// Use the hardware adapter with the most dedicated video memory
+    #[structopt(long, conflicts_with_all(&["adapter", "warp", "adapter-name"]))]
+    auto: bool,
+
+    /// Use the adapter with this LUID, as "high:low" in hex. The adapter list shows each LUID.
+    #[structopt(
+        long,
+        parse(try_from_str = parse_luid),
+        conflicts_with_all(&["adapter", "warp", "adapter-name", "auto"])
+    )]
+    adapter_luid: Option<LUID>,
+
+    /// Include software adapters (like the Microsoft Basic Render Driver) when listing adapters
+    #[structopt(long)]
+    show_software: bool,
+
+    /// List the adapters and exit, without creating a device or window
+    #[structopt(long)]
+    list_adapters: bool,
+
+    /// Like --list-adapters, but also try to create a device on each adapter at --feature-level
+    #[structopt(long)]
+    probe: bool,
+
+    /// Print which formats the selected device can render to, blend, display, and use as typed UAVs, then exit.
+    /// Handy for picking a --swapchain-format or seeing why an --hdr format is rejected.
+    #[structopt(long)]
+    list_formats: bool,
+
+    /// How to print the adapter list. Combine "json" with --list-adapters for clean output.
+    #[structopt(
+        long,
+        default_value = "text",
+        parse(try_from_str = parse_output_format),
+        possible_values = &["text", "json"]
+    )]
+    format: OutputFormat,
+
+    /// DX Feature level to request. "auto" picks the highest one the adapter supports.
     #[structopt(
         short,
         long,
         default_value = "11_0",
         parse(try_from_str = parse_feature_level),
         possible_values=&[
-            "9_1", "9_2", "9_3", "10_0", "10_1", "11_0", "11_1", "12_0", "12_1",
+            "auto", "9_1", "9_2", "9_3", "10_0", "10_1", "11_0", "11_1", "12_0", "12_1", "12_2",
         ]
     )]
-    feature_level: d3d12::FeatureLevel,
+    feature_level: FeatureLevelRequest,
 
     /// Number of frames to buffer. Double/triple buffering are normal.
     #[structopt(
@@ -68,160 +722,1667 @@ struct Opts {
         possible_values = &["1", "2", "3", "4", "5", "6", "7", "8"]
     )]
     buffer_count: u32,
+
+    /// Width of the window and swapchain, in pixels.
+    /// Defaults to the window's width when it last closed, or 1024 the first time.
+    #[structopt(long, parse(try_from_str = parse_nonzero))]
+    width: Option<u32>,
+
+    /// Height of the window and swapchain, in pixels.
+    /// Defaults to the window's height when it last closed, or 1024 the first time.
+    #[structopt(long, parse(try_from_str = parse_nonzero))]
+    height: Option<u32>,
+
+    /// Open this many windows, each presenting the scene through its own swapchain on the same device and queue.
+    /// The first one gets the keyboard and mouse controls. Raytracing and --msaa only support one window.
+    #[structopt(
+        long,
+        default_value = "1",
+        parse(try_from_str = parse_nonzero),
+        conflicts_with("headless")
+    )]
+    windows: u32,
+
+    /// Start in borderless fullscreen on the window's monitor. Alt+Enter toggles it while running.
+    #[structopt(long, conflicts_with("headless"))]
+    fullscreen: bool,
+
+    /// Color to clear the back buffer to, as "rrggbb" hex
+    #[structopt(long, default_value = "1a3366", parse(try_from_str = parse_color))]
+    clear_color: [f32; 4],
+
+    /// Draw through sRGB views of the back buffers, so shader output is gamma-encoded.
+    /// Needs an rgba8 or bgra8 --swapchain-format.
+    #[structopt(long, conflicts_with("hdr"))]
+    srgb: bool,
+
+    /// Pixel format of the swapchain buffers
+    #[structopt(
+        long,
+        default_value = "rgba8",
+        parse(try_from_str = parse_format),
+        possible_values = &["rgba8", "bgra8", "rgb10a2", "rgba16f"]
+    )]
+    swapchain_format: DXGI_FORMAT,
+
+    /// Present in HDR, as "hdr10" or "scrgb". This picks the swapchain format too.
+    #[structopt(
+        long,
+        parse(try_from_str = parse_hdr_mode),
+        possible_values = &["hdr10", "scrgb"],
+        conflicts_with = "swapchain-format"
+    )]
+    hdr: Option<hdr::HdrMode>,
+
+    /// How the swapchain hands buffers to DWM. "flip-sequential" keeps the previous frame's contents.
+    #[structopt(
+        long,
+        default_value = "flip-discard",
+        parse(try_from_str = parse_swap_effect),
+        possible_values = &["flip-discard", "flip-sequential"]
+    )]
+    swap_effect: d3d12::SwapEffect,
+
+    /// Present without waiting for vsync, tearing if the display allows it. Same as --vsync 0.
+    #[structopt(long, conflicts_with = "vsync")]
+    no_vsync: bool,
+
+    /// Vblanks to wait for each present. 0 presents as fast as possible, which needs tearing support.
+    #[structopt(
+        long,
+        default_value = "1",
+        possible_values = &["0", "1", "2", "3", "4"]
+    )]
+    vsync: u32,
+
+    /// Cap the frame rate when vsync is off. 0 leaves it uncapped.
+    #[structopt(long, default_value = "0")]
+    max_fps: u32,
+
+    /// Exit after this many presents, printing the average frame time. 0 runs until the window closes.
+    #[structopt(long, default_value = "0")]
+    present_count: u32,
+
+    /// Lock GPU clocks for repeatable timings. Needs Windows developer mode.
+    #[structopt(long)]
+    stable_power: bool,
+
+    /// Skip the debug layer, for machines without the Graphics Tools optional feature or where its overhead matters
+    #[structopt(long)]
+    no_debug_layer: bool,
+
+    /// Turn on GPU-based validation in the debug layer. This catches descriptor and resource state bugs, but is slow.
+    #[structopt(long, conflicts_with("no-debug-layer"))]
+    gpu_validation: bool,
+
+    /// Break into the debugger as soon as the debug layer reports an error
+    #[structopt(long, conflicts_with("no-debug-layer"))]
+    break_on_error: bool,
+
+    /// Turn on DRED, to report the last GPU operations and any page fault if the device is removed
+    #[structopt(long)]
+    dred: bool,
+
+    /// How to draw each frame: "raster" draws the triangle, "raytrace" traces it with DXR,
+    /// and "mesh" draws the built-in triangle with a mesh shader, falling back to "raster" without them
+    #[structopt(
+        long,
+        default_value = "raster",
+        parse(try_from_str = parse_mode),
+        possible_values = &["raster", "raytrace", "mesh"]
+    )]
+    mode: RenderMode,
+
+    /// How to rasterize the triangle. Press W to toggle it while running.
+    #[structopt(
+        long,
+        default_value = "solid",
+        parse(try_from_str = parse_fill_mode),
+        possible_values = &["solid", "wireframe"]
+    )]
+    fill: triangle::FillMode,
+
+    /// Rasterize every pixel the triangle touches at all, not just the ones whose centers it covers.
+    /// Needs a device with a conservative rasterization tier. Wireframe ignores it.
+    #[structopt(long)]
+    conservative_raster: bool,
+
+    /// Trace this many copies of the triangle or --mesh, laid out on a grid and tinted by instance.
+    /// Only --mode raytrace uses it.
+    #[structopt(long, default_value = "1", parse(try_from_str = parse_nonzero))]
+    instances: u32,
+
+    /// Fill the raytracing output on a separate compute queue each frame, synchronized with the
+    /// direct queue through a fence. Only --mode raytrace uses it.
+    #[structopt(long)]
+    async_compute: bool,
+
+    /// Samples per pixel for the triangle, resolved into the back buffer each frame
+    #[structopt(long, default_value = "1", possible_values = &["1", "2", "4", "8"])]
+    msaa: u32,
+
+    /// Draw and trace the triangles in this OBJ file instead of the built-in triangle
+    #[structopt(long, parse(from_os_str))]
+    mesh: Option<PathBuf>,
+
+    /// Load shaders from this directory instead of the built-in ones: precompiled .cso or .dxil,
+    /// or .hlsl compiled with DXC. Edits to the directory reload the shaders live.
+    #[structopt(long, parse(from_os_str))]
+    shader_dir: Option<PathBuf>,
+
+    /// Render without a window or swapchain, into offscreen targets, then exit. For build servers.
+    #[structopt(long, conflicts_with("hdr"))]
+    headless: bool,
+
+    /// How many frames --headless renders before exiting
+    #[structopt(long, default_value = "1", parse(try_from_str = parse_nonzero))]
+    frames: u32,
+
+    /// Save the last --headless frame to this PNG
+    #[structopt(long, parse(from_os_str), requires("headless"))]
+    output: Option<PathBuf>,
+
+    /// Render up to this frame, counting from 1, then print a CRC-32 of its pixels and exit.
+    /// Animation steps at a fixed rate, so the same build and driver always print the same hash.
+    #[structopt(long, parse(try_from_str = parse_nonzero))]
+    hash_frame: Option<u32>,
+
+    /// Record each frame's CPU phases (wait, record, submit, present) and GPU time to this
+    /// Chrome trace JSON, written on exit. Open it in chrome://tracing or Perfetto.
+    #[structopt(long, parse(from_os_str))]
+    trace: Option<PathBuf>,
+
+    /// Record this frame, counting from 1, to a PIX GPU capture.
+    /// Needs a build with the pix feature, and WinPixGpuCapturer.dll from PIX on the PATH.
+    #[structopt(long, parse(try_from_str = parse_nonzero))]
+    pix_capture_frame: Option<u32>,
+
+    /// Where --pix-capture-frame saves its capture. Defaults to itsy-bitsy-frame-<N>.wpix.
+    #[structopt(long, parse(from_os_str), requires("pix-capture-frame"))]
+    pix_capture_output: Option<PathBuf>,
+
+    /// Exit once the --pix-capture-frame capture is saved, instead of running until the window closes
+    #[structopt(long, requires("pix-capture-frame"))]
+    exit_after_capture: bool,
+
+    /// Log D3D calls as they're made. Repeat for more detail (-vv).
+    #[structopt(short, long, parse(from_occurrences))]
+    verbose: u32,
 }
 
-fn display_adapter(adapter: &d3d12::Adapter1, label: &str) {
-    let mut desc: DXGI_ADAPTER_DESC;
-    unsafe {
-        desc = zeroed();
-        adapter.GetDesc(&mut desc);
+fn main() -> Result<(), DxError> {
+    let (opts, config_path): (Opts, _) = config::from_args_and_config();
+    os_helpers::set_verbosity(opts.verbose);
+    // Keep stdout to the JSON itself, so tools can parse it as is
+    let json_output = matches!(opts.format, OutputFormat::Json);
+    os_helpers::set_status_to_stderr(json_output);
+    if let Some(path) = &config_path {
+        status!("Loaded options from {}", path.display());
     }
 
-    let description = String::from_utf16(&desc.Description).unwrap();
-    let vendor_id = format!("0x{:x}", desc.VendorId);
-    let device_id = format!("0x{:x}", desc.DeviceId);
-    let subsys_id = format!("0x{:x}", desc.SubSysId);
-    let revision = format!("0x{:x}", desc.Revision);
+    // Print log messages bare, so they read like the rest of our output.
+    // RUST_LOG still overrides the level picked by -v.
+    let log_level = if opts.verbose == 0 {
+        log::LevelFilter::Error
+    } else {
+        log::LevelFilter::Trace
+    };
+    let mut logger = env_logger::Builder::new();
+    logger
+        .filter_level(log_level)
+        .target(if json_output {
+            env_logger::Target::Stderr
+        } else {
+            env_logger::Target::Stdout
+        })
+        .format(|buf, record| writeln!(buf, "{}", record.args()));
+    if let Ok(filters) = std::env::var("RUST_LOG") {
+        logger.parse_filters(&filters);
+    }
+    logger.init();
 
-    let video_mem = desc.DedicatedVideoMemory / MiB;
-    let system_mem = desc.DedicatedSystemMemory / MiB;
-    let shared_mem = desc.SharedSystemMemory / MiB;
+    if let Some(dir) = &opts.shader_dir {
+        if !dir.is_dir() {
+            return Err(DxError {
+                hr: HRESULT_FROM_WIN32(ERROR_PATH_NOT_FOUND),
+                location: format!("{}:{}", file!(), line!()),
+                call: format!("--shader-dir {}", dir.display()),
+            });
+        }
+        status!("Loading shaders from {}", dir.display());
+    }
 
-    println!("{}: {}", label, description);
-    println!("    VendorId:      {:>10}", vendor_id);
-    println!("    DeviceId:      {:>10}", device_id);
-    println!("    SubSysId:      {:>10}", subsys_id);
-    println!("    Revision:      {:>10}", revision);
-    println!("    Video Memory:  {:>6} MiB", video_mem);
-    println!("    System Memory: {:>6} MiB", system_mem);
-    println!("    Shared Memory: {:>6} MiB", shared_mem);
-    println!();
-}
+    // --srgb views the back buffers through a typed sRGB alias, which only 8-bit UNORM formats have
+    if opts.srgb
+        && opts.mode != RenderMode::Raytrace
+        && render_targets::srgb_view_format(opts.swapchain_format).is_none()
+    {
+        eprintln!("Error: --srgb needs an 8-bit UNORM --swapchain-format, rgba8 or bgra8");
+        return Err(DxError {
+            hr: E_INVALIDARG,
+            location: format!("{}:{}", file!(), line!()),
+            call: format!("--srgb --swapchain-format {}", opts.swapchain_format),
+        });
+    }
+
+    // Loading the OBJ needs no GPU, so a bad file fails before anything is created
+    let mesh = match &opts.mesh {
+        Some(path) => match mesh::Mesh::load_obj(path) {
+            Ok(mesh) => {
+                status!(
+                    "Loaded {} triangles from {}",
+                    mesh.indices.len() / 3,
+                    path.display()
+                );
+                mesh
+            }
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                return Err(DxError {
+                    hr: E_FAIL,
+                    location: format!("{}:{}", file!(), line!()),
+                    call: format!("mesh::Mesh::load_obj({:?})", path),
+                });
+            }
+        },
+        None => mesh::Mesh::triangle(),
+    };
 
-fn main() {
-    let opts = Opts::from_args();
+    // PIX's capturer has to be loaded before any device exists
+    let mut frame_capture = opts.pix_capture_frame.and_then(|frame| {
+        let path = opts
+            .pix_capture_output
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(format!("itsy-bitsy-frame-{}.wpix", frame)));
+        match pix::load_gpu_capturer() {
+            Ok(()) => Some(pix::FrameCapture::new(frame, path)),
+            Err(err) => {
+                status!("⚠️ {}. Not capturing frame {}.", err, frame);
+                None
+            }
+        }
+    });
 
-    let factory = check_hr!(d3d12::Factory4::create(d3d12::FactoryCreationFlags::DEBUG));
-    let _debug = check_hr!(d3d12::Debug::get_interface());
+    let factory = if opts.no_debug_layer {
+        check_hr_err!(d3d12::Factory4::create(d3d12::FactoryCreationFlags::empty()))
+    } else {
+        match d3d12::Factory4::create(d3d12::FactoryCreationFlags::DEBUG) {
+            (factory, hr) if SUCCEEDED(hr) => factory,
+            // The DXGI debug layer ships with Graphics Tools too, so it's missing in the same places
+            (_, hr) => {
+                status!(
+                    "⚠️ Couldn't create a debug DXGI factory, so DXGI won't be validated: {}",
+                    os_helpers::hr_string(hr)
+                );
+                check_hr_err!(d3d12::Factory4::create(d3d12::FactoryCreationFlags::empty()))
+            }
+        }
+    };
 
-    // Log the adapters
-    for i in 0.. {
-        let (this_adapter, hr) = factory.enumerate_adapters(i);
+    // What --no-vsync and --hdr can expect, before anyone passes them. JSON listings leave it out.
+    let tearing = caps::query_tearing_support(&factory);
+    if !json_output {
+        let hdr_output = hdr::any_output_supports_hdr(&factory);
+        status!(
+            "Tearing: {}    HDR output: {}",
+            if tearing {
+                "SUPPORTED"
+            } else {
+                "NOT SUPPORTED"
+            },
+            if hdr_output { "FOUND" } else { "NONE" }
+        );
+    }
+
+    // Say plainly whether validation is on, so nobody mistakes a missing layer for a clean run
+    if opts.no_debug_layer {
+        status!("Debug layer: DISABLED (--no-debug-layer)");
+    } else {
+        let (debug, hr) = d3d12::Debug::get_interface();
 
         if SUCCEEDED(hr) {
-            // If we're choosing an adapter, label it to make it clear what we're using
-            // This will be omitted when using the warp adapter
-            let icon = if !opts.warp && opts.adapter == i {
-                "✨"
-            } else {
-                " "
-            };
-            let label = format!("{} Adapter {}:", icon, i);
-
-            display_adapter(&this_adapter, &label);
-        } else if hr == DXGI_ERROR_NOT_FOUND {
-            // Not found - we're at the last one.
-            break;
+            debug.enable_layer();
+            status!("Debug layer: ENABLED");
+
+            if opts.gpu_validation {
+                enable_gpu_validation(&debug);
+            }
         } else {
-            println!("Failed to enumerate adapter #{}: {}", i, hr_string(hr));
-            return;
+            status!("Debug layer: NOT AVAILABLE (install Graphics Tools)");
+            status!(
+                "⚠️ Nothing will be validated: {}",
+                os_helpers::hr_string(hr)
+            );
         }
     }
 
-    // Select adapter
-    let adapter: d3d12::Adapter1 = if opts.warp {
-        println!("Using WARP adapter");
+    if opts.dred {
+        if debug_layer::enable_dred() {
+            status!("DRED enabled");
+        } else {
+            status!("⚠️ DRED is not available");
+        }
+    }
 
-        check_hr! {
-            unsafe {
-                let mut warp_adapter = d3d12::Adapter1::null();
-                let hr = factory.EnumWarpAdapter(&IDXGIAdapter1::uuidof(), warp_adapter.mut_void());
+    let selecting_by_index =
+        !opts.warp && opts.adapter_name.is_none() && !opts.auto && opts.adapter_luid.is_none();
 
-                (warp_adapter, hr)
+    // Collect the adapters
+    let adapters: Vec<adapters::Adapter> = adapters::adapters(&factory).collect();
+
+    let mut infos: Vec<AdapterInfo> = adapters
+        .iter()
+        .enumerate()
+        .map(|(i, adapter)| AdapterInfo {
+            index: i as u32,
+            ..adapter.desc()
+        })
+        .collect();
+
+    let hardware_count = infos.iter().filter(|info| !info.software).count();
+    let hidden_count = if opts.show_software {
+        0
+    } else {
+        infos.len() - hardware_count
+    };
+
+    match opts.format {
+        OutputFormat::Text => {
+            if hidden_count == 0 {
+                status!("Found {} hardware adapter(s)", hardware_count);
+            } else {
+                status!(
+                    "Found {} hardware adapter(s), hiding {} software adapter(s) (see --show-software)",
+                    hardware_count, hidden_count
+                );
+            }
+            status!();
+
+            // Log the adapters
+            for (this_adapter, info) in adapters.iter().zip(&mut infos) {
+                if !opts.show_software && info.software {
+                    continue;
+                }
+
+                // If we're choosing an adapter, label it to make it clear what we're using
+                // This will be omitted when the adapter isn't picked by index
+                let icon = if selecting_by_index && opts.adapter == info.index {
+                    "✨"
+                } else {
+                    " "
+                };
+                let label = format!("{} Adapter {}:", icon, info.index);
+
+                adapters::display_adapter(this_adapter, info, &label);
             }
         }
-    } else {
-        let adapter = check_hr!(factory.enumerate_adapters(opts.adapter));
-        if adapter.is_null() {
-            return;
+        OutputFormat::Json => {
+            let mut shown: Vec<&mut AdapterInfo> = vec![];
+            for (this_adapter, info) in adapters.iter().zip(&mut infos) {
+                if opts.show_software || !info.software {
+                    this_adapter.probe_caps(info);
+                    shown.push(info);
+                }
+            }
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&shown).expect("Failed to serialize adapter info")
+            );
         }
+    }
 
-        adapter
-    };
+    if opts.probe {
+        status!("Creating a device on each adapter:");
+        for (this_adapter, info) in adapters.iter().zip(&infos) {
+            if !opts.show_software && info.software {
+                continue;
+            }
 
-    let adapter = unsafe {
-        use winapi::shared::dxgi1_4::*;
-        use winapi::shared::dxgi1_6::*;
-        let adapter = check_hr!(adapter.cast::<IDXGIAdapter4>());
+            let level = resolve_feature_level(opts.feature_level, this_adapter.raw());
+            let (device, hr) = adapters::create_device(this_adapter.raw(), level);
+            if SUCCEEDED(hr) {
+                unsafe {
+                    device.destroy();
+                }
+            }
+            status!(
+                "    Adapter {} at {}: {}",
+                info.index,
+                caps::feature_level_name(level),
+                os_helpers::hr_string(hr)
+            );
+        }
+        status!();
+    }
+
+    let adapter_count = adapters.len();
+    drop(adapters);
+
+    if opts.list_adapters || opts.probe {
+        if adapter_count == 0 {
+            return Err(DxError {
+                hr: DXGI_ERROR_NOT_FOUND,
+                location: format!("{}:{}", file!(), line!()),
+                call: "factory.enumerate_adapters(0)".to_string(),
+            });
+        }
+
+        return Ok(());
+    }
+
+    // Select adapter
+    let adapter: adapters::Adapter = if opts.warp {
+        status!("Using WARP adapter");
+        adapters::warp_adapter(&factory)?
+    } else if let Some(name) = &opts.adapter_name {
+        adapters::find_adapter_by_name(&factory, name)?
+    } else if opts.auto {
+        adapters::find_adapter_with_most_memory(&factory)?
+    } else if let Some(luid) = opts.adapter_luid {
+        adapters::find_adapter_by_luid(&factory, luid)?
+    } else {
+        if opts.adapter as usize >= adapter_count {
+            status!(
+                "Requested adapter {} but only {} adapters found",
+                opts.adapter,
+                adapter_count
+            );
+            return Err(DxError {
+                hr: DXGI_ERROR_NOT_FOUND,
+                location: format!("{}:{}", file!(), line!()),
+                call: format!("factory.enumerate_adapters({})", opts.adapter),
+            });
+        }
 
-        let mut mem_info: DXGI_QUERY_VIDEO_MEMORY_INFO = zeroed();
-        adapter.QueryVideoMemoryInfo(0, DXGI_MEMORY_SEGMENT_GROUP_LOCAL, &mut mem_info);
+        adapters::Adapter::from_raw(check_hr_err!(factory.enumerate_adapters(opts.adapter)))
+    };
 
+    if let Some(mem_info) = adapter.local_memory_info() {
         let budget = mem_info.Budget / MiB as u64;
         let current = mem_info.CurrentUsage / MiB as u64;
         let reserved_avail = mem_info.AvailableForReservation / MiB as u64;
         let reserved_current = mem_info.CurrentReservation / MiB as u64;
 
-        println!("DXGI_QUERY_VIDEO_MEMORY_INFO");
-        println!("    budget:           {:>5} MiB", budget);
-        println!("    current:          {:>5} MiB", current);
-        println!("    reserved_avail:   {:>5} MiB", reserved_avail);
-        println!("    reserved_current: {:>5} MiB", reserved_current);
-        println!();
+        status!("DXGI_QUERY_VIDEO_MEMORY_INFO");
+        status!("    budget:           {:>5} MiB", budget);
+        status!("    current:          {:>5} MiB", current);
+        status!("    reserved_avail:   {:>5} MiB", reserved_avail);
+        status!("    reserved_current: {:>5} MiB", reserved_current);
+        status!();
+    }
+
+    let feature_level = resolve_feature_level(opts.feature_level, adapter.raw());
+    let mut device = match adapters::create_device(adapter.raw(), feature_level) {
+        (device, hr) if SUCCEEDED(hr) => device,
+        (_, hr) => {
+            status!(
+                "⚠️ Couldn't create a device on this adapter: {}",
+                os_helpers::hr_string(hr)
+            );
+            if feature_level == d3d12_ext::D3D_FEATURE_LEVEL_12_2 {
+                status!("⚠️ Feature level 12_2 needs a DX12 Ultimate GPU and driver, on Windows 10 2004 or newer");
+            }
+
+            if !opts.warp_fallback || opts.warp {
+                return Err(DxError {
+                    hr,
+                    location: format!("{}:{}", file!(), line!()),
+                    call: format!(
+                        "adapters::create_device(adapter, {})",
+                        caps::feature_level_name(feature_level)
+                    ),
+                });
+            }
 
-        adapter
+            status!("⚠️ Falling back to WARP, which renders in software");
+            let warp_adapter = adapters::warp_adapter(&factory)?;
+            let warp_level = resolve_feature_level(opts.feature_level, warp_adapter.raw());
+            check_hr_err!(adapters::create_device(warp_adapter.raw(), warp_level))
+        }
     };
+    os_helpers::set_name(&device, "Device");
+    // The device holds on to the adapter itself
+    drop(adapter);
 
-    let device = check_hr!(d3d12::Device::create(adapter, opts.feature_level));
+    if opts.stable_power {
+        // Outside developer mode, SetStablePowerState removes the device instead of failing
+        if !os_helpers::developer_mode_enabled() {
+            status!("⚠️ --stable-power needs Windows developer mode. GPU clocks will vary.");
+        } else {
+            let hr = check_hr_only!(unsafe { device.SetStablePowerState(TRUE) });
+            if SUCCEEDED(hr) {
+                status!("GPU clocks locked with SetStablePowerState");
+            } else {
+                status!("⚠️ Couldn't set a stable power state. GPU clocks will vary.");
+            }
+        }
+    }
 
-    let cmd_queue = check_hr!(device.create_command_queue(
-        d3d12::CmdListType::Direct,
-        d3d12::Priority::Normal,
-        d3d12::CommandQueueFlags::empty(),
-        0,
-    ));
+    let mut info_queue = debug_layer::get_info_queue(&device, opts.break_on_error);
+    if opts.break_on_error && info_queue.is_none() {
+        status!("⚠️ No info queue on this device, so --break-on-error won't do anything");
+    }
 
-    // Initialize a window object to render onto
-    let event_loop = EventLoop::new();
-    let window = WindowBuilder::new()
-        .with_title("☀ Itsy Bitsy DXR ☀")
-        .build(&event_loop)
-        .expect("Failed to create a window");
+    let mut budget_watcher =
+        memory_budget::BudgetWatcher::new(&factory, unsafe { device.GetAdapterLuid() });
 
-    let hwnd: winapi::shared::windef::HWND = match window.raw_window_handle() {
-        raw_window_handle::RawWindowHandle::Windows(handle) => handle.hwnd as _,
-        raw_handle => panic!("Unsupported window handle + OS: {:?}", raw_handle),
+    let device_caps = caps::DeviceCaps::query(&device);
+    status!("{}", device_caps.to_string().trim_end());
+    if let Some(shader_model) = device_caps.shader_model {
+        shaders::set_shader_model(shader_model);
+    }
+    if device_caps.raytracing_tier == caps::RaytracingTier::NotSupported {
+        status!("⚠️ This device does not support DXR. Raytracing will not be available.");
+    }
+    let mode = if opts.mode == RenderMode::Mesh
+        && device_caps.mesh_shader_tier == caps::MeshShaderTier::NotSupported
+    {
+        status!("⚠️ This device does not support mesh shaders. Falling back to --mode raster.");
+        RenderMode::Raster
+    } else {
+        opts.mode
+    };
+    if opts.conservative_raster
+        && device_caps.conservative_raster_tier == caps::ConservativeRasterTier::NotSupported
+    {
+        status!("⚠️ This device does not support conservative rasterization, which --conservative-raster needs.");
+    }
+    status!();
+
+    if opts.list_formats {
+        caps::display_format_support(&device);
+        if let Some(budget_watcher) = &mut budget_watcher {
+            budget_watcher.destroy();
+        }
+        unsafe {
+            device.destroy();
+        }
+        return Ok(());
+    }
+
+    let mut cmd_queue = create_direct_queue(&device)?;
+
+    let mut sync_interval = if opts.no_vsync { 0 } else { opts.vsync };
+    let allow_tearing = if sync_interval == 0 && !opts.headless {
+        if !tearing {
+            status!("⚠️ Tearing is not supported here. Falling back to vsync.");
+            sync_interval = 1;
+        }
+        tearing
+    } else {
+        false
+    };
+    let mut frame_limiter = match opts.max_fps {
+        0 => None,
+        _ if sync_interval != 0 => {
+            status!("⚠️ --max-fps only applies with vsync off, so it won't do anything");
+            None
+        }
+        max_fps => Some(frame_limiter::FrameLimiter::new(max_fps)),
+    };
+    let present_flags = if allow_tearing {
+        DXGI_PRESENT_ALLOW_TEARING
+    } else {
+        0
     };
 
-    let swapchain_desc = d3d12::SwapchainDesc {
+    let mut swapchain_desc = d3d12::SwapchainDesc {
         buffer_count: opts.buffer_count,
         buffer_usage: DXGI_USAGE_RENDER_TARGET_OUTPUT,
-        width: 1024,
-        height: 1024,
-        format: DXGI_FORMAT_R8G8B8A8_UNORM,
-        swap_effect: d3d12::SwapEffect::FlipDiscard,
+        width: opts.width.unwrap_or(DEFAULT_WIDTH),
+        height: opts.height.unwrap_or(DEFAULT_HEIGHT),
+        format: opts.hdr.map_or(opts.swapchain_format, hdr::HdrMode::format),
+        swap_effect: opts.swap_effect,
         sample: d3d12::SampleDesc {
             count: 1,
             ..unsafe { zeroed() }
         },
+        flags: if allow_tearing {
+            DXGI_SWAP_CHAIN_FLAG_ALLOW_TEARING
+        } else {
+            0
+        },
 
         ..unsafe { zeroed() }
     };
-    let _swapchain = check_hr!(factory.as_factory2().create_swapchain_for_hwnd(
-        cmd_queue,
-        hwnd,
-        &swapchain_desc
-    ));
+
+    // The HDR mode we're presenting in, once we know the output supports it
+    let mut hdr_mode = opts.hdr;
+
+    // Headless runs skip the window and swapchain entirely, for machines without a desktop
+    let presenter = if opts.headless {
+        None
+    } else {
+        // Both of these fail swapchain creation with HRESULTs that don't point at the real problem
+        if os_helpers::in_session_zero() {
+            status!("⚠️ Running in session 0, which has no desktop to present to. Try --headless.");
+        } else if os_helpers::remote_session() {
+            status!("⚠️ Running over Remote Desktop, where flip-model swapchains may be unavailable. Try --headless if creating the swapchain fails.");
+        }
+
+        // Initialize a window object to render onto
+        let event_loop = EventLoop::new();
+        // Hidden until it's moved to where the last run left it, so it doesn't jump
+        let window = WindowBuilder::new()
+            .with_title(WINDOW_TITLE)
+            .with_inner_size(PhysicalSize::new(
+                swapchain_desc.width,
+                swapchain_desc.height,
+            ))
+            .with_visible(false)
+            .build(&event_loop)
+            .expect("Failed to create a window");
+        if let Some(state) = window_state::load() {
+            let size_given = opts.width.is_some() || opts.height.is_some();
+            window_state::restore(&window, &state, !size_given);
+            let size = window.inner_size();
+            swapchain_desc.width = size.width;
+            swapchain_desc.height = size.height;
+        }
+        window.set_visible(true);
+
+        let swapchain = create_swapchain(
+            &factory,
+            &device,
+            cmd_queue,
+            window_hwnd(&window),
+            &swapchain_desc,
+        )?;
+
+        // Size the RTVs and frame contexts to the buffers we got, not the ones we asked for
+        if let Some(actual_count) = swapchain_buffer_count(&swapchain) {
+            if actual_count != swapchain_desc.buffer_count {
+                status!(
+                    "⚠️ Asked for {} back buffers, but the swapchain has {}",
+                    swapchain_desc.buffer_count,
+                    actual_count
+                );
+                swapchain_desc.buffer_count = actual_count;
+            }
+        }
+
+        if let Some(mode) = opts.hdr {
+            let output_desc = hdr::containing_output_desc(&swapchain);
+            if let Some(desc) = &output_desc {
+                hdr::display_hdr_caps(desc);
+            }
+
+            let hdr_supported =
+                matches!(&output_desc, Some(desc) if hdr::output_supports_hdr(desc));
+            if !hdr_supported || !hdr::set_color_space(&swapchain, mode) {
+                status!("⚠️ This output doesn't support HDR. Falling back to SDR.");
+                hdr_mode = None;
+
+                swapchain_desc.format = DXGI_FORMAT_R8G8B8A8_UNORM;
+                let hr = unsafe {
+                    swapchain.ResizeBuffers(
+                        0,
+                        swapchain_desc.width,
+                        swapchain_desc.height,
+                        swapchain_desc.format,
+                        swapchain_desc.flags,
+                    )
+                };
+                os_helpers::report_device_removed(&device, hr);
+                check_hr_err!(((), hr));
+            }
+        }
+
+        Some((event_loop, window, swapchain))
+    };
+
+    // Size the depth buffer to match the swapchain, so the two always line up
+    let (mut depth_buffer, mut dsv_heap) =
+        depth::create_depth_buffer(&device, swapchain_desc.width, swapchain_desc.height)?;
+
+    // One context (and so one allocator) per back buffer, so resetting one can't stomp on commands still in flight
+    let mut frames = (0..swapchain_desc.buffer_count)
+        .map(|i| sync::FrameContext::new(&device, &format!("Frame {} Commands", i)))
+        .collect::<Result<Vec<_>, DxError>>()?;
+
+    // The DXR output is a UAV, which can't be sRGB, so --srgb only changes how we rasterize
+    let rtv_format = if opts.srgb && opts.mode == RenderMode::Raytrace {
+        status!("⚠️ --srgb doesn't apply to --mode raytrace. Ignoring it.");
+        swapchain_desc.format
+    } else if opts.srgb {
+        render_targets::srgb_view_format(swapchain_desc.format)
+            .expect("--srgb was checked against --swapchain-format")
+    } else {
+        swapchain_desc.format
+    };
+    // Clear colors are given in sRGB, so through an sRGB view they have to be written linear
+    let clear_color = if rtv_format != swapchain_desc.format {
+        srgb_to_linear(opts.clear_color)
+    } else {
+        opts.clear_color
+    };
+
+    let mut render_targets = match &presenter {
+        Some((_, _, swapchain)) => render_targets::RenderTargets::new(
+            &device,
+            swapchain,
+            swapchain_desc.buffer_count,
+            rtv_format,
+        )?,
+        None => render_targets::RenderTargets::offscreen(
+            &device,
+            swapchain_desc.buffer_count,
+            swapchain_desc.format,
+            rtv_format,
+            swapchain_desc.width,
+            swapchain_desc.height,
+            clear_color,
+        )?,
+    };
+
+    if opts.mesh.is_some() && mode == RenderMode::Mesh {
+        status!("⚠️ --mode mesh always draws the built-in triangle, not --mesh");
+    }
+
+    let mut scene_desc = SceneDesc {
+        mode,
+        shader_dir: opts.shader_dir.clone(),
+        fill: opts.fill,
+        mesh,
+        format: swapchain_desc.format,
+        rtv_format,
+        // Raytracing writes every pixel itself, so only the raster path multisamples
+        msaa: if opts.msaa > 1 && opts.mode == RenderMode::Raytrace {
+            status!("⚠️ --msaa doesn't apply to --mode raytrace. Ignoring it.");
+            1
+        } else {
+            opts.msaa
+        },
+        clear_color,
+        buffer_count: swapchain_desc.buffer_count,
+        conservative_raster: if opts.conservative_raster && opts.mode == RenderMode::Raytrace {
+            status!("⚠️ --conservative-raster doesn't apply to --mode raytrace. Ignoring it.");
+            false
+        } else {
+            opts.conservative_raster
+        },
+        instances: if opts.instances > 1 && mode != RenderMode::Raytrace {
+            status!("⚠️ --instances only applies to --mode raytrace. Ignoring it.");
+            1
+        } else {
+            opts.instances
+        },
+    };
+
+    let mut fence = sync::QueueFence::new(&device)?;
+    let mut scene = create_scene(
+        &device,
+        &device_caps,
+        &cmd_queue,
+        &mut fence,
+        &mut frames[0],
+        &scene_desc,
+        swapchain_desc.width,
+        swapchain_desc.height,
+    )?;
+
+    if opts.async_compute && scene.raytracer.is_none() {
+        status!("⚠️ --async-compute only applies to --mode raytrace. Ignoring it.");
+    }
+    let use_async_compute = opts.async_compute && scene.raytracer.is_some();
+    let mut async_compute = if use_async_compute {
+        Some(async_compute::AsyncCompute::new(
+            &device,
+            scene_desc.shader_dir.as_deref(),
+            swapchain_desc.buffer_count,
+            scene_desc.clear_color,
+        )?)
+    } else {
+        None
+    };
+
+    let mut gpu_timer = gpu_timer::GpuTimer::new(&device, &cmd_queue, swapchain_desc.buffer_count)?;
+    let mut trace = match &opts.trace {
+        Some(path) => Some(chrome_trace::ChromeTrace::new(path, &cmd_queue)?),
+        None => None,
+    };
+
+    let (event_loop, window, mut swapchain) = match presenter {
+        Some(presenter) => presenter,
+        None => {
+            let start = Instant::now();
+            // Run far enough to reach the frame we're hashing
+            let mut frame_total = opts.frames.max(opts.hash_frame.unwrap_or(0));
+            let ctrl_c = os_helpers::ctrl_c_flag(|| ());
+            for frame_number in 0..frame_total {
+                if ctrl_c.load(Ordering::SeqCst) {
+                    frame_total = frame_number;
+                    break;
+                }
+                let index = frame_number % swapchain_desc.buffer_count;
+                if let Some(frame_capture) = &mut frame_capture {
+                    frame_capture.before_frame(frame_number + 1);
+                }
+                if opts.hash_frame.is_some() {
+                    scene.fixed_time = Some(frame_number as f32 / HASH_FRAME_RATE);
+                }
+
+                let wait_start = Instant::now();
+                let frame = &mut frames[index as usize];
+                frame.begin(&fence);
+                if let Some(trace) = &mut trace {
+                    trace.cpu_phase("wait", frame_number + 1, wait_start);
+                }
+
+                let record_start = Instant::now();
+                let cmd_list = frame.cmd_list;
+                scene.begin_frame(index);
+                if let Some((begin, end)) = gpu_timer.read_timestamps(index) {
+                    if let Some(trace) = &mut trace {
+                        // This index last rendered the frame `buffer_count` ago
+                        trace.gpu_frame(frame_number + 1 - swapchain_desc.buffer_count, begin, end);
+                    }
+                }
+
+                let target = render_targets.buffer(index);
+                gpu_timer.begin(&cmd_list, index);
+                scene.record(
+                    &cmd_list,
+                    target,
+                    render_targets.rtv_handle(index),
+                    dsv_heap.start_cpu_descriptor(),
+                    swapchain_desc.width,
+                    swapchain_desc.height,
+                );
+                gpu_timer.end(&cmd_list, index);
+
+                let screenshot = match &opts.output {
+                    Some(_) if frame_number + 1 == frame_total => {
+                        Some(screenshot::Screenshot::record(&device, &cmd_list, target)?)
+                    }
+                    _ => None,
+                };
+                let hash_readback = if opts.hash_frame == Some(frame_number + 1) {
+                    Some(screenshot::Screenshot::record(&device, &cmd_list, target)?)
+                } else {
+                    None
+                };
+                if let Some(trace) = &mut trace {
+                    trace.cpu_phase("record", frame_number + 1, record_start);
+                }
+
+                let submit_start = Instant::now();
+                if let (Some(async_compute), Some(raytracer)) =
+                    (&mut async_compute, &scene.raytracer)
+                {
+                    async_compute.fill_output(index, raytracer, &cmd_queue, &fence);
+                }
+                frame.submit(&cmd_queue, &mut fence);
+                if let Some(trace) = &mut trace {
+                    trace.cpu_phase("submit", frame_number + 1, submit_start);
+                }
+                if let Some(frame_capture) = &mut frame_capture {
+                    frame_capture.after_frame(frame_number + 1);
+                }
+                if let Some(budget_watcher) = &mut budget_watcher {
+                    budget_watcher.poll();
+                }
+
+                if let Some(info_queue) = &info_queue {
+                    debug_layer::drain_info_queue(info_queue);
+                }
+
+                if let (Some(screenshot), Some(path)) = (screenshot, &opts.output) {
+                    fence.wait_for_last_submission();
+                    match screenshot.save(path) {
+                        Ok(()) => status!("Saved the last frame to {}", path.display()),
+                        Err(err) => status!("⚠️ {}", err),
+                    }
+                }
+                if let Some(readback) = hash_readback {
+                    fence.wait_for_last_submission();
+                    print_frame_hash(frame_number + 1, readback);
+                }
+            }
+            fence.wait_for_last_submission();
+
+            status!(
+                "Rendered {} frame(s) headless in {:.2?}",
+                frame_total,
+                start.elapsed()
+            );
+            if let Some(trace) = &mut trace {
+                // The last few frames' timestamps are still waiting to be read
+                let first_unread = frame_total.saturating_sub(swapchain_desc.buffer_count);
+                for frame_number in first_unread..frame_total {
+                    let index = frame_number % swapchain_desc.buffer_count;
+                    if let Some((begin, end)) = gpu_timer.read_timestamps(index) {
+                        trace.gpu_frame(frame_number + 1, begin, end);
+                    }
+                }
+                trace.save();
+            }
+            if let Some(async_compute) = &mut async_compute {
+                async_compute.destroy();
+            }
+            release_renderer(
+                &mut scene,
+                &mut gpu_timer,
+                &mut frames,
+                &mut render_targets,
+                (depth_buffer, dsv_heap),
+                budget_watcher.take(),
+                &mut fence,
+                None,
+                cmd_queue,
+                info_queue.take(),
+                device,
+            );
+
+            return Ok(());
+        }
+    };
+
+    let shader_watcher = match &opts.shader_dir {
+        Some(dir) => match shader_watch::ShaderWatcher::new(dir) {
+            Ok(watcher) => Some(watcher),
+            Err(err) => {
+                status!("⚠️ {}. Shaders won't hot reload.", err);
+                None
+            }
+        },
+        None => None,
+    };
+    let shader_dir = opts.shader_dir.clone();
+
+    let mut frame_timer = frame_timer::FrameTimer::new();
+    let mut screenshot_requested = false;
+    let mut gpu_time_ms = 0.0;
+    let mut presents = 0;
+    let run_start = Instant::now();
+
+    // Nothing renders while the window is minimized or in the background, unless --present-count is
+    // counting presents, which would otherwise never finish behind another window.
+    // Skipped frames never signal the fence, so there's nothing to wait on when we resume.
+    let mut focused = true;
+    let mut minimized = false;
+
+    // Raytracing and MSAA render through textures sized to the main window, so only it can show them
+    let window_count = if opts.windows > 1 && (scene.raytracer.is_some() || scene.msaa.is_some()) {
+        status!("⚠️ --windows doesn't work with --mode raytrace or --msaa. Opening one window.");
+        1
+    } else {
+        opts.windows
+    };
+    let mut extra_windows = vec![];
+    for number in 2..=window_count {
+        let extra = WindowBuilder::new()
+            .with_title(format!("{} — Window {}", WINDOW_TITLE, number))
+            .with_inner_size(PhysicalSize::new(
+                swapchain_desc.width,
+                swapchain_desc.height,
+            ))
+            .build(&event_loop)
+            .expect("Failed to create a window");
+        let extra_desc = d3d12::SwapchainDesc {
+            buffer_count: swapchain_desc.buffer_count,
+            buffer_usage: DXGI_USAGE_RENDER_TARGET_OUTPUT,
+            width: swapchain_desc.width,
+            height: swapchain_desc.height,
+            format: swapchain_desc.format,
+            swap_effect: swapchain_desc.swap_effect,
+            sample: d3d12::SampleDesc {
+                count: 1,
+                ..unsafe { zeroed() }
+            },
+            flags: swapchain_desc.flags,
+
+            ..unsafe { zeroed() }
+        };
+        let extra_swapchain = create_swapchain(
+            &factory,
+            &device,
+            cmd_queue,
+            window_hwnd(&extra),
+            &extra_desc,
+        )?;
+        if let Some(mode) = hdr_mode {
+            hdr::set_color_space(&extra_swapchain, mode);
+        }
+        let extra_buffer_count =
+            swapchain_buffer_count(&extra_swapchain).unwrap_or(extra_desc.buffer_count);
+        extra_windows.push(extra_window::ExtraWindow::new(
+            &device,
+            extra,
+            extra_swapchain,
+            &extra_desc,
+            extra_buffer_count,
+            rtv_format,
+            CONSTANTS_PER_FRAME,
+        )?);
+    }
+    // Closing the main window only hides it while others are still open
+    let mut main_window_open = true;
+
+    let mut modifiers = ModifiersState::empty();
+    let mut fullscreen = fullscreen::FullscreenToggle::default();
+    if opts.fullscreen {
+        fullscreen.toggle(&window);
+    }
+
+    // Dragging with the left button orbits the camera
+    let mut dragging = false;
+    let mut last_cursor: Option<(f64, f64)> = None;
+
+    // Tab switches to the next adapter, so it shows in the title next to the frame time
+    let mut adapter_name =
+        adapters::description_by_luid(&factory, unsafe { device.GetAdapterLuid() })
+            .unwrap_or_default();
+    let feature_level_request = opts.feature_level;
+    let break_on_error = opts.break_on_error;
+
+    let buffer_count = swapchain_desc.buffer_count;
+    let present_count = opts.present_count;
+    let exit_after_capture = opts.exit_after_capture;
+    let hash_frame = opts.hash_frame;
+
+    // Waiting for events doesn't check the flag, so Ctrl+C sends one
+    let proxy = event_loop.create_proxy();
+    let ctrl_c = os_helpers::ctrl_c_flag(move || {
+        let _ = proxy.send_event(());
+    });
 
     event_loop.run(move |event, _, control_flow| {
-        // *control_flow = ControlFlow::Wait;
-        *control_flow = ControlFlow::Exit;
+        let rendering = (focused || present_count != 0)
+            && ((main_window_open && !minimized)
+                || extra_windows.iter().any(|extra| !extra.minimized));
+        *control_flow = if rendering {
+            ControlFlow::Poll
+        } else {
+            ControlFlow::Wait
+        };
+
+        // LoopDestroyed still has to run, since that's where we wait for the GPU and release everything
+        if ctrl_c.load(Ordering::SeqCst) && !matches!(event, Event::LoopDestroyed) {
+            *control_flow = ControlFlow::Exit;
+            return;
+        }
 
         match event {
             Event::WindowEvent {
                 event: WindowEvent::CloseRequested,
                 window_id,
-            } if window_id == window.id() => *control_flow = ControlFlow::Exit,
+            } if window_id == window.id() => {
+                if extra_windows.is_empty() {
+                    *control_flow = ControlFlow::Exit;
+                } else {
+                    window.set_visible(false);
+                    main_window_open = false;
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                window_id,
+            } => {
+                if let Some(position) = extra_windows
+                    .iter()
+                    .position(|extra| extra.window.id() == window_id)
+                {
+                    fence.wait_for_last_submission();
+                    extra_windows.remove(position).destroy();
+                    if extra_windows.is_empty() && !main_window_open {
+                        *control_flow = ControlFlow::Exit;
+                    }
+                }
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F12),
+                                ..
+                            },
+                        ..
+                    },
+                window_id,
+            } if window_id == window.id() => screenshot_requested = true,
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::W),
+                                ..
+                            },
+                        ..
+                    },
+                window_id,
+            } if window_id == window.id()
+                && scene.raytracer.is_none()
+                && scene.mesh_shader.is_none() =>
+            {
+                scene.triangle.fill_mode = scene.triangle.fill_mode.toggled();
+                status!("Fill mode: {}", scene.triangle.fill_mode.name());
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::R),
+                                ..
+                            },
+                        ..
+                    },
+                window_id,
+            } if window_id == window.id() => scene.camera.reset(),
+            Event::WindowEvent {
+                event: WindowEvent::ModifiersChanged(now_pressed),
+                window_id,
+            } if window_id == window.id() => modifiers = now_pressed,
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Return),
+                                ..
+                            },
+                        ..
+                    },
+                window_id,
+            } if window_id == window.id() && modifiers.alt() => fullscreen.toggle(&window),
+            Event::WindowEvent {
+                event:
+                    WindowEvent::MouseInput {
+                        state,
+                        button: MouseButton::Left,
+                        ..
+                    },
+                window_id,
+            } if window_id == window.id() => dragging = state == ElementState::Pressed,
+            Event::WindowEvent {
+                event: WindowEvent::CursorMoved { position, .. },
+                window_id,
+            } if window_id == window.id() => {
+                if let (true, Some((last_x, last_y))) = (dragging, last_cursor) {
+                    scene
+                        .camera
+                        .drag((position.x - last_x) as f32, (position.y - last_y) as f32);
+                }
+                last_cursor = Some((position.x, position.y));
+            }
+            Event::WindowEvent {
+                event: WindowEvent::MouseWheel { delta, .. },
+                window_id,
+            } if window_id == window.id() => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, lines) => lines,
+                    // Touchpads scroll in pixels. Call a line about 20 of them.
+                    MouseScrollDelta::PixelDelta(pixels) => pixels.y as f32 / 20.0,
+                };
+                scene.camera.zoom(lines);
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Tab),
+                                ..
+                            },
+                        ..
+                    },
+                window_id,
+            } if window_id == window.id() && !minimized => {
+                // The other windows' swapchains belong to this device's queue
+                if !extra_windows.is_empty() {
+                    status!("⚠️ Switching adapters only works with one window");
+                    return;
+                }
+
+                let current_luid = unsafe { device.GetAdapterLuid() };
+                let index = match adapters::next_adapter_index(&factory, current_luid) {
+                    Some(index) => index,
+                    None => return,
+                };
+
+                // Create the new device before touching the old one, so a failure leaves us running where we were
+                let (new_device, description) =
+                    match create_device_at_index(&factory, index, feature_level_request) {
+                        Ok(created) => created,
+                        Err(err) => {
+                            status!("⚠️ Couldn't switch to adapter {}: {}", index, err);
+                            return;
+                        }
+                    };
+                let new_caps = caps::DeviceCaps::query(&new_device);
+                if let Some(flag) = scene_desc.unsupported_flag(&new_caps) {
+                    status!(
+                        "⚠️ Adapter {} can't do {}. Staying on this one.",
+                        index,
+                        flag
+                    );
+                    unsafe {
+                        new_device.destroy();
+                    }
+                    return;
+                }
+
+                status!("Switching to adapter {}: {}", index, description);
+                status!("{}", new_caps.to_string().trim_end());
+                if let Some(shader_model) = new_caps.shader_model {
+                    shaders::set_shader_model(shader_model);
+                }
+
+                // Every object on the old device goes, including the swapchain, since a window only takes one.
+                // None of them can be released while the GPU is still using them.
+                fence.wait_for_last_submission();
+                scene_desc.fill = scene.triangle.fill_mode;
+                let camera = scene.camera;
+                scene.destroy();
+                gpu_timer.destroy();
+                if let Some(mut old_async_compute) = async_compute.take() {
+                    old_async_compute.destroy();
+                }
+                for frame in &mut frames {
+                    frame.destroy();
+                }
+                render_targets.destroy();
+                fence.destroy();
+                unsafe {
+                    depth_buffer.destroy();
+                    dsv_heap.destroy();
+                    swapchain.destroy();
+                    cmd_queue.destroy();
+                    if let Some(info_queue) = info_queue.take() {
+                        info_queue.destroy();
+                    }
+                    device.destroy();
+                }
+                if let Some(mut old_watcher) = budget_watcher.take() {
+                    old_watcher.destroy();
+                }
+
+                device = new_device;
+                info_queue = debug_layer::get_info_queue(&device, break_on_error);
+                budget_watcher =
+                    memory_budget::BudgetWatcher::new(&factory, unsafe { device.GetAdapterLuid() });
+                cmd_queue = create_direct_queue(&device).unwrap_or_else(|err| exit_with_error(err));
+                swapchain = create_swapchain(
+                    &factory,
+                    &device,
+                    cmd_queue,
+                    window_hwnd(&window),
+                    &swapchain_desc,
+                )
+                .unwrap_or_else(|err| exit_with_error(err));
+                if let Some(mode) = hdr_mode {
+                    if !hdr::set_color_space(&swapchain, mode) {
+                        status!("⚠️ Couldn't present in HDR from this adapter");
+                    }
+                }
+
+                render_targets = render_targets::RenderTargets::new(
+                    &device,
+                    &swapchain,
+                    buffer_count,
+                    rtv_format,
+                )
+                .unwrap_or_else(|err| exit_with_error(err));
+                let (new_depth_buffer, new_dsv_heap) = depth::create_depth_buffer(
+                    &device,
+                    swapchain_desc.width,
+                    swapchain_desc.height,
+                )
+                .unwrap_or_else(|err| exit_with_error(err));
+                depth_buffer = new_depth_buffer;
+                dsv_heap = new_dsv_heap;
+
+                frames = (0..buffer_count)
+                    .map(|i| sync::FrameContext::new(&device, &format!("Frame {} Commands", i)))
+                    .collect::<Result<Vec<_>, DxError>>()
+                    .unwrap_or_else(|err| exit_with_error(err));
+                fence = sync::QueueFence::new(&device).unwrap_or_else(|err| exit_with_error(err));
+                scene = create_scene(
+                    &device,
+                    &new_caps,
+                    &cmd_queue,
+                    &mut fence,
+                    &mut frames[0],
+                    &scene_desc,
+                    swapchain_desc.width,
+                    swapchain_desc.height,
+                )
+                .unwrap_or_else(|err| exit_with_error(err));
+                scene.camera = camera;
+                if use_async_compute {
+                    async_compute = Some(
+                        async_compute::AsyncCompute::new(
+                            &device,
+                            scene_desc.shader_dir.as_deref(),
+                            buffer_count,
+                            scene_desc.clear_color,
+                        )
+                        .unwrap_or_else(|err| exit_with_error(err)),
+                    );
+                }
+                gpu_timer = gpu_timer::GpuTimer::new(&device, &cmd_queue, buffer_count)
+                    .unwrap_or_else(|err| exit_with_error(err));
+                gpu_time_ms = 0.0;
+                if let Some(trace) = &mut trace {
+                    trace
+                        .calibrate(&cmd_queue)
+                        .unwrap_or_else(|err| exit_with_error(err));
+                }
+
+                adapter_name = description;
+                window.set_title(&format!("{} — {}", WINDOW_TITLE, adapter_name));
+            }
+            // Focus moving between our own windows keeps us rendering
+            Event::WindowEvent {
+                event: WindowEvent::Focused(now_focused),
+                window_id,
+            } if window_id == window.id()
+                || extra_windows
+                    .iter()
+                    .any(|extra| extra.window.id() == window_id) =>
+            {
+                focused = now_focused
+            }
+            Event::WindowEvent {
+                event: WindowEvent::Resized(size),
+                window_id,
+            } if window_id == window.id() => {
+                // Minimizing reports 0x0, which ResizeBuffers rejects. Keep the old buffers until we're restored.
+                minimized = size.width == 0 || size.height == 0;
+                if minimized {
+                    return;
+                }
+
+                let (width, height) = (size.width, size.height);
+                if (width, height) == (swapchain_desc.width, swapchain_desc.height) {
+                    return;
+                }
+
+                pix::pix_set_marker(&cmd_queue, RESIZE_MARKER_COLOR, "Resize");
+
+                // The swapchain can't resize while anything still references its buffers
+                fence.wait_for_last_submission();
+                render_targets.destroy();
+                unsafe {
+                    depth_buffer.destroy();
+                    dsv_heap.destroy();
+                }
+
+                let hr = unsafe {
+                    swapchain.ResizeBuffers(
+                        buffer_count,
+                        width,
+                        height,
+                        swapchain_desc.format,
+                        swapchain_desc.flags,
+                    )
+                };
+                os_helpers::report_device_removed(&device, hr);
+                check_hr_only!(hr);
+                swapchain_desc.width = width;
+                swapchain_desc.height = height;
+
+                render_targets = render_targets::RenderTargets::new(
+                    &device,
+                    &swapchain,
+                    buffer_count,
+                    rtv_format,
+                )
+                .unwrap_or_else(|err| exit_with_error(err));
+                let (new_depth_buffer, new_dsv_heap) =
+                    depth::create_depth_buffer(&device, width, height)
+                        .unwrap_or_else(|err| exit_with_error(err));
+                depth_buffer = new_depth_buffer;
+                dsv_heap = new_dsv_heap;
+
+                if let Some(raytracer) = &mut scene.raytracer {
+                    raytracer
+                        .resize(&device, width, height)
+                        .unwrap_or_else(|err| exit_with_error(err));
+                }
+                if let Some(msaa) = &mut scene.msaa {
+                    msaa.resize(&device, width, height)
+                        .unwrap_or_else(|err| exit_with_error(err));
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::Resized(size),
+                window_id,
+            } => {
+                if let Some(extra) = extra_windows
+                    .iter_mut()
+                    .find(|extra| extra.window.id() == window_id)
+                {
+                    extra
+                        .resize(&device, &fence, size.width, size.height)
+                        .unwrap_or_else(|err| exit_with_error(err));
+                }
+            }
+            Event::MainEventsCleared if !rendering => (),
+            Event::MainEventsCleared => {
+                if let (Some(watcher), Some(dir)) = (&shader_watcher, &shader_dir) {
+                    if watcher.changed() {
+                        // Pipelines can't be destroyed while a frame in flight still uses them
+                        fence.wait_for_last_submission();
+                        let reloaded = match (&mut scene.raytracer, &mut scene.mesh_shader) {
+                            (Some(raytracer), _) => raytracer.reload(&device, dir),
+                            (None, Some(mesh_shader)) => mesh_shader.reload(&device, dir),
+                            (None, None) => scene.triangle.reload(&device, dir),
+                        };
+                        match reloaded {
+                            Ok(()) => status!("Reloaded shaders from {}", dir.display()),
+                            Err(err) => status!(
+                                "⚠️ Shader reload failed, keeping the last good pipeline: {:?}",
+                                err
+                            ),
+                        }
+                    }
+                }
+
+                // One present a frame waits for vsync: the main window's, or while it's hidden or
+                // minimized, the first extra window's. Otherwise every window would wait in turn.
+                let mut paced = main_window_open && !minimized;
+                for extra in &mut extra_windows {
+                    if !extra.minimized {
+                        let (interval, flags) = if paced {
+                            (0, 0)
+                        } else {
+                            (sync_interval, present_flags)
+                        };
+                        paced = true;
+                        extra.render(&device, &mut scene, &cmd_queue, &mut fence, interval, flags);
+                    }
+                }
+                if minimized || !main_window_open {
+                    return;
+                }
+
+                if let Some(frame_capture) = &mut frame_capture {
+                    frame_capture.before_frame(presents + 1);
+                }
+                if hash_frame.is_some() {
+                    scene.fixed_time = Some(presents as f32 / HASH_FRAME_RATE);
+                }
+
+                let back_buffer_index = swapchain.get_current_back_buffer_index();
+
+                // Don't touch a back buffer (or its context) until the GPU is done with its last frame
+                let wait_start = Instant::now();
+                let frame = &mut frames[back_buffer_index as usize];
+                frame.begin(&fence);
+                if let Some(trace) = &mut trace {
+                    trace.cpu_phase("wait", presents + 1, wait_start);
+                }
+
+                let record_start = Instant::now();
+                let cmd_list = frame.cmd_list;
+                scene.begin_frame(back_buffer_index);
+                if let Some((begin, end)) = gpu_timer.read_timestamps(back_buffer_index) {
+                    gpu_time_ms = gpu_timer.elapsed_ms(begin, end);
+                    if let Some(trace) = &mut trace {
+                        // Frame numbers are approximate here, since skipped and resized frames
+                        // don't keep back buffers in step with presents
+                        trace.gpu_frame(presents + 1, begin, end);
+                    }
+                }
+
+                let back_buffer = render_targets.buffer(back_buffer_index);
+
+                gpu_timer.begin(&cmd_list, back_buffer_index);
+                scene.record(
+                    &cmd_list,
+                    back_buffer,
+                    render_targets.rtv_handle(back_buffer_index),
+                    dsv_heap.start_cpu_descriptor(),
+                    swapchain_desc.width,
+                    swapchain_desc.height,
+                );
+                gpu_timer.end(&cmd_list, back_buffer_index);
+
+                // The back buffer is back in PRESENT by now, with the whole frame in it
+                let screenshot = if std::mem::replace(&mut screenshot_requested, false) {
+                    screenshot::Screenshot::record(&device, &cmd_list, back_buffer)
+                        .map_err(|err| status!("⚠️ Couldn't take a screenshot: {}", err))
+                        .ok()
+                } else {
+                    None
+                };
+                let hash_readback = if hash_frame == Some(presents + 1) {
+                    Some(
+                        screenshot::Screenshot::record(&device, &cmd_list, back_buffer)
+                            .unwrap_or_else(|err| exit_with_error(err)),
+                    )
+                } else {
+                    None
+                };
+                if let Some(trace) = &mut trace {
+                    trace.cpu_phase("record", presents + 1, record_start);
+                }
+
+                let submit_start = Instant::now();
+                if let (Some(async_compute), Some(raytracer)) =
+                    (&mut async_compute, &scene.raytracer)
+                {
+                    async_compute.fill_output(back_buffer_index, raytracer, &cmd_queue, &fence);
+                }
+                frame.submit(&cmd_queue, &mut fence);
+                if let Some(trace) = &mut trace {
+                    trace.cpu_phase("submit", presents + 1, submit_start);
+                }
+
+                if let Some(readback) = hash_readback {
+                    fence.wait_for_last_submission();
+                    print_frame_hash(presents + 1, readback);
+                    *control_flow = ControlFlow::Exit;
+                }
+
+                if let Some(screenshot) = screenshot {
+                    fence.wait_for_last_submission();
+                    let path = screenshot::timestamped_path();
+                    match screenshot.save(&path) {
+                        Ok(()) => status!("Saved a screenshot to {}", path.display()),
+                        Err(err) => status!("⚠️ {}", err),
+                    }
+                }
+
+                let present_start = Instant::now();
+                pix::pix_begin_event(&cmd_queue, PRESENT_EVENT_COLOR, "Present");
+                let hr = swapchain
+                    .as_swapchain0()
+                    .present(sync_interval, present_flags);
+                pix::pix_end_event(&cmd_queue);
+                if let Some(trace) = &mut trace {
+                    trace.cpu_phase("present", presents + 1, present_start);
+                }
+                os_helpers::report_device_removed(&device, hr);
+                check_hr_only!(hr);
+
+                if let Some(frame_limiter) = &mut frame_limiter {
+                    frame_limiter.wait();
+                }
+                if let Some(budget_watcher) = &mut budget_watcher {
+                    budget_watcher.poll();
+                }
+
+                if let Some(info_queue) = &info_queue {
+                    debug_layer::drain_info_queue(info_queue);
+                }
+
+                if let Some(frame_time) = frame_timer.tick() {
+                    window.set_title(&format!(
+                        "{} — {} — {} — GPU {:.2} ms",
+                        WINDOW_TITLE,
+                        adapter_name,
+                        frame_timer::fps_text(frame_time),
+                        gpu_time_ms
+                    ));
+                }
+
+                presents += 1;
+                if let Some(frame_capture) = &mut frame_capture {
+                    if frame_capture.after_frame(presents) && exit_after_capture {
+                        *control_flow = ControlFlow::Exit;
+                    }
+                }
+                if presents == present_count {
+                    let average = run_start.elapsed() / presents;
+                    status!(
+                        "Presented {} frames, averaging {}",
+                        presents,
+                        frame_timer::fps_text(average)
+                    );
+                    *control_flow = ControlFlow::Exit;
+                }
+            }
+            Event::LoopDestroyed => {
+                // Fullscreen covers the whole monitor, so save where it goes back to instead
+                let state = match fullscreen.windowed_rect() {
+                    Some((position, size)) => Some(window_state::WindowState {
+                        x: position.x,
+                        y: position.y,
+                        width: size.width,
+                        height: size.height,
+                    }),
+                    None => window_state::current(&window),
+                };
+                if let Some(state) = state {
+                    window_state::save(&state);
+                }
+
+                // Let the GPU finish before the process tears everything down
+                fence.wait_for_last_submission();
+                for extra in &mut extra_windows {
+                    extra.destroy();
+                }
+                if let Some(trace) = &trace {
+                    trace.save();
+                }
+                if let Some(async_compute) = &mut async_compute {
+                    async_compute.destroy();
+                }
+
+                release_renderer(
+                    &mut scene,
+                    &mut gpu_timer,
+                    &mut frames,
+                    &mut render_targets,
+                    (depth_buffer, dsv_heap),
+                    budget_watcher.take(),
+                    &mut fence,
+                    Some(swapchain),
+                    cmd_queue,
+                    info_queue.take(),
+                    device,
+                );
+            }
             _ => (),
         }
     });
@@ -0,0 +1,143 @@
+//! Watching the adapter's local video memory budget while we run, so running low shows up
+//! in the log before allocations start failing
+
+use crate::os_helpers;
+use crate::status;
+use crate::MiB;
+
+use winapi::shared::dxgi1_4::*;
+use winapi::shared::ntdef::LUID;
+use winapi::shared::winerror::*;
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::winbase::WAIT_OBJECT_0;
+use winapi::Interface;
+
+use std::mem::zeroed;
+use std::time::{Duration, Instant};
+
+/// How often `poll` reads the usage. Querying isn't free, so this isn't every frame.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Warn once usage passes this fraction of the budget
+const WARN_FRACTION: f64 = 0.9;
+
+fn mib(bytes: u64) -> u64 {
+    bytes / MiB as u64
+}
+
+pub struct BudgetWatcher {
+    adapter: d3d12::WeakPtr<IDXGIAdapter3>,
+    /// Signaled by the OS whenever it revises the budget
+    event: d3d12::Event,
+    /// Only set if registering `event` worked
+    cookie: Option<u32>,
+    budget: u64,
+    last_check: Instant,
+    /// Set while usage is over `WARN_FRACTION`, so we warn once per crossing instead of every check
+    warned: bool,
+}
+
+impl BudgetWatcher {
+    /// Watch the adapter with `luid`, which is the device's. `None` if it has no budget to watch.
+    pub fn new(factory: &d3d12::Factory4, luid: LUID) -> Option<Self> {
+        let adapter = unsafe {
+            let mut adapter = d3d12::WeakPtr::<IDXGIAdapter3>::null();
+            let hr = factory.EnumAdapterByLuid(luid, &IDXGIAdapter3::uuidof(), adapter.mut_void());
+            if FAILED(hr) {
+                status!(
+                    "⚠️ Can't watch the video memory budget: {}",
+                    os_helpers::hr_string(hr)
+                );
+                return None;
+            }
+            adapter
+        };
+
+        let event = d3d12::Event::create(false, false);
+        let mut cookie = 0;
+        let hr = unsafe {
+            adapter.RegisterVideoMemoryBudgetChangeNotificationEvent(event.0, &mut cookie)
+        };
+        let cookie = if SUCCEEDED(hr) {
+            Some(cookie)
+        } else {
+            status!(
+                "⚠️ Budget changes won't be logged: {}",
+                os_helpers::hr_string(hr)
+            );
+            None
+        };
+
+        let mut watcher = BudgetWatcher {
+            adapter,
+            event,
+            cookie,
+            budget: 0,
+            last_check: Instant::now(),
+            warned: false,
+        };
+        watcher.budget = watcher.query().map_or(0, |info| info.Budget);
+
+        Some(watcher)
+    }
+
+    fn query(&self) -> Option<DXGI_QUERY_VIDEO_MEMORY_INFO> {
+        unsafe {
+            let mut info: DXGI_QUERY_VIDEO_MEMORY_INFO = zeroed();
+            let hr =
+                self.adapter
+                    .QueryVideoMemoryInfo(0, DXGI_MEMORY_SEGMENT_GROUP_LOCAL, &mut info);
+            if SUCCEEDED(hr) {
+                Some(info)
+            } else {
+                None
+            }
+        }
+    }
+
+    /// Call once per frame. Logs budget changes as soon as the OS makes them,
+    /// and warns when usage gets close to the budget.
+    pub fn poll(&mut self) {
+        // Don't wait at all, just see whether the event has fired since we last looked
+        let budget_changed = self.event.wait(0) == WAIT_OBJECT_0;
+        if !budget_changed && self.last_check.elapsed() < CHECK_INTERVAL {
+            return;
+        }
+        self.last_check = Instant::now();
+
+        let info = match self.query() {
+            Some(info) => info,
+            None => return,
+        };
+
+        if info.Budget != self.budget {
+            status!(
+                "Video memory budget changed: {} MiB -> {} MiB",
+                mib(self.budget),
+                mib(info.Budget)
+            );
+            self.budget = info.Budget;
+        }
+
+        let near_budget = info.CurrentUsage as f64 > info.Budget as f64 * WARN_FRACTION;
+        if near_budget && !self.warned {
+            status!(
+                "⚠️ Using {} MiB of a {} MiB video memory budget. Allocations may start failing.",
+                mib(info.CurrentUsage),
+                mib(info.Budget)
+            );
+        }
+        self.warned = near_budget;
+    }
+
+    pub fn destroy(&mut self) {
+        unsafe {
+            if let Some(cookie) = self.cookie {
+                self.adapter
+                    .UnregisterVideoMemoryBudgetChangeNotification(cookie);
+            }
+            CloseHandle(self.event.0);
+            self.adapter.destroy();
+        }
+    }
+}
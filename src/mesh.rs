@@ -0,0 +1,141 @@
+//! Geometry to draw and trace: the built-in triangle, or a mesh from an OBJ file
+
+use std::fs;
+use std::path::Path;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub color: [f32; 4],
+}
+
+/// An indexed triangle list
+pub struct Mesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    /// The red/green/blue triangle we draw without `--mesh`
+    pub fn triangle() -> Self {
+        Mesh {
+            vertices: vec![
+                Vertex {
+                    position: [0.0, 0.5, 0.0],
+                    color: [1.0, 0.0, 0.0, 1.0],
+                },
+                Vertex {
+                    position: [0.5, -0.5, 0.0],
+                    color: [0.0, 1.0, 0.0, 1.0],
+                },
+                Vertex {
+                    position: [-0.5, -0.5, 0.0],
+                    color: [0.0, 0.0, 1.0, 1.0],
+                },
+            ],
+            indices: vec![0, 1, 2],
+        }
+    }
+
+    /// Read the positions and faces out of an OBJ file. Everything else in it is ignored.
+    /// The mesh is centered and scaled to the built-in triangle's size, so the default camera frames it.
+    pub fn load_obj(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Couldn't read {}: {}", path.display(), err))?;
+
+        let mut positions: Vec<[f32; 3]> = vec![];
+        let mut indices = vec![];
+        for (line_index, line) in text.lines().enumerate() {
+            let error =
+                |message: &str| format!("{}:{}: {}", path.display(), line_index + 1, message);
+
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("v") => {
+                    let mut position = [0.0; 3];
+                    for coord in position.iter_mut() {
+                        *coord = words
+                            .next()
+                            .and_then(|word| word.parse().ok())
+                            .ok_or_else(|| error("expected 3 numbers after \"v\""))?;
+                    }
+                    // OBJ is right-handed, and we're left-handed
+                    position[2] = -position[2];
+                    positions.push(position);
+                }
+                Some("f") => {
+                    let corners = words
+                        .map(|word| parse_face_index(word, positions.len()))
+                        .collect::<Option<Vec<u32>>>()
+                        .ok_or_else(|| error("face refers to a vertex that doesn't exist"))?;
+                    if corners.len() < 3 {
+                        return Err(error("faces need at least 3 vertices"));
+                    }
+
+                    // Fan out anything bigger than a triangle
+                    for i in 1..corners.len() - 1 {
+                        indices.extend_from_slice(&[corners[0], corners[i], corners[i + 1]]);
+                    }
+                }
+                // Normals, texture coordinates, materials, groups
+                _ => {}
+            }
+        }
+
+        if indices.is_empty() {
+            return Err(format!("{} doesn't have any faces", path.display()));
+        }
+
+        Ok(Mesh {
+            vertices: fit_to_unit_size(&positions),
+            indices,
+        })
+    }
+}
+
+/// The position index out of one `f` corner: `v`, `v/vt`, `v//vn`, or `v/vt/vn`.
+/// OBJ counts from 1, or back from the end when negative.
+fn parse_face_index(word: &str, position_count: usize) -> Option<u32> {
+    let index: i64 = word.split('/').next()?.parse().ok()?;
+    let index = if index < 0 {
+        position_count as i64 + index
+    } else {
+        index - 1
+    };
+
+    if (0..position_count as i64).contains(&index) {
+        Some(index as u32)
+    } else {
+        None
+    }
+}
+
+/// Center `positions` on the origin with their largest extent at 1, and color them by where they ended up
+fn fit_to_unit_size(positions: &[[f32; 3]]) -> Vec<Vertex> {
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for position in positions {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(position[axis]);
+            max[axis] = max[axis].max(position[axis]);
+        }
+    }
+
+    let extent = (0..3).map(|axis| max[axis] - min[axis]).fold(0.0, f32::max);
+    let scale = if extent > 0.0 { 1.0 / extent } else { 1.0 };
+
+    positions
+        .iter()
+        .map(|position| {
+            let mut fitted = [0.0; 3];
+            for axis in 0..3 {
+                fitted[axis] = (position[axis] - (min[axis] + max[axis]) / 2.0) * scale;
+            }
+            Vertex {
+                position: fitted,
+                color: [fitted[0] + 0.5, fitted[1] + 0.5, fitted[2] + 0.5, 1.0],
+            }
+        })
+        .collect()
+}
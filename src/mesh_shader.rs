@@ -0,0 +1,349 @@
+//! Hello triangle again, through a mesh shader: no input assembler or vertex buffers,
+//! just one threadgroup writing out the triangle's vertices itself
+
+use crate::check_hr_err;
+use crate::d3d12_ext::*;
+use crate::os_helpers::{self, DxError};
+use crate::root_sig::RootSignatureBuilder;
+use crate::shaders;
+
+use winapi::shared::dxgiformat::*;
+use winapi::shared::dxgitype::*;
+use winapi::shared::minwindef::{FALSE, TRUE};
+use winapi::shared::winerror::*;
+use winapi::um::d3d12::*;
+use winapi::Interface;
+
+use std::mem::{size_of, zeroed};
+use std::path::Path;
+use std::ptr;
+
+/// The same triangle as `Mesh::triangle`, with its vertices baked into the shader
+const MESH_SHADER_HLSL: &str = r#"
+cbuffer FrameConstants : register(b0) {
+    row_major float4x4 view_proj;
+    float angle;
+};
+
+struct MsOutput {
+    float4 position : SV_Position;
+    float4 color    : COLOR;
+};
+
+static const float3 POSITIONS[3] = {
+    float3( 0.0,  0.5, 0.0),
+    float3( 0.5, -0.5, 0.0),
+    float3(-0.5, -0.5, 0.0),
+};
+
+static const float4 COLORS[3] = {
+    float4(1.0, 0.0, 0.0, 1.0),
+    float4(0.0, 1.0, 0.0, 1.0),
+    float4(0.0, 0.0, 1.0, 1.0),
+};
+
+// One thread per vertex
+[outputtopology("triangle")]
+[numthreads(3, 1, 1)]
+void MSMain(
+    uint thread : SV_GroupThreadID,
+    out vertices MsOutput verts[3],
+    out indices uint3 tris[1]
+) {
+    SetMeshOutputCounts(3, 1);
+
+    float3 position = POSITIONS[thread];
+    float s = sin(angle);
+    float c = cos(angle);
+    float2 rotated = float2(c * position.x - s * position.y, s * position.x + c * position.y);
+    verts[thread].position = mul(view_proj, float4(rotated, position.z, 1.0));
+    verts[thread].color = COLORS[thread];
+
+    if (thread == 0) {
+        tris[0] = uint3(0, 1, 2);
+    }
+}
+
+float4 PSMain(MsOutput input) : SV_Target {
+    return input.color;
+}
+"#;
+
+/// Get a shader blob, reporting compile errors through our logging
+fn mesh_shader(
+    shader_dir: Option<&Path>,
+    name: &str,
+    entry: &str,
+    target: &str,
+) -> Result<Vec<u8>, DxError> {
+    shaders::load_or_compile(
+        shader_dir,
+        name,
+        "mesh_shader",
+        MESH_SHADER_HLSL,
+        entry,
+        target,
+    )
+    .map_err(|err| {
+        log::error!("{}", err);
+        DxError {
+            hr: E_FAIL,
+            location: format!("{}:{}", file!(), line!()),
+            call: format!("shaders::load_or_compile({:?}, {:?})", name, target),
+        }
+    })
+}
+
+fn bytecode(blob: &[u8]) -> D3D12_SHADER_BYTECODE {
+    D3D12_SHADER_BYTECODE {
+        pShaderBytecode: blob.as_ptr() as *const _,
+        BytecodeLength: blob.len(),
+    }
+}
+
+/// One entry in a pipeline state stream. d3d12.h aligns these to pointers, so each one is padded to 8 bytes.
+#[repr(C, align(8))]
+struct Subobject<T> {
+    kind: D3D12_PIPELINE_STATE_SUBOBJECT_TYPE,
+    desc: T,
+}
+
+fn subobject<T>(kind: D3D12_PIPELINE_STATE_SUBOBJECT_TYPE, desc: T) -> Subobject<T> {
+    Subobject { kind, desc }
+}
+
+/// Everything a mesh shader PSO sets. Anything left out of a stream keeps its default.
+#[repr(C)]
+struct MeshPipelineStream {
+    root_sig: Subobject<*mut ID3D12RootSignature>,
+    ms: Subobject<D3D12_SHADER_BYTECODE>,
+    ps: Subobject<D3D12_SHADER_BYTECODE>,
+    rasterizer: Subobject<D3D12_RASTERIZER_DESC>,
+    blend: Subobject<D3D12_BLEND_DESC>,
+    sample_mask: Subobject<u32>,
+    depth_stencil: Subobject<D3D12_DEPTH_STENCIL_DESC>,
+    rtv_formats: Subobject<D3D12_RT_FORMAT_ARRAY>,
+    sample_desc: Subobject<DXGI_SAMPLE_DESC>,
+}
+
+/// Load the mesh and pixel shaders, and build a PSO from them through the pipeline stream API.
+/// `CreateGraphicsPipelineState` has no slot for a mesh shader, so this is the only way.
+fn create_pipeline(
+    device: &d3d12::Device,
+    shader_dir: Option<&Path>,
+    rtv_format: DXGI_FORMAT,
+    sample_count: u32,
+    conservative: bool,
+) -> Result<(d3d12::RootSignature, d3d12::PipelineState), DxError> {
+    let ms_target = shaders::default_target("ms");
+    let ps_target = shaders::target_at_least("ps", D3D_SHADER_MODEL_6_5);
+    let ms_blob = mesh_shader(shader_dir, "mesh_ms", "MSMain", &ms_target)?;
+    let ps_blob = mesh_shader(shader_dir, "mesh_ps", "PSMain", &ps_target)?;
+
+    let root_sig = RootSignatureBuilder::new()
+        .add_cbv(0)
+        .build(device, "Mesh Shader Root Signature")?;
+
+    let mut blend: D3D12_BLEND_DESC = unsafe { zeroed() };
+    for target in blend.RenderTarget.iter_mut() {
+        target.RenderTargetWriteMask = D3D12_COLOR_WRITE_ENABLE_ALL as u8;
+    }
+    let mut depth_stencil: D3D12_DEPTH_STENCIL_DESC = unsafe { zeroed() };
+    depth_stencil.DepthEnable = FALSE;
+    depth_stencil.StencilEnable = FALSE;
+    let mut rtv_formats: D3D12_RT_FORMAT_ARRAY = unsafe { zeroed() };
+    rtv_formats.NumRenderTargets = 1;
+    rtv_formats.RTFormats[0] = rtv_format;
+
+    let mut stream = MeshPipelineStream {
+        root_sig: subobject(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_ROOT_SIGNATURE,
+            root_sig.as_mut_ptr(),
+        ),
+        ms: subobject(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_MS, bytecode(&ms_blob)),
+        ps: subobject(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_PS, bytecode(&ps_blob)),
+        rasterizer: subobject(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_RASTERIZER,
+            D3D12_RASTERIZER_DESC {
+                FillMode: D3D12_FILL_MODE_SOLID,
+                // The camera can orbit around to the triangle's back
+                CullMode: D3D12_CULL_MODE_NONE,
+                FrontCounterClockwise: FALSE,
+                DepthBias: D3D12_DEFAULT_DEPTH_BIAS as i32,
+                DepthBiasClamp: D3D12_DEFAULT_DEPTH_BIAS_CLAMP,
+                SlopeScaledDepthBias: D3D12_DEFAULT_SLOPE_SCALED_DEPTH_BIAS,
+                DepthClipEnable: TRUE,
+                MultisampleEnable: if sample_count > 1 { TRUE } else { FALSE },
+                AntialiasedLineEnable: FALSE,
+                ForcedSampleCount: 0,
+                ConservativeRaster: if conservative {
+                    D3D12_CONSERVATIVE_RASTERIZATION_MODE_ON
+                } else {
+                    D3D12_CONSERVATIVE_RASTERIZATION_MODE_OFF
+                },
+            },
+        ),
+        blend: subobject(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_BLEND, blend),
+        sample_mask: subobject(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_SAMPLE_MASK, u32::MAX),
+        depth_stencil: subobject(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_DEPTH_STENCIL,
+            depth_stencil,
+        ),
+        rtv_formats: subobject(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_RENDER_TARGET_FORMATS,
+            rtv_formats,
+        ),
+        sample_desc: subobject(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_SAMPLE_DESC,
+            DXGI_SAMPLE_DESC {
+                Count: sample_count,
+                Quality: 0,
+            },
+        ),
+    };
+    let stream_desc = D3D12_PIPELINE_STATE_STREAM_DESC {
+        SizeInBytes: size_of::<MeshPipelineStream>(),
+        pPipelineStateSubobjectStream: &mut stream as *mut _ as *mut _,
+    };
+
+    // Pipeline streams are on ID3D12Device2
+    let device2 = match unsafe { device.cast::<ID3D12Device2>() } {
+        (device2, hr) if SUCCEEDED(hr) => device2,
+        (_, hr) => {
+            unsafe {
+                root_sig.destroy();
+            }
+            return Err(DxError {
+                hr,
+                location: format!("{}:{}", file!(), line!()),
+                call: "ID3D12Device::QueryInterface(ID3D12Device2)".to_string(),
+            });
+        }
+    };
+    let (pso, hr) = unsafe {
+        let mut pso = d3d12::PipelineState::null();
+        let hr = device2.CreatePipelineState(
+            &stream_desc,
+            &ID3D12PipelineState::uuidof(),
+            pso.mut_void(),
+        );
+        device2.destroy();
+
+        (pso, hr)
+    };
+    if FAILED(hr) {
+        unsafe {
+            root_sig.destroy();
+        }
+    }
+    let pso = check_hr_err!((pso, hr));
+    os_helpers::set_name(&pso, "Mesh Shader PSO");
+
+    Ok((root_sig, pso))
+}
+
+/// The built-in triangle, drawn by a mesh shader instead of the input assembler
+pub struct MeshShaderTriangle {
+    root_sig: d3d12::RootSignature,
+    pso: d3d12::PipelineState,
+    /// What the pipeline draws into, so `reload` can build it the same way
+    rtv_format: DXGI_FORMAT,
+    sample_count: u32,
+    conservative: bool,
+}
+
+impl MeshShaderTriangle {
+    /// Check `caps::query_mesh_shader_tier` first. Devices without mesh shaders fail here.
+    pub fn new(
+        device: &d3d12::Device,
+        shader_dir: Option<&Path>,
+        rtv_format: DXGI_FORMAT,
+        sample_count: u32,
+        conservative: bool,
+    ) -> Result<Self, DxError> {
+        let (root_sig, pso) =
+            create_pipeline(device, shader_dir, rtv_format, sample_count, conservative)?;
+
+        Ok(MeshShaderTriangle {
+            root_sig,
+            pso,
+            rtv_format,
+            sample_count,
+            conservative,
+        })
+    }
+
+    /// Rebuild the pipeline from `shader_dir`. If that fails, the old pipeline stays.
+    /// Nothing in flight can still be using the old pipeline.
+    pub fn reload(&mut self, device: &d3d12::Device, shader_dir: &Path) -> Result<(), DxError> {
+        let (root_sig, pso) = create_pipeline(
+            device,
+            Some(shader_dir),
+            self.rtv_format,
+            self.sample_count,
+            self.conservative,
+        )?;
+        self.destroy();
+        self.root_sig = root_sig;
+        self.pso = pso;
+
+        Ok(())
+    }
+
+    /// Draw into `rtv`, covering a `width` x `height` target.
+    /// `constants` is the GPU address of a `TriangleConstants`.
+    pub fn draw(
+        &self,
+        cmd_list: &d3d12::GraphicsCommandList,
+        rtv: d3d12::CpuDescriptor,
+        width: u32,
+        height: u32,
+        constants: u64,
+    ) {
+        let viewport = D3D12_VIEWPORT {
+            TopLeftX: 0.0,
+            TopLeftY: 0.0,
+            Width: width as f32,
+            Height: height as f32,
+            MinDepth: D3D12_MIN_DEPTH,
+            MaxDepth: D3D12_MAX_DEPTH,
+        };
+        let scissor = D3D12_RECT {
+            left: 0,
+            top: 0,
+            right: width as i32,
+            bottom: height as i32,
+        };
+
+        // Checked when the pipeline was created, so this only fails if the device lost mesh shaders since
+        let cmd_list6 = match unsafe { cmd_list.cast::<ID3D12GraphicsCommandList6>() } {
+            (cmd_list6, hr) if SUCCEEDED(hr) => cmd_list6,
+            (_, hr) => {
+                log::error!(
+                    "ID3D12GraphicsCommandList6 is missing: {}",
+                    os_helpers::hr_string(hr)
+                );
+                return;
+            }
+        };
+
+        cmd_list.set_graphics_root_signature(self.root_sig);
+        cmd_list.set_pipeline_state(self.pso);
+        cmd_list.set_graphics_root_constant_buffer_view(0, constants);
+        unsafe {
+            cmd_list.OMSetRenderTargets(1, &rtv, FALSE, ptr::null());
+            cmd_list.RSSetViewports(1, &viewport);
+            cmd_list.RSSetScissorRects(1, &scissor);
+            // One threadgroup makes the whole triangle
+            cmd_list6.DispatchMesh(1, 1, 1);
+            cmd_list6.destroy();
+        }
+    }
+
+    pub fn destroy(&mut self) {
+        unsafe {
+            self.pso.destroy();
+            self.root_sig.destroy();
+        }
+    }
+}
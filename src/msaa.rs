@@ -0,0 +1,202 @@
+//! A multisampled render target to draw into, resolved into the back buffer each frame.
+//! Flip-model swapchains can't have multisampled buffers themselves.
+
+use crate::check_hr_err;
+use crate::commands;
+use crate::os_helpers::{self, DxError};
+use crate::render_targets;
+
+use winapi::shared::dxgiformat::DXGI_FORMAT;
+use winapi::shared::dxgitype::*;
+use winapi::um::d3d12::*;
+use winapi::Interface;
+
+use std::mem::zeroed;
+
+pub struct MsaaTarget {
+    texture: d3d12::Resource,
+    rtv_heap: d3d12::DescriptorHeap,
+    /// The back buffer's format, which the texture resolves as
+    format: DXGI_FORMAT,
+    rtv_format: DXGI_FORMAT,
+    sample_count: u32,
+    clear_color: [f32; 4],
+}
+
+/// Create the texture in RESOLVE_SOURCE, which is where each frame leaves it
+fn create_texture(
+    device: &d3d12::Device,
+    format: DXGI_FORMAT,
+    rtv_format: DXGI_FORMAT,
+    sample_count: u32,
+    width: u32,
+    height: u32,
+    clear_color: [f32; 4],
+) -> Result<d3d12::Resource, DxError> {
+    let heap_props = D3D12_HEAP_PROPERTIES {
+        Type: D3D12_HEAP_TYPE_DEFAULT,
+        CPUPageProperty: D3D12_CPU_PAGE_PROPERTY_UNKNOWN,
+        MemoryPoolPreference: D3D12_MEMORY_POOL_UNKNOWN,
+        CreationNodeMask: 0,
+        VisibleNodeMask: 0,
+    };
+    let resource_desc = D3D12_RESOURCE_DESC {
+        Dimension: D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+        Alignment: 0,
+        Width: width as u64,
+        Height: height,
+        DepthOrArraySize: 1,
+        MipLevels: 1,
+        Format: render_targets::texture_format(format, rtv_format),
+        SampleDesc: DXGI_SAMPLE_DESC {
+            Count: sample_count,
+            Quality: 0,
+        },
+        Layout: D3D12_TEXTURE_LAYOUT_UNKNOWN,
+        Flags: D3D12_RESOURCE_FLAG_ALLOW_RENDER_TARGET,
+    };
+
+    // Matching the clear we do each frame lets the driver use its fast-clear path
+    let clear_value = unsafe {
+        let mut clear_value: D3D12_CLEAR_VALUE = zeroed();
+        clear_value.Format = rtv_format;
+        *clear_value.u.Color_mut() = clear_color;
+
+        clear_value
+    };
+
+    let texture = check_hr_err! {
+        unsafe {
+            let mut texture = d3d12::Resource::null();
+            let hr = device.CreateCommittedResource(
+                &heap_props,
+                D3D12_HEAP_FLAG_NONE,
+                &resource_desc,
+                D3D12_RESOURCE_STATE_RESOLVE_SOURCE,
+                &clear_value,
+                &ID3D12Resource::uuidof(),
+                texture.mut_void(),
+            );
+
+            (texture, hr)
+        }
+    };
+    os_helpers::set_name(&texture, &format!("MSAA {}x Target", sample_count));
+
+    Ok(texture)
+}
+
+impl MsaaTarget {
+    /// A target to resolve into `format` back buffers, drawn into through `rtv_format` views
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &d3d12::Device,
+        format: DXGI_FORMAT,
+        rtv_format: DXGI_FORMAT,
+        sample_count: u32,
+        width: u32,
+        height: u32,
+        clear_color: [f32; 4],
+    ) -> Result<Self, DxError> {
+        let rtv_heap = check_hr_err!(device.create_descriptor_heap(
+            1,
+            d3d12::DescriptorHeapType::Rtv,
+            d3d12::DescriptorHeapFlags::empty(),
+            0,
+        ));
+        os_helpers::set_name(&rtv_heap, "MSAA RTV Heap");
+
+        let mut target = MsaaTarget {
+            texture: d3d12::Resource::null(),
+            rtv_heap,
+            format,
+            rtv_format,
+            sample_count,
+            clear_color,
+        };
+        if let Err(err) = target.resize(device, width, height) {
+            unsafe {
+                rtv_heap.destroy();
+            }
+            return Err(err);
+        }
+
+        Ok(target)
+    }
+
+    pub fn texture(&self) -> d3d12::Resource {
+        self.texture
+    }
+
+    pub fn rtv(&self) -> d3d12::CpuDescriptor {
+        self.rtv_heap.start_cpu_descriptor()
+    }
+
+    /// Recreate the texture at the new size. Nothing in flight can still be using it.
+    pub fn resize(
+        &mut self,
+        device: &d3d12::Device,
+        width: u32,
+        height: u32,
+    ) -> Result<(), DxError> {
+        if !self.texture.is_null() {
+            unsafe {
+                self.texture.destroy();
+            }
+            self.texture = d3d12::Resource::null();
+        }
+        self.texture = create_texture(
+            device,
+            self.format,
+            self.rtv_format,
+            self.sample_count,
+            width,
+            height,
+            self.clear_color,
+        )?;
+
+        let rtv_desc = D3D12_RENDER_TARGET_VIEW_DESC {
+            Format: self.rtv_format,
+            ViewDimension: D3D12_RTV_DIMENSION_TEXTURE2DMS,
+            ..unsafe { zeroed() }
+        };
+        unsafe {
+            device.CreateRenderTargetView(self.texture.as_mut_ptr(), &rtv_desc, self.rtv());
+        }
+
+        Ok(())
+    }
+
+    /// Resolve the texture into `back_buffer`, which starts and ends in PRESENT.
+    /// The texture has to be back in RESOLVE_SOURCE already.
+    pub fn resolve(&self, cmd_list: &d3d12::GraphicsCommandList, back_buffer: d3d12::Resource) {
+        commands::transition(
+            cmd_list,
+            back_buffer,
+            D3D12_RESOURCE_STATE_PRESENT,
+            D3D12_RESOURCE_STATE_RESOLVE_DEST,
+        );
+        unsafe {
+            cmd_list.ResolveSubresource(
+                back_buffer.as_mut_ptr(),
+                0,
+                self.texture.as_mut_ptr(),
+                0,
+                self.format,
+            );
+        }
+        commands::transition(
+            cmd_list,
+            back_buffer,
+            D3D12_RESOURCE_STATE_RESOLVE_DEST,
+            D3D12_RESOURCE_STATE_PRESENT,
+        );
+    }
+
+    pub fn destroy(&mut self) {
+        unsafe {
+            self.texture.destroy();
+            self.rtv_heap.destroy();
+        }
+    }
+}
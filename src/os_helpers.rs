@@ -1,9 +1,13 @@
 use winapi::shared::winerror::*;
 
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
 #[macro_export]
 macro_rules! check_hr {
     ($call:expr) => {{
-        let (obj, _hr) = crate::check_hr2!($call);
+        let (obj, _hr) = $crate::check_hr2!($call);
         obj
     }};
 }
@@ -12,46 +16,291 @@ macro_rules! check_hr {
 macro_rules! check_hr2 {
     ($call:expr) => {{
         let (obj, hr): (_, d3d12::HRESULT) = $call;
-        if SUCCEEDED(hr) {
-            // Log nothing, we're good :)
+        let location = format!("{}:{}", file!(), line!());
+        $crate::os_helpers::log_hr(hr, &location, stringify!($call));
+        (obj, hr)
+    }};
+}
+
+/// Like `check_hr!`, for calls that only return an `HRESULT`. Evaluates to that `HRESULT`.
+#[macro_export]
+macro_rules! check_hr_only {
+    ($call:expr) => {{
+        let hr: d3d12::HRESULT = $call;
+        let location = format!("{}:{}", file!(), line!());
+        $crate::os_helpers::log_hr(hr, &location, stringify!($call));
+        hr
+    }};
+}
+
+/// Like `check_hr!`, but returns early with a `DxError` when the call fails
+#[macro_export]
+macro_rules! check_hr_err {
+    ($call:expr) => {{
+        let (obj, hr): (_, d3d12::HRESULT) = $call;
+        let location = format!("{}:{}", file!(), line!());
+        if !winapi::shared::winerror::SUCCEEDED(hr) {
+            return Err($crate::os_helpers::DxError {
+                hr,
+                location,
+                call: stringify!($call).to_string(),
+            });
+        }
+        $crate::os_helpers::log_hr(hr, &location, stringify!($call));
+        obj
+    }};
+}
+
+/// `println!` for progress and status lines. They go to stderr while stdout is for machine-readable output.
+#[macro_export]
+macro_rules! status {
+    ($($arg:tt)*) => {{
+        if $crate::os_helpers::status_to_stderr() {
+            eprintln!($($arg)*);
         } else {
-            let location = format!("{}:{}", file!(), line!());
-            let call_site = format!("\n{}", stringify!($call)).replace("\n", "\n\t");
-            println!(
-                "{location}: {hr}:\n{call}",
-                location = location,
-                hr = crate::os_helpers::hr_string(hr),
-                call = call_site
-            );
+            println!($($arg)*);
         }
-        (obj, hr)
     }};
 }
 
+static VERBOSITY: AtomicU32 = AtomicU32::new(0);
+static STATUS_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Send `status!` lines to stderr, e.g. so `--format json` leaves nothing but JSON on stdout
+pub fn set_status_to_stderr(enabled: bool) {
+    STATUS_TO_STDERR.store(enabled, Ordering::Relaxed);
+}
+
+pub fn status_to_stderr() -> bool {
+    STATUS_TO_STDERR.load(Ordering::Relaxed)
+}
+
+/// How chatty `check_hr!` should be:
+///     0 - Only failures
+///     1 - Also the HRESULT of successful calls
+///     2 - Also the call site of successful calls
+pub fn set_verbosity(level: u32) {
+    VERBOSITY.store(level, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> u32 {
+    VERBOSITY.load(Ordering::Relaxed)
+}
+
+/// Report the result of a call, based on the current verbosity
+pub fn log_hr(hr: d3d12::HRESULT, location: &str, call: &str) {
+    let call_site = format!("\n{}", call).replace("\n", "\n\t");
+    if !SUCCEEDED(hr) {
+        log::error!("{}: {}:{}", location, hr_string(hr), call_site);
+    } else if verbosity() >= 2 {
+        log::trace!("{}: {}:{}", location, hr_string(hr), call_site);
+    } else {
+        log::trace!("{}: {}", location, hr_string(hr));
+    }
+}
+
+/// If `hr` means the GPU went away, ask the device why and report both
+pub fn report_device_removed(device: &d3d12::Device, hr: d3d12::HRESULT) {
+    if hr == DXGI_ERROR_DEVICE_REMOVED || hr == DXGI_ERROR_DEVICE_RESET {
+        let reason = unsafe { device.GetDeviceRemovedReason() };
+        log::error!(
+            "Device removed: {}\n\tReason: {}",
+            hr_string(hr),
+            hr_string(reason)
+        );
+
+        crate::debug_layer::report_dred(device);
+    }
+}
+
+/// A flag Ctrl+C sets, instead of killing the process mid-frame, so the caller can drain the GPU and exit.
+/// `wake` runs on the handler's thread after the flag is set, to rouse a loop that's waiting for events.
+/// A second Ctrl+C exits right away, in case shutting down is what's stuck.
+pub fn ctrl_c_flag(wake: impl Fn() + Send + 'static) -> Arc<AtomicBool> {
+    let flag = Arc::new(AtomicBool::new(false));
+    let handler_flag = flag.clone();
+    let result = ctrlc::set_handler(move || {
+        if handler_flag.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+        status!("Ctrl+C: shutting down once the GPU is idle. Press it again to exit now.");
+        wake();
+    });
+    if let Err(err) = result {
+        status!(
+            "⚠️ Couldn't install a Ctrl+C handler, so Ctrl+C won't shut down cleanly: {}",
+            err
+        );
+    }
+
+    flag
+}
+
+/// Decode a fixed-size UTF-16 buffer from a Windows struct, stopping at the first NUL.
+/// Drivers fill these in, so bad surrogates become U+FFFD instead of an error.
+pub fn string_from_wide(wide: &[u16]) -> String {
+    let len = wide.iter().position(|&c| c == 0).unwrap_or(wide.len());
+    String::from_utf16_lossy(&wide[..len])
+}
+
+/// Whether we're running over Remote Desktop
+pub fn remote_session() -> bool {
+    use winapi::um::winuser::{GetSystemMetrics, SM_REMOTESESSION};
+
+    unsafe { GetSystemMetrics(SM_REMOTESESSION) != 0 }
+}
+
+/// Whether we're in session 0, where services run without an interactive desktop
+pub fn in_session_zero() -> bool {
+    use winapi::um::processthreadsapi::{GetCurrentProcessId, ProcessIdToSessionId};
+
+    let mut session_id = 0;
+    let found = unsafe { ProcessIdToSessionId(GetCurrentProcessId(), &mut session_id) };
+
+    found != 0 && session_id == 0
+}
+
+/// Whether Windows is in developer mode, which `SetStablePowerState` needs
+pub fn developer_mode_enabled() -> bool {
+    use winapi::shared::winerror::ERROR_SUCCESS;
+    use winapi::um::winreg::{RegGetValueW, HKEY_LOCAL_MACHINE, RRF_RT_REG_DWORD};
+
+    let wide = |text: &str| -> Vec<u16> { text.encode_utf16().chain(Some(0)).collect() };
+    let key = wide(r"SOFTWARE\Microsoft\Windows\CurrentVersion\AppModelUnlock");
+    let value = wide("AllowDevelopmentWithoutDevLicense");
+
+    let mut enabled: u32 = 0;
+    let mut size = std::mem::size_of::<u32>() as u32;
+    let status = unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            key.as_ptr(),
+            value.as_ptr(),
+            RRF_RT_REG_DWORD,
+            std::ptr::null_mut(),
+            &mut enabled as *mut u32 as *mut _,
+            &mut size,
+        )
+    };
+
+    status == ERROR_SUCCESS as i32 && enabled != 0
+}
+
+/// Name a D3D12 object, so debug layer messages and PIX captures can refer to it
+pub fn set_name(object: &winapi::um::d3d12::ID3D12Object, name: &str) {
+    let wide: Vec<u16> = name.encode_utf16().chain(Some(0)).collect();
+    let hr = unsafe { object.SetName(wide.as_ptr()) };
+
+    let location = format!("{}:{}", file!(), line!());
+    log_hr(hr, &location, &format!("SetName({:?})", name));
+}
+
+/// A failed HRESULT, along with where it came from
+pub struct DxError {
+    pub hr: d3d12::HRESULT,
+    pub location: String,
+    pub call: String,
+}
+
+impl fmt::Display for DxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let call_site = format!("\n{}", self.call).replace("\n", "\n\t");
+        write!(f, "{}: {}:{}", self.location, hr_string(self.hr), call_site)
+    }
+}
+
+// `main` reports its error through `Debug`, so make that the readable version too.
+impl fmt::Debug for DxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for DxError {}
+
+// winapi doesn't define these yet
+pub const D3D12_ERROR_ADAPTER_NOT_FOUND: d3d12::HRESULT = 0x887E0001_u32 as _;
+pub const D3D12_ERROR_DRIVER_VERSION_MISMATCH: d3d12::HRESULT = 0x887E0002_u32 as _;
+
+/// DXGI and D3D12 codes that `FormatMessage` doesn't have text for
+#[rustfmt::skip]
+const DX_ERROR_MESSAGES: &[(d3d12::HRESULT, &str)] = &[
+    (DXGI_ERROR_INVALID_CALL,                 "DXGI_ERROR_INVALID_CALL: The application provided invalid parameter data"),
+    (DXGI_ERROR_NOT_FOUND,                    "DXGI_ERROR_NOT_FOUND: The object was not found"),
+    (DXGI_ERROR_MORE_DATA,                    "DXGI_ERROR_MORE_DATA: The buffer supplied is not big enough to hold the requested data"),
+    (DXGI_ERROR_UNSUPPORTED,                  "DXGI_ERROR_UNSUPPORTED: The requested functionality is not supported by the device or the driver"),
+    (DXGI_ERROR_DEVICE_REMOVED,               "DXGI_ERROR_DEVICE_REMOVED: The GPU device has been physically removed, or a driver upgrade occurred"),
+    (DXGI_ERROR_DEVICE_HUNG,                  "DXGI_ERROR_DEVICE_HUNG: The device failed due to a badly formed command"),
+    (DXGI_ERROR_DEVICE_RESET,                 "DXGI_ERROR_DEVICE_RESET: The device was reset and must be recreated"),
+    (DXGI_ERROR_WAS_STILL_DRAWING,            "DXGI_ERROR_WAS_STILL_DRAWING: The GPU was busy at the moment the call was made"),
+    (DXGI_ERROR_FRAME_STATISTICS_DISJOINT,    "DXGI_ERROR_FRAME_STATISTICS_DISJOINT: An event invalidated the frame statistics"),
+    (DXGI_ERROR_GRAPHICS_VIDPN_SOURCE_IN_USE, "DXGI_ERROR_GRAPHICS_VIDPN_SOURCE_IN_USE: Another app already owns exclusive use of this output"),
+    (DXGI_ERROR_DRIVER_INTERNAL_ERROR,        "DXGI_ERROR_DRIVER_INTERNAL_ERROR: The driver encountered a problem and was put into the device removed state"),
+    (DXGI_ERROR_NONEXCLUSIVE,                 "DXGI_ERROR_NONEXCLUSIVE: A global counter resource is in use"),
+    (DXGI_ERROR_NOT_CURRENTLY_AVAILABLE,      "DXGI_ERROR_NOT_CURRENTLY_AVAILABLE: The resource or request is not currently available"),
+    (DXGI_ERROR_REMOTE_CLIENT_DISCONNECTED,   "DXGI_ERROR_REMOTE_CLIENT_DISCONNECTED: The remote desktop client disconnected"),
+    (DXGI_ERROR_REMOTE_OUTOFMEMORY,           "DXGI_ERROR_REMOTE_OUTOFMEMORY: The remote desktop client ran out of memory"),
+    (DXGI_ERROR_MODE_CHANGE_IN_PROGRESS,      "DXGI_ERROR_MODE_CHANGE_IN_PROGRESS: A mode change is in progress"),
+    (DXGI_ERROR_ACCESS_LOST,                  "DXGI_ERROR_ACCESS_LOST: The desktop duplication interface is invalid"),
+    (DXGI_ERROR_WAIT_TIMEOUT,                 "DXGI_ERROR_WAIT_TIMEOUT: The timeout elapsed before the resource became available"),
+    (DXGI_ERROR_SESSION_DISCONNECTED,         "DXGI_ERROR_SESSION_DISCONNECTED: The Remote Desktop Services session is disconnected"),
+    (DXGI_ERROR_RESTRICT_TO_OUTPUT_STALE,     "DXGI_ERROR_RESTRICT_TO_OUTPUT_STALE: The output restricted to by the swapchain is no longer valid"),
+    (DXGI_ERROR_CANNOT_PROTECT_CONTENT,       "DXGI_ERROR_CANNOT_PROTECT_CONTENT: Content protection is unavailable"),
+    (DXGI_ERROR_ACCESS_DENIED,                "DXGI_ERROR_ACCESS_DENIED: Access to a shared resource was denied"),
+    (DXGI_ERROR_NAME_ALREADY_EXISTS,          "DXGI_ERROR_NAME_ALREADY_EXISTS: A shared resource with this name already exists"),
+    (DXGI_ERROR_SDK_COMPONENT_MISSING,        "DXGI_ERROR_SDK_COMPONENT_MISSING: The operation depends on an SDK component that is missing"),
+    (D3D12_ERROR_ADAPTER_NOT_FOUND,           "D3D12_ERROR_ADAPTER_NOT_FOUND: The cached PSO was created on a different adapter"),
+    (D3D12_ERROR_DRIVER_VERSION_MISMATCH,     "D3D12_ERROR_DRIVER_VERSION_MISMATCH: The cached PSO was created on a different driver version"),
+];
+
+/// Look up text for DXGI/D3D12 specific codes
+fn dx_error_message(hr: d3d12::HRESULT) -> Option<&'static str> {
+    DX_ERROR_MESSAGES
+        .iter()
+        .find(|(code, _msg)| *code == hr)
+        .map(|(_code, msg)| *msg)
+}
+
 /// Turn an HRESULT code into something Google-able
 pub fn hr_string(hr: d3d12::HRESULT) -> String {
-    let mut buffer = [0u8; 128];
+    let success_icon = if SUCCEEDED(hr) { "✔️" } else { "❌" };
+
+    if let Some(msg) = dx_error_message(hr) {
+        return format!("{} (0x{:08X}) {}", success_icon, hr, msg);
+    }
 
     // Query the system message for this result code.
-    unsafe {
+    // Use the wide version so that localized messages decode cleanly, and let the OS size
+    // the buffer so long messages aren't cut off.
+    let buffer: Vec<u16> = unsafe {
         use std::ptr::{null, null_mut};
         use winapi::shared::ntdef::{LANG_NEUTRAL, MAKELANGID, SUBLANG_DEFAULT};
-        use winapi::um::winbase::{FormatMessageA, FORMAT_MESSAGE_FROM_SYSTEM};
+        use winapi::um::winbase::{
+            FormatMessageW, LocalFree, FORMAT_MESSAGE_ALLOCATE_BUFFER, FORMAT_MESSAGE_FROM_SYSTEM,
+        };
 
-        FormatMessageA(
-            FORMAT_MESSAGE_FROM_SYSTEM,
+        let mut os_buffer: *mut u16 = null_mut();
+        let len = FormatMessageW(
+            FORMAT_MESSAGE_FROM_SYSTEM | FORMAT_MESSAGE_ALLOCATE_BUFFER,
             null(),
             hr as u32,
             MAKELANGID(LANG_NEUTRAL, SUBLANG_DEFAULT).into(), // Default language
-            buffer.as_mut_ptr() as *mut i8,
-            buffer.len() as u32,
+            // With ALLOCATE_BUFFER, this is secretly a pointer to our pointer
+            &mut os_buffer as *mut *mut u16 as *mut u16,
+            0,
             null_mut(),
         );
-    }
 
-    let success_icon = if SUCCEEDED(hr) { "✔️" } else { "❌" };
+        if os_buffer.is_null() {
+            vec![]
+        } else {
+            let buffer = std::slice::from_raw_parts(os_buffer, len as usize).to_vec();
+            LocalFree(os_buffer as *mut _);
+            buffer
+        }
+    };
 
-    let full_msg = std::str::from_utf8(&buffer).expect("Invalid utf8");
+    let full_msg = String::from_utf16_lossy(&buffer);
     let str_len = full_msg
         .trim_end_matches(char::from(0)) // Trailing NULs
         .trim_end() // Trailing whitespace (FormatMessage may add newlines)
@@ -64,3 +313,38 @@ pub fn hr_string(hr: d3d12::HRESULT) -> String {
         format!("{} (0x{:08X}) {}", success_icon, hr, msg)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn string_from_wide_stops_at_the_first_nul() {
+        // Like DXGI_ADAPTER_DESC1::Description: the name, then NULs to the end of the array
+        let mut description = [0u16; 128];
+        for (slot, c) in description.iter_mut().zip("WARP Adapter".encode_utf16()) {
+            *slot = c;
+        }
+
+        assert_eq!(string_from_wide(&description), "WARP Adapter");
+        assert_eq!(string_from_wide(&[0u16; 8]), "");
+    }
+
+    #[test]
+    fn hr_string_keeps_long_system_messages_whole() {
+        // hr_string used to read messages into a 128-character buffer. This one runs well past that:
+        // "The application has failed to start because its side-by-side configuration is incorrect.
+        // Please see the application event log or use the command-line sxstrace.exe tool for more detail."
+        let text = hr_string(HRESULT_FROM_WIN32(ERROR_SXS_CANT_GEN_ACTCTX));
+        let message = text.split_once(") ").map_or("", |(_, message)| message);
+
+        assert!(message.chars().count() > 128, "Cut off: {:?}", message);
+        assert!(
+            message
+                .trim_end()
+                .ends_with("sxstrace.exe tool for more detail."),
+            "Cut off: {:?}",
+            message
+        );
+    }
+}
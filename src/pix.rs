@@ -0,0 +1,244 @@
+//! PIX event markers, so captures show named regions, and programmatic GPU captures.
+//! These call into WinPixEventRuntime.dll with the `pix` feature, and do nothing without it.
+
+use crate::os_helpers;
+use crate::status;
+
+use winapi::shared::winerror::SUCCEEDED;
+
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "pix")]
+mod ffi {
+    use winapi::ctypes::c_char;
+    use winapi::shared::minwindef::{BOOL, DWORD};
+    use winapi::um::d3d12::{ID3D12CommandQueue, ID3D12GraphicsCommandList};
+    use winapi::um::winnt::{HRESULT, PCWSTR};
+
+    pub const PIX_CAPTURE_GPU: DWORD = 1 << 1;
+
+    /// From pix3.h, where it's a union. GPU captures only read the file name.
+    #[repr(C)]
+    pub struct PIXCaptureParameters {
+        pub file_name: PCWSTR,
+        /// The rest of the union, sized for its timing capture member
+        pub _timing_parameters: [u32; 14],
+    }
+
+    // From pix3_win.h. The runtime treats `format_string` as printf-style, so names must not contain '%'.
+    #[link(name = "WinPixEventRuntime")]
+    extern "system" {
+        pub fn PIXBeginEventOnCommandList(
+            command_list: *mut ID3D12GraphicsCommandList,
+            color: u64,
+            format_string: *const c_char,
+        );
+        pub fn PIXEndEventOnCommandList(command_list: *mut ID3D12GraphicsCommandList);
+        pub fn PIXSetMarkerOnCommandList(
+            command_list: *mut ID3D12GraphicsCommandList,
+            color: u64,
+            format_string: *const c_char,
+        );
+
+        pub fn PIXBeginEventOnCommandQueue(
+            command_queue: *mut ID3D12CommandQueue,
+            color: u64,
+            format_string: *const c_char,
+        );
+        pub fn PIXEndEventOnCommandQueue(command_queue: *mut ID3D12CommandQueue);
+        pub fn PIXSetMarkerOnCommandQueue(
+            command_queue: *mut ID3D12CommandQueue,
+            color: u64,
+            format_string: *const c_char,
+        );
+
+        pub fn PIXBeginCapture2(
+            capture_flags: DWORD,
+            capture_parameters: *const PIXCaptureParameters,
+        ) -> HRESULT;
+        pub fn PIXEndCapture(discard: BOOL) -> HRESULT;
+    }
+}
+
+/// Same as PIX_COLOR(r, g, b)
+pub const fn pix_color(r: u8, g: u8, b: u8) -> u64 {
+    0xff00_0000 | ((r as u64) << 16) | ((g as u64) << 8) | (b as u64)
+}
+
+/// Something PIX can attach events to: a command list or a command queue
+pub trait PixTarget {
+    fn begin_event(&self, color: u64, name: &str);
+    fn end_event(&self);
+    fn set_marker(&self, color: u64, name: &str);
+}
+
+#[cfg(feature = "pix")]
+fn c_string(name: &str) -> std::ffi::CString {
+    // Names are ours, so an interior NUL would be a bug
+    std::ffi::CString::new(name).expect("PIX event names can't contain NUL")
+}
+
+#[cfg(feature = "pix")]
+impl PixTarget for d3d12::GraphicsCommandList {
+    fn begin_event(&self, color: u64, name: &str) {
+        let name = c_string(name);
+        unsafe { ffi::PIXBeginEventOnCommandList(self.as_mut_ptr(), color, name.as_ptr()) }
+    }
+
+    fn end_event(&self) {
+        unsafe { ffi::PIXEndEventOnCommandList(self.as_mut_ptr()) }
+    }
+
+    fn set_marker(&self, color: u64, name: &str) {
+        let name = c_string(name);
+        unsafe { ffi::PIXSetMarkerOnCommandList(self.as_mut_ptr(), color, name.as_ptr()) }
+    }
+}
+
+#[cfg(feature = "pix")]
+impl PixTarget for d3d12::CommandQueue {
+    fn begin_event(&self, color: u64, name: &str) {
+        let name = c_string(name);
+        unsafe { ffi::PIXBeginEventOnCommandQueue(self.as_mut_ptr(), color, name.as_ptr()) }
+    }
+
+    fn end_event(&self) {
+        unsafe { ffi::PIXEndEventOnCommandQueue(self.as_mut_ptr()) }
+    }
+
+    fn set_marker(&self, color: u64, name: &str) {
+        let name = c_string(name);
+        unsafe { ffi::PIXSetMarkerOnCommandQueue(self.as_mut_ptr(), color, name.as_ptr()) }
+    }
+}
+
+#[cfg(not(feature = "pix"))]
+impl PixTarget for d3d12::GraphicsCommandList {
+    fn begin_event(&self, _color: u64, _name: &str) {}
+    fn end_event(&self) {}
+    fn set_marker(&self, _color: u64, _name: &str) {}
+}
+
+#[cfg(not(feature = "pix"))]
+impl PixTarget for d3d12::CommandQueue {
+    fn begin_event(&self, _color: u64, _name: &str) {}
+    fn end_event(&self) {}
+    fn set_marker(&self, _color: u64, _name: &str) {}
+}
+
+pub fn pix_begin_event(target: &impl PixTarget, color: u64, name: &str) {
+    target.begin_event(color, name);
+}
+
+pub fn pix_end_event(target: &impl PixTarget) {
+    target.end_event();
+}
+
+pub fn pix_set_marker(target: &impl PixTarget, color: u64, name: &str) {
+    target.set_marker(color, name);
+}
+
+/// Load PIX's GPU capturer, which has to happen before any D3D12 device is created.
+/// It comes with PIX, and has to be on the PATH or next to the executable.
+#[cfg(feature = "pix")]
+pub fn load_gpu_capturer() -> Result<(), String> {
+    use winapi::um::libloaderapi::LoadLibraryW;
+
+    let module = unsafe { LoadLibraryW(crate::shaders::wide("WinPixGpuCapturer.dll").as_ptr()) };
+    if module.is_null() {
+        Err(
+            "Couldn't load WinPixGpuCapturer.dll. Is PIX's install directory on the PATH?"
+                .to_string(),
+        )
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "pix"))]
+pub fn load_gpu_capturer() -> Result<(), String> {
+    Err("Programmatic captures need a build with the pix feature".to_string())
+}
+
+#[cfg(feature = "pix")]
+fn begin_capture(path: &Path) -> d3d12::HRESULT {
+    let file_name = crate::shaders::wide(&path.to_string_lossy());
+    let parameters = ffi::PIXCaptureParameters {
+        file_name: file_name.as_ptr(),
+        _timing_parameters: [0; 14],
+    };
+    unsafe { ffi::PIXBeginCapture2(ffi::PIX_CAPTURE_GPU, &parameters) }
+}
+
+#[cfg(feature = "pix")]
+fn end_capture() -> d3d12::HRESULT {
+    unsafe { ffi::PIXEndCapture(winapi::shared::minwindef::FALSE) }
+}
+
+#[cfg(not(feature = "pix"))]
+fn begin_capture(_path: &Path) -> d3d12::HRESULT {
+    winapi::shared::winerror::E_NOTIMPL
+}
+
+#[cfg(not(feature = "pix"))]
+fn end_capture() -> d3d12::HRESULT {
+    winapi::shared::winerror::E_NOTIMPL
+}
+
+/// Records one frame, counting from 1, into a `.wpix` file
+pub struct FrameCapture {
+    frame: u32,
+    path: PathBuf,
+    capturing: bool,
+}
+
+impl FrameCapture {
+    pub fn new(frame: u32, path: PathBuf) -> Self {
+        FrameCapture {
+            frame,
+            path,
+            capturing: false,
+        }
+    }
+
+    /// Call before recording `frame`, so its commands land in the capture
+    pub fn before_frame(&mut self, frame: u32) {
+        if frame != self.frame {
+            return;
+        }
+
+        let hr = begin_capture(&self.path);
+        if SUCCEEDED(hr) {
+            self.capturing = true;
+        } else {
+            status!(
+                "⚠️ Couldn't start a PIX capture: {}",
+                os_helpers::hr_string(hr)
+            );
+        }
+    }
+
+    /// Call after presenting `frame`. Returns true once the capture is finished.
+    pub fn after_frame(&mut self, frame: u32) -> bool {
+        if frame != self.frame {
+            return false;
+        }
+
+        if std::mem::replace(&mut self.capturing, false) {
+            let hr = end_capture();
+            if SUCCEEDED(hr) {
+                status!(
+                    "Saved a PIX capture of frame {} to {}",
+                    frame,
+                    self.path.display()
+                );
+            } else {
+                status!(
+                    "⚠️ Couldn't finish the PIX capture: {}",
+                    os_helpers::hr_string(hr)
+                );
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,601 @@
+//! The DXR path: trace the scene into a UAV texture, then copy it into the back buffer
+
+use crate::accel::{self, AccelerationStructure};
+use crate::camera::Mat4;
+use crate::caps::RaytracingTier;
+use crate::check_hr_err;
+use crate::commands;
+use crate::d3d12_ext::*;
+use crate::os_helpers::{self, DxError};
+use crate::root_sig::RootSignatureBuilder;
+use crate::rt_pipeline::{self, RtPipeline};
+use crate::shader_table::{self, ShaderTableLayout};
+use crate::shaders;
+
+use d3d12::{Binding, DescriptorRange, DescriptorRangeType};
+
+use winapi::shared::dxgiformat::*;
+use winapi::shared::dxgitype::*;
+use winapi::shared::winerror::*;
+use winapi::um::d3d12::*;
+use winapi::Interface;
+
+use std::mem::zeroed;
+use std::path::Path;
+use std::ptr;
+
+const RAYTRACE_HLSL: &str = r#"
+RaytracingAccelerationStructure Scene : register(t0);
+ByteAddressBuffer Indices : register(t1);
+ByteAddressBuffer Vertices : register(t2);
+RWTexture2D<float4> Output : register(u0);
+
+cbuffer MissConstants : register(b0) {
+    float4 miss_color;
+};
+
+cbuffer CameraConstants : register(b1) {
+    row_major float4x4 inv_view_proj;
+};
+
+struct Payload {
+    float4 color;
+};
+
+struct ShadowPayload {
+    float visibility;
+};
+
+// Each ray type has the miss record and hit group record at its index
+static const uint RAY_PRIMARY = 0;
+static const uint RAY_SHADOW = 1;
+static const uint RAY_TYPE_COUNT = 2;
+
+// Matches mesh::Vertex: a float3 position, then a float4 color
+static const uint VERTEX_STRIDE = 28;
+static const uint ALPHA_OFFSET = 24;
+
+// Points towards the light
+static const float3 LIGHT_DIR = float3(-0.4, 1.0, 0.6);
+static const float AMBIENT = 0.3;
+
+uint3 TriangleIndices() {
+    return Indices.Load3(PrimitiveIndex() * 12);
+}
+
+float3 VertexPosition(uint index) {
+    return asfloat(Vertices.Load3(index * VERTEX_STRIDE));
+}
+
+float VertexAlpha(uint index) {
+    return asfloat(Vertices.Load(index * VERTEX_STRIDE + ALPHA_OFFSET));
+}
+
+float3 Barycentrics(BuiltInTriangleIntersectionAttributes attribs) {
+    float2 bary = attribs.barycentrics;
+    return float3(1.0 - bary.x - bary.y, bary.x, bary.y);
+}
+
+[shader("raygeneration")]
+void RayGen() {
+    uint2 index = DispatchRaysIndex().xy;
+    float2 dims = float2(DispatchRaysDimensions().xy);
+    float2 ndc = (float2(index) + 0.5) / dims * 2.0 - 1.0;
+
+    // Unproject this pixel at the near and far planes, so the scene lines up with the raster triangle
+    float4 near_point = mul(inv_view_proj, float4(ndc.x, -ndc.y, 0.0, 1.0));
+    float4 far_point = mul(inv_view_proj, float4(ndc.x, -ndc.y, 1.0, 1.0));
+    near_point /= near_point.w;
+    far_point /= far_point.w;
+
+    RayDesc ray;
+    ray.Origin = near_point.xyz;
+    ray.Direction = normalize(far_point.xyz - near_point.xyz);
+    ray.TMin = 0.0;
+    ray.TMax = length(far_point.xyz - near_point.xyz);
+
+    Payload payload = { float4(0.0, 0.0, 0.0, 0.0) };
+    TraceRay(Scene, RAY_FLAG_NONE, 0xff, RAY_PRIMARY, RAY_TYPE_COUNT, RAY_PRIMARY, ray, payload);
+
+    Output[index] = payload.color;
+}
+
+// Fades from the clear color at the horizon to a pale blue straight up
+[shader("miss")]
+void Miss(inout Payload payload) {
+    float up = saturate(WorldRayDirection().y);
+    payload.color = float4(lerp(miss_color.rgb, float3(0.55, 0.7, 0.95), up), miss_color.a);
+}
+
+[shader("miss")]
+void ShadowMiss(inout ShadowPayload payload) {
+    payload.visibility = 1.0;
+}
+
+// Vertices with alpha under a half cut their triangles away, for both ray types
+void AlphaTest(BuiltInTriangleIntersectionAttributes attribs) {
+    uint3 tri = TriangleIndices();
+    float3 alphas = float3(VertexAlpha(tri.x), VertexAlpha(tri.y), VertexAlpha(tri.z));
+    if (dot(alphas, Barycentrics(attribs)) < 0.5) {
+        IgnoreHit();
+    }
+}
+
+[shader("anyhit")]
+void AnyHit(inout Payload payload, in BuiltInTriangleIntersectionAttributes attribs) {
+    AlphaTest(attribs);
+}
+
+[shader("anyhit")]
+void ShadowAnyHit(inout ShadowPayload payload, in BuiltInTriangleIntersectionAttributes attribs) {
+    AlphaTest(attribs);
+}
+
+// Instance 0 keeps the plain barycentric colors, and every other instance gets its own tint
+float3 InstanceTint(uint id) {
+    if (id == 0) {
+        return float3(1.0, 1.0, 1.0);
+    }
+    float hue = frac(id * 0.618034);
+    float3 rgb = saturate(abs(frac(hue + float3(0.0, 2.0 / 3.0, 1.0 / 3.0)) * 6.0 - 3.0) - 1.0);
+    return lerp(float3(0.25, 0.25, 0.25), float3(1.0, 1.0, 1.0), rgb);
+}
+
+[shader("closesthit")]
+void ClosestHit(inout Payload payload, in BuiltInTriangleIntersectionAttributes attribs) {
+    uint3 tri = TriangleIndices();
+    float3 p0 = VertexPosition(tri.x);
+    float3 p1 = VertexPosition(tri.y);
+    float3 p2 = VertexPosition(tri.z);
+    float3 normal = normalize(mul((float3x3)ObjectToWorld3x4(), cross(p1 - p0, p2 - p0)));
+    // Triangles are two-sided, so light whichever side the ray hit
+    if (dot(normal, WorldRayDirection()) > 0.0) {
+        normal = -normal;
+    }
+
+    float3 light_dir = normalize(LIGHT_DIR);
+    float lit = saturate(dot(normal, light_dir));
+    if (lit > 0.0) {
+        // Start a little off the surface, so the shadow ray doesn't hit the triangle it left
+        RayDesc shadow_ray;
+        shadow_ray.Origin = WorldRayOrigin() + WorldRayDirection() * RayTCurrent() + normal * 0.001;
+        shadow_ray.Direction = light_dir;
+        shadow_ray.TMin = 0.0;
+        shadow_ray.TMax = 1.0e6;
+
+        ShadowPayload shadow = { 0.0 };
+        TraceRay(
+            Scene,
+            RAY_FLAG_ACCEPT_FIRST_HIT_AND_END_SEARCH | RAY_FLAG_SKIP_CLOSEST_HIT_SHADER,
+            0xff, RAY_SHADOW, RAY_TYPE_COUNT, RAY_SHADOW, shadow_ray, shadow);
+        lit *= shadow.visibility;
+    }
+
+    float3 color = Barycentrics(attribs) * InstanceTint(InstanceID());
+    payload.color = float4(color * (AMBIENT + (1.0 - AMBIENT) * lit), 1.0);
+}
+"#;
+
+/// Global root parameters, in the order `create_root_signature` adds them
+const ROOT_PARAM_SCENE: u32 = 0;
+const ROOT_PARAM_OUTPUT: u32 = 1;
+const ROOT_PARAM_MISS_COLOR: u32 = 2;
+const ROOT_PARAM_CAMERA: u32 = 3;
+const ROOT_PARAM_INDICES: u32 = 4;
+const ROOT_PARAM_VERTICES: u32 = 5;
+
+/// Distance between instance centers. Meshes are scaled to the built-in triangle's unit size.
+const INSTANCE_SPACING: f32 = 1.25;
+
+/// Matches `CameraConstants` in RAYTRACE_HLSL
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct CameraConstants {
+    /// Takes clip space back to world space, to build each pixel's ray
+    pub inv_view_proj: Mat4,
+}
+
+fn create_root_signature(device: &d3d12::Device) -> Result<d3d12::RootSignature, DxError> {
+    // Typed UAVs can't be root descriptors, so the output goes through a table
+    RootSignatureBuilder::new()
+        .add_srv(0)
+        .add_descriptor_table(vec![DescriptorRange::new(
+            DescriptorRangeType::UAV,
+            1,
+            Binding {
+                register: 0,
+                space: 0,
+            },
+            0,
+        )])
+        .add_constants(0, 4)
+        .add_cbv(1)
+        .add_srv(1)
+        .add_srv(2)
+        .build(device, "Raytracing Root Signature")
+}
+
+/// A `width` x `height` UAV texture to trace into, with its view written into `uav_heap`
+fn create_output(
+    device: &d3d12::Device,
+    uav_heap: d3d12::DescriptorHeap,
+    format: DXGI_FORMAT,
+    width: u32,
+    height: u32,
+) -> Result<d3d12::Resource, DxError> {
+    let heap_props = D3D12_HEAP_PROPERTIES {
+        Type: D3D12_HEAP_TYPE_DEFAULT,
+        CPUPageProperty: D3D12_CPU_PAGE_PROPERTY_UNKNOWN,
+        MemoryPoolPreference: D3D12_MEMORY_POOL_UNKNOWN,
+        CreationNodeMask: 0,
+        VisibleNodeMask: 0,
+    };
+
+    // CopyResource needs this to match the back buffer exactly
+    let resource_desc = D3D12_RESOURCE_DESC {
+        Dimension: D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+        Alignment: 0,
+        Width: width as u64,
+        Height: height,
+        DepthOrArraySize: 1,
+        MipLevels: 1,
+        Format: format,
+        SampleDesc: DXGI_SAMPLE_DESC {
+            Count: 1,
+            Quality: 0,
+        },
+        Layout: D3D12_TEXTURE_LAYOUT_UNKNOWN,
+        Flags: D3D12_RESOURCE_FLAG_ALLOW_UNORDERED_ACCESS,
+    };
+
+    let output = check_hr_err! {
+        unsafe {
+            let mut output = d3d12::Resource::null();
+            let hr = device.CreateCommittedResource(
+                &heap_props,
+                D3D12_HEAP_FLAG_NONE,
+                &resource_desc,
+                D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+                ptr::null(),
+                &ID3D12Resource::uuidof(),
+                output.mut_void(),
+            );
+
+            (output, hr)
+        }
+    };
+    os_helpers::set_name(&output, "Raytracing Output");
+
+    unsafe {
+        let mut uav_desc: D3D12_UNORDERED_ACCESS_VIEW_DESC = zeroed();
+        uav_desc.Format = format;
+        uav_desc.ViewDimension = D3D12_UAV_DIMENSION_TEXTURE2D;
+
+        device.CreateUnorderedAccessView(
+            output.as_mut_ptr(),
+            ptr::null_mut(),
+            &uav_desc,
+            uav_heap.start_cpu_descriptor(),
+        );
+    }
+
+    Ok(output)
+}
+
+/// Load the raytracing library, and build the pipeline and its shader table around it
+fn create_pipeline(
+    device: &d3d12::Device,
+    shader_dir: Option<&Path>,
+    root_sig: d3d12::RootSignature,
+) -> Result<(RtPipeline, d3d12::Resource, ShaderTableLayout), DxError> {
+    let target = shaders::default_target("lib");
+    let lib_blob = shaders::load_or_compile(
+        shader_dir,
+        "raytrace_lib",
+        "raytrace",
+        RAYTRACE_HLSL,
+        "",
+        &target,
+    )
+    .map_err(|err| {
+        log::error!("{}", err);
+        DxError {
+            hr: E_FAIL,
+            location: format!("{}:{}", file!(), line!()),
+            call: format!("shaders::load_or_compile(\"raytrace_lib\", {:?})", target),
+        }
+    })?;
+
+    let mut pipeline = rt_pipeline::create_rt_pipeline(device, &lib_blob, root_sig)?;
+    match shader_table::build_shader_table(
+        device,
+        &pipeline.properties,
+        rt_pipeline::RAYGEN_EXPORT,
+        &[rt_pipeline::MISS_EXPORT, rt_pipeline::SHADOW_MISS_EXPORT],
+        &[
+            rt_pipeline::HIT_GROUP_EXPORT,
+            rt_pipeline::SHADOW_HIT_GROUP_EXPORT,
+        ],
+    ) {
+        Ok((shader_table, layout)) => Ok((pipeline, shader_table, layout)),
+        Err(err) => {
+            pipeline.destroy();
+            Err(err)
+        }
+    }
+}
+
+/// Everything the DXR path needs to trace one frame
+pub struct Raytracer {
+    root_sig: d3d12::RootSignature,
+    pipeline: RtPipeline,
+    shader_table: d3d12::Resource,
+    shader_table_layout: ShaderTableLayout,
+    /// Hit shaders read the triangles' vertices out of these. They belong to the raster triangle.
+    geometry: accel::TriangleGeometry,
+    blas: AccelerationStructure,
+    tlas: AccelerationStructure,
+    uav_heap: d3d12::DescriptorHeap,
+    output: d3d12::Resource,
+    format: DXGI_FORMAT,
+    width: u32,
+    height: u32,
+}
+
+impl Raytracer {
+    /// Build the pipeline and output texture, and record acceleration structure builds
+    /// over `instance_count` copies of `geometry` into `cmd_list`.
+    /// Call `release_build_buffers` once those builds finish.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &d3d12::Device,
+        raytracing_tier: RaytracingTier,
+        shader_dir: Option<&Path>,
+        cmd_list: &d3d12::GraphicsCommandList,
+        geometry: accel::TriangleGeometry,
+        instance_count: u32,
+        format: DXGI_FORMAT,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, DxError> {
+        let root_sig = create_root_signature(device)?;
+        let (pipeline, shader_table, shader_table_layout) =
+            create_pipeline(device, shader_dir, root_sig)?;
+
+        let blas = accel::build_blas(device, raytracing_tier, cmd_list, &geometry)?;
+        let instances = accel::grid_instances(
+            blas.result.gpu_virtual_address(),
+            instance_count,
+            INSTANCE_SPACING,
+        );
+        let tlas = accel::build_tlas(device, raytracing_tier, cmd_list, &instances)?;
+
+        let uav_heap = check_hr_err!(device.create_descriptor_heap(
+            1,
+            d3d12::DescriptorHeapType::CbvSrvUav,
+            d3d12::DescriptorHeapFlags::SHADER_VISIBLE,
+            0,
+        ));
+        os_helpers::set_name(&uav_heap, "Raytracing UAV Heap");
+        let output = create_output(device, uav_heap, format, width, height)?;
+
+        Ok(Raytracer {
+            root_sig,
+            pipeline,
+            shader_table,
+            shader_table_layout,
+            geometry,
+            blas,
+            tlas,
+            uav_heap,
+            output,
+            format,
+            width,
+            height,
+        })
+    }
+
+    /// Free the scratch and instance buffers, once the builds recorded by `new` have finished
+    pub fn release_build_buffers(&mut self) {
+        self.blas.release_build_buffers();
+        self.tlas.release_build_buffers();
+    }
+
+    pub fn destroy(&mut self) {
+        self.blas.release_build_buffers();
+        self.tlas.release_build_buffers();
+        self.pipeline.destroy();
+        unsafe {
+            self.output.destroy();
+            self.uav_heap.destroy();
+            self.tlas.result.destroy();
+            self.blas.result.destroy();
+            self.shader_table.destroy();
+            self.root_sig.destroy();
+        }
+    }
+
+    /// Rebuild the pipeline and shader table from `shader_dir`. If that fails, the old ones stay.
+    /// Nothing in flight can still be using the old pipeline.
+    pub fn reload(&mut self, device: &d3d12::Device, shader_dir: &Path) -> Result<(), DxError> {
+        let (pipeline, shader_table, shader_table_layout) =
+            create_pipeline(device, Some(shader_dir), self.root_sig)?;
+        self.pipeline.destroy();
+        unsafe {
+            self.shader_table.destroy();
+        }
+        self.pipeline = pipeline;
+        self.shader_table = shader_table;
+        self.shader_table_layout = shader_table_layout;
+
+        Ok(())
+    }
+
+    /// The shader-visible heap holding the output's UAV, at its start
+    pub fn uav_heap(&self) -> d3d12::DescriptorHeap {
+        self.uav_heap
+    }
+
+    pub fn output_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Recreate the output texture at the new size. Nothing in flight can still be using it.
+    pub fn resize(
+        &mut self,
+        device: &d3d12::Device,
+        width: u32,
+        height: u32,
+    ) -> Result<(), DxError> {
+        unsafe {
+            self.output.destroy();
+        }
+        self.output = create_output(device, self.uav_heap, self.format, width, height)?;
+        self.width = width;
+        self.height = height;
+
+        Ok(())
+    }
+
+    /// Bind the pipeline and its global root arguments, and trace one ray per output pixel.
+    /// `camera` is the GPU address of a `CameraConstants`.
+    pub fn dispatch_rays(
+        &self,
+        cmd_list: &d3d12::GraphicsCommandList,
+        miss_color: [f32; 4],
+        camera: u64,
+    ) {
+        cmd_list.set_descriptor_heaps(&[self.uav_heap]);
+        cmd_list.set_compute_root_signature(self.root_sig);
+        cmd_list.set_compute_root_shader_resource_view(
+            ROOT_PARAM_SCENE,
+            self.tlas.result.gpu_virtual_address(),
+        );
+        cmd_list.set_compute_root_descriptor_table(
+            ROOT_PARAM_OUTPUT,
+            self.uav_heap.start_gpu_descriptor(),
+        );
+        unsafe {
+            cmd_list.SetComputeRoot32BitConstants(
+                ROOT_PARAM_MISS_COLOR,
+                miss_color.len() as u32,
+                miss_color.as_ptr() as *const _,
+                0,
+            );
+        }
+        cmd_list.set_compute_root_constant_buffer_view(ROOT_PARAM_CAMERA, camera);
+        cmd_list.set_compute_root_shader_resource_view(
+            ROOT_PARAM_INDICES,
+            self.geometry.index_buffer.gpu_virtual_address(),
+        );
+        cmd_list.set_compute_root_shader_resource_view(
+            ROOT_PARAM_VERTICES,
+            self.geometry.vertex_buffer.gpu_virtual_address(),
+        );
+
+        dispatch_rays(
+            cmd_list,
+            &self.pipeline,
+            &self.shader_table_layout,
+            self.width,
+            self.height,
+        );
+    }
+
+    /// Trace a frame and copy it into `back_buffer`, which starts and ends in PRESENT
+    pub fn render(
+        &self,
+        cmd_list: &d3d12::GraphicsCommandList,
+        back_buffer: d3d12::Resource,
+        miss_color: [f32; 4],
+        camera: u64,
+    ) {
+        self.dispatch_rays(cmd_list, miss_color, camera);
+
+        commands::transition_all(
+            cmd_list,
+            &[
+                (
+                    self.output,
+                    D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+                    D3D12_RESOURCE_STATE_COPY_SOURCE,
+                ),
+                (
+                    back_buffer,
+                    D3D12_RESOURCE_STATE_PRESENT,
+                    D3D12_RESOURCE_STATE_COPY_DEST,
+                ),
+            ],
+        );
+        unsafe {
+            cmd_list.CopyResource(back_buffer.as_mut_ptr(), self.output.as_mut_ptr());
+        }
+        commands::transition_all(
+            cmd_list,
+            &[
+                (
+                    self.output,
+                    D3D12_RESOURCE_STATE_COPY_SOURCE,
+                    D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+                ),
+                (
+                    back_buffer,
+                    D3D12_RESOURCE_STATE_COPY_DEST,
+                    D3D12_RESOURCE_STATE_PRESENT,
+                ),
+            ],
+        );
+    }
+}
+
+/// Bind `pipeline` and call DispatchRays over a `width` x `height` grid, using `layout`'s shader records.
+/// The global root signature and its arguments must already be set.
+pub fn dispatch_rays(
+    cmd_list: &d3d12::GraphicsCommandList,
+    pipeline: &RtPipeline,
+    layout: &ShaderTableLayout,
+    width: u32,
+    height: u32,
+) {
+    let desc = D3D12_DISPATCH_RAYS_DESC {
+        RayGenerationShaderRecord: D3D12_GPU_VIRTUAL_ADDRESS_RANGE {
+            StartAddress: layout.raygen.start_address,
+            SizeInBytes: layout.raygen.size_in_bytes,
+        },
+        MissShaderTable: D3D12_GPU_VIRTUAL_ADDRESS_RANGE_AND_STRIDE {
+            StartAddress: layout.miss.start_address,
+            SizeInBytes: layout.miss.size_in_bytes,
+            StrideInBytes: layout.miss.stride_in_bytes,
+        },
+        HitGroupTable: D3D12_GPU_VIRTUAL_ADDRESS_RANGE_AND_STRIDE {
+            StartAddress: layout.hit_group.start_address,
+            SizeInBytes: layout.hit_group.size_in_bytes,
+            StrideInBytes: layout.hit_group.stride_in_bytes,
+        },
+        CallableShaderTable: D3D12_GPU_VIRTUAL_ADDRESS_RANGE_AND_STRIDE {
+            StartAddress: 0,
+            SizeInBytes: 0,
+            StrideInBytes: 0,
+        },
+        Width: width,
+        Height: height,
+        Depth: 1,
+    };
+
+    // Checked when the raytracer was created, so this only fails if the device lost DXR since
+    let cmd_list4 = match unsafe { cmd_list.cast::<ID3D12GraphicsCommandList4>() } {
+        (cmd_list4, hr) if SUCCEEDED(hr) => cmd_list4,
+        (_, hr) => {
+            log::error!(
+                "ID3D12GraphicsCommandList4 is missing: {}",
+                os_helpers::hr_string(hr)
+            );
+            return;
+        }
+    };
+    unsafe {
+        cmd_list4.SetPipelineState1(pipeline.state_object.as_mut_ptr());
+        cmd_list4.DispatchRays(&desc);
+        cmd_list4.destroy();
+    }
+}
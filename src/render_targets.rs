@@ -0,0 +1,179 @@
+//! Swapchain back buffers and their RTVs
+
+use crate::check_hr_err;
+use crate::os_helpers::{self, DxError};
+
+use winapi::shared::dxgiformat::*;
+use winapi::shared::dxgitype::*;
+use winapi::um::d3d12::*;
+use winapi::Interface;
+
+use std::mem::zeroed;
+
+/// The sRGB format to view `format` buffers through, so writes are gamma-encoded for us.
+/// Flip-model swapchains have to be UNORM, but their RTVs can still be sRGB.
+pub fn srgb_view_format(format: DXGI_FORMAT) -> Option<DXGI_FORMAT> {
+    match format {
+        DXGI_FORMAT_R8G8B8A8_UNORM => Some(DXGI_FORMAT_R8G8B8A8_UNORM_SRGB),
+        DXGI_FORMAT_B8G8R8A8_UNORM => Some(DXGI_FORMAT_B8G8R8A8_UNORM_SRGB),
+        _ => None,
+    }
+}
+
+/// What to create a texture as so it can hold `format` pixels behind `rtv_format` views.
+/// Only typeless textures can be viewed as a different format than they hold.
+pub fn texture_format(format: DXGI_FORMAT, rtv_format: DXGI_FORMAT) -> DXGI_FORMAT {
+    if format == rtv_format {
+        return format;
+    }
+
+    match format {
+        DXGI_FORMAT_R8G8B8A8_UNORM => DXGI_FORMAT_R8G8B8A8_TYPELESS,
+        DXGI_FORMAT_B8G8R8A8_UNORM => DXGI_FORMAT_B8G8R8A8_TYPELESS,
+        _ => format,
+    }
+}
+
+/// Every back buffer in the swapchain, with an RTV for each one.
+/// Headless runs have no swapchain, and fill this with offscreen textures instead.
+pub struct RenderTargets {
+    rtv_heap: d3d12::DescriptorHeap,
+    rtv_descriptor_size: u32,
+    buffers: Vec<d3d12::Resource>,
+}
+
+impl RenderTargets {
+    /// Views are `rtv_format`, which is either the swapchain's format or its `srgb_view_format`
+    pub fn new(
+        device: &d3d12::Device,
+        swapchain: &d3d12::SwapChain3,
+        buffer_count: u32,
+        rtv_format: DXGI_FORMAT,
+    ) -> Result<Self, DxError> {
+        let mut render_targets = Self::with_rtv_heap(device, buffer_count)?;
+
+        let rtv_desc = d3d12::RenderTargetViewDesc::texture_2d(rtv_format, 0, 0);
+        for i in 0..buffer_count {
+            let buffer = check_hr_err!(swapchain.as_swapchain0().get_buffer(i));
+            os_helpers::set_name(&buffer, &format!("BackBuffer {}", i));
+            device.create_render_target_view(buffer, &rtv_desc, render_targets.rtv_handle(i));
+
+            render_targets.buffers.push(buffer);
+        }
+
+        Ok(render_targets)
+    }
+
+    /// `buffer_count` textures to stand in for a swapchain's back buffers.
+    /// They start in PRESENT, like back buffers, so frames can treat the two the same.
+    #[allow(clippy::too_many_arguments)]
+    pub fn offscreen(
+        device: &d3d12::Device,
+        buffer_count: u32,
+        format: DXGI_FORMAT,
+        rtv_format: DXGI_FORMAT,
+        width: u32,
+        height: u32,
+        clear_color: [f32; 4],
+    ) -> Result<Self, DxError> {
+        let mut render_targets = Self::with_rtv_heap(device, buffer_count)?;
+
+        let heap_props = D3D12_HEAP_PROPERTIES {
+            Type: D3D12_HEAP_TYPE_DEFAULT,
+            CPUPageProperty: D3D12_CPU_PAGE_PROPERTY_UNKNOWN,
+            MemoryPoolPreference: D3D12_MEMORY_POOL_UNKNOWN,
+            CreationNodeMask: 0,
+            VisibleNodeMask: 0,
+        };
+        let resource_desc = D3D12_RESOURCE_DESC {
+            Dimension: D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+            Alignment: 0,
+            Width: width as u64,
+            Height: height,
+            DepthOrArraySize: 1,
+            MipLevels: 1,
+            Format: texture_format(format, rtv_format),
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            Layout: D3D12_TEXTURE_LAYOUT_UNKNOWN,
+            Flags: D3D12_RESOURCE_FLAG_ALLOW_RENDER_TARGET,
+        };
+
+        // Matching the clear we do each frame lets the driver use its fast-clear path
+        let clear_value = unsafe {
+            let mut clear_value: D3D12_CLEAR_VALUE = zeroed();
+            clear_value.Format = rtv_format;
+            *clear_value.u.Color_mut() = clear_color;
+
+            clear_value
+        };
+
+        let rtv_desc = d3d12::RenderTargetViewDesc::texture_2d(rtv_format, 0, 0);
+        for i in 0..buffer_count {
+            let buffer = check_hr_err! {
+                unsafe {
+                    let mut buffer = d3d12::Resource::null();
+                    let hr = device.CreateCommittedResource(
+                        &heap_props,
+                        D3D12_HEAP_FLAG_NONE,
+                        &resource_desc,
+                        D3D12_RESOURCE_STATE_PRESENT,
+                        &clear_value,
+                        &ID3D12Resource::uuidof(),
+                        buffer.mut_void(),
+                    );
+
+                    (buffer, hr)
+                }
+            };
+            os_helpers::set_name(&buffer, &format!("Offscreen Target {}", i));
+            device.create_render_target_view(buffer, &rtv_desc, render_targets.rtv_handle(i));
+
+            render_targets.buffers.push(buffer);
+        }
+
+        Ok(render_targets)
+    }
+
+    fn with_rtv_heap(device: &d3d12::Device, buffer_count: u32) -> Result<Self, DxError> {
+        let rtv_heap = check_hr_err!(device.create_descriptor_heap(
+            buffer_count,
+            d3d12::DescriptorHeapType::Rtv,
+            d3d12::DescriptorHeapFlags::empty(),
+            0,
+        ));
+        os_helpers::set_name(&rtv_heap, "RTV Heap");
+        let rtv_descriptor_size =
+            device.get_descriptor_increment_size(d3d12::DescriptorHeapType::Rtv);
+
+        Ok(RenderTargets {
+            rtv_heap,
+            rtv_descriptor_size,
+            buffers: Vec::with_capacity(buffer_count as usize),
+        })
+    }
+
+    pub fn buffer(&self, index: u32) -> d3d12::Resource {
+        self.buffers[index as usize]
+    }
+
+    /// CPU handle of the RTV for back buffer `index`
+    pub fn rtv_handle(&self, index: u32) -> d3d12::CpuDescriptor {
+        let mut handle = self.rtv_heap.start_cpu_descriptor();
+        handle.ptr += (index * self.rtv_descriptor_size) as usize;
+
+        handle
+    }
+
+    /// Release the back buffers and RTV heap. The swapchain can't resize while we hold them.
+    pub fn destroy(&mut self) {
+        unsafe {
+            for buffer in self.buffers.drain(..) {
+                buffer.destroy();
+            }
+            self.rtv_heap.destroy();
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! A builder for root signatures, so we don't fill in `D3D12_ROOT_SIGNATURE_DESC` by hand
+
+use crate::check_hr_err;
+use crate::os_helpers::{self, DxError};
+
+use d3d12::{Binding, DescriptorRange, RootParameter, ShaderVisibility, StaticSampler};
+
+enum Parameter {
+    Cbv(Binding),
+    Srv(Binding),
+    Uav(Binding),
+    Constants { binding: Binding, count: u32 },
+    DescriptorTable(Vec<DescriptorRange>),
+}
+
+/// Every binding lives in register space 0, and is visible to all shader stages
+fn register(register: u32) -> Binding {
+    Binding { register, space: 0 }
+}
+
+pub struct RootSignatureBuilder {
+    parameters: Vec<Parameter>,
+    static_samplers: Vec<StaticSampler>,
+    flags: d3d12::RootSignatureFlags,
+}
+
+impl RootSignatureBuilder {
+    pub fn new() -> Self {
+        RootSignatureBuilder {
+            parameters: vec![],
+            static_samplers: vec![],
+            flags: d3d12::RootSignatureFlags::empty(),
+        }
+    }
+
+    /// Root CBV at `b<reg>`
+    pub fn add_cbv(mut self, reg: u32) -> Self {
+        self.parameters.push(Parameter::Cbv(register(reg)));
+        self
+    }
+
+    /// Root SRV at `t<reg>`
+    pub fn add_srv(mut self, reg: u32) -> Self {
+        self.parameters.push(Parameter::Srv(register(reg)));
+        self
+    }
+
+    /// Root UAV at `u<reg>`
+    pub fn add_uav(mut self, reg: u32) -> Self {
+        self.parameters.push(Parameter::Uav(register(reg)));
+        self
+    }
+
+    /// `count` 32-bit root constants at `b<reg>`
+    pub fn add_constants(mut self, reg: u32, count: u32) -> Self {
+        self.parameters.push(Parameter::Constants {
+            binding: register(reg),
+            count,
+        });
+        self
+    }
+
+    pub fn add_descriptor_table(mut self, ranges: Vec<DescriptorRange>) -> Self {
+        self.parameters.push(Parameter::DescriptorTable(ranges));
+        self
+    }
+
+    pub fn add_static_sampler(mut self, sampler: StaticSampler) -> Self {
+        self.static_samplers.push(sampler);
+        self
+    }
+
+    pub fn flags(mut self, flags: d3d12::RootSignatureFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Serialize the root signature and create it on `device`, with a debug name
+    pub fn build(
+        self,
+        device: &d3d12::Device,
+        name: &str,
+    ) -> Result<d3d12::RootSignature, DxError> {
+        let visibility = ShaderVisibility::All;
+
+        // Descriptor tables point into `self.parameters`, so this has to outlive serialization
+        let parameters: Vec<RootParameter> = self
+            .parameters
+            .iter()
+            .map(|parameter| match parameter {
+                Parameter::Cbv(binding) => RootParameter::cbv_descriptor(visibility, *binding),
+                Parameter::Srv(binding) => RootParameter::srv_descriptor(visibility, *binding),
+                Parameter::Uav(binding) => RootParameter::uav_descriptor(visibility, *binding),
+                Parameter::Constants { binding, count } => {
+                    RootParameter::constants(visibility, *binding, *count)
+                }
+                Parameter::DescriptorTable(ranges) => {
+                    RootParameter::descriptor_table(visibility, ranges)
+                }
+            })
+            .collect();
+
+        let (blob, error) = check_hr_err! {{
+            let ((blob, error), hr) = d3d12::RootSignature::serialize(
+                d3d12::RootSignatureVersion::V1_0,
+                &parameters,
+                &self.static_samplers,
+                self.flags,
+            );
+
+            // The error blob says what was wrong with the description
+            if !error.is_null() {
+                log::error!("{}: {}", name, unsafe { error.as_c_str() }.to_string_lossy());
+            }
+
+            ((blob, error), hr)
+        }};
+
+        let root_sig = check_hr_err! {{
+            let (root_sig, hr) = device.create_root_signature(blob, 0);
+            unsafe {
+                blob.destroy();
+                if !error.is_null() {
+                    error.destroy();
+                }
+            }
+
+            (root_sig, hr)
+        }};
+        os_helpers::set_name(&root_sig, name);
+
+        Ok(root_sig)
+    }
+}
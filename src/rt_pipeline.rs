@@ -0,0 +1,180 @@
+//! The DXR pipeline state object, assembled out of subobjects
+
+use crate::check_hr_err;
+use crate::d3d12_ext::*;
+use crate::os_helpers::{self, DxError};
+use crate::shaders::wide;
+
+use winapi::shared::winerror::*;
+use winapi::um::d3d12::*;
+use winapi::Interface;
+
+use std::ptr;
+
+/// Export names in the DXIL library, and the hit groups built out of them
+pub const RAYGEN_EXPORT: &str = "RayGen";
+pub const MISS_EXPORT: &str = "Miss";
+pub const SHADOW_MISS_EXPORT: &str = "ShadowMiss";
+pub const CLOSEST_HIT_EXPORT: &str = "ClosestHit";
+pub const ANY_HIT_EXPORT: &str = "AnyHit";
+pub const SHADOW_ANY_HIT_EXPORT: &str = "ShadowAnyHit";
+pub const HIT_GROUP_EXPORT: &str = "HitGroup";
+/// Shadow rays only need to know whether anything is in the way, so this one has no closest hit
+pub const SHADOW_HIT_GROUP_EXPORT: &str = "ShadowHitGroup";
+
+/// A float4 color. The shadow payload is smaller.
+pub const MAX_PAYLOAD_SIZE: u32 = 4 * 4;
+/// Triangle barycentrics, from `BuiltInTriangleIntersectionAttributes`
+pub const MAX_ATTRIBUTE_SIZE: u32 = 2 * 4;
+/// RayGen traces primary rays, and ClosestHit traces a shadow ray from each hit
+pub const MAX_TRACE_RECURSION_DEPTH: u32 = 2;
+
+pub type StateObject = d3d12::WeakPtr<ID3D12StateObject>;
+pub type StateObjectProperties = d3d12::WeakPtr<ID3D12StateObjectProperties>;
+
+pub struct RtPipeline {
+    pub state_object: StateObject,
+    pub properties: StateObjectProperties,
+}
+
+impl RtPipeline {
+    pub fn destroy(&mut self) {
+        unsafe {
+            self.properties.destroy();
+            self.state_object.destroy();
+        }
+    }
+}
+
+/// Look up the identifier of `export` in `props`. This is `None` for names the pipeline doesn't export.
+pub fn shader_identifier(
+    props: &ID3D12StateObjectProperties,
+    export: &str,
+) -> Option<[u8; D3D12_SHADER_IDENTIFIER_SIZE_IN_BYTES]> {
+    let name = wide(export);
+    let identifier = unsafe { props.GetShaderIdentifier(name.as_ptr()) };
+    if identifier.is_null() {
+        return None;
+    }
+
+    let mut bytes = [0u8; D3D12_SHADER_IDENTIFIER_SIZE_IN_BYTES];
+    unsafe {
+        ptr::copy_nonoverlapping(identifier as *const u8, bytes.as_mut_ptr(), bytes.len());
+    }
+
+    Some(bytes)
+}
+
+/// Create a raytracing pipeline out of `lib_blob`, a DXIL library exporting `RAYGEN_EXPORT`,
+/// `MISS_EXPORT`, `SHADOW_MISS_EXPORT`, `CLOSEST_HIT_EXPORT`, `ANY_HIT_EXPORT`, and `SHADOW_ANY_HIT_EXPORT`.
+/// `root_sig` is bound as the global root signature.
+pub fn create_rt_pipeline(
+    device: &d3d12::Device,
+    lib_blob: &[u8],
+    root_sig: d3d12::RootSignature,
+) -> Result<RtPipeline, DxError> {
+    // Every subobject points at these, so they have to outlive CreateStateObject
+    let hit_group_name = wide(HIT_GROUP_EXPORT);
+    let shadow_hit_group_name = wide(SHADOW_HIT_GROUP_EXPORT);
+    let closest_hit_name = wide(CLOSEST_HIT_EXPORT);
+    let any_hit_name = wide(ANY_HIT_EXPORT);
+    let shadow_any_hit_name = wide(SHADOW_ANY_HIT_EXPORT);
+
+    // No exports listed means "export everything in the library"
+    let library = D3D12_DXIL_LIBRARY_DESC {
+        DXILLibrary: D3D12_SHADER_BYTECODE {
+            pShaderBytecode: lib_blob.as_ptr() as *const _,
+            BytecodeLength: lib_blob.len(),
+        },
+        NumExports: 0,
+        pExports: ptr::null(),
+    };
+    let hit_group = D3D12_HIT_GROUP_DESC {
+        HitGroupExport: hit_group_name.as_ptr(),
+        Type: D3D12_HIT_GROUP_TYPE_TRIANGLES,
+        AnyHitShaderImport: any_hit_name.as_ptr(),
+        ClosestHitShaderImport: closest_hit_name.as_ptr(),
+        IntersectionShaderImport: ptr::null(),
+    };
+    let shadow_hit_group = D3D12_HIT_GROUP_DESC {
+        HitGroupExport: shadow_hit_group_name.as_ptr(),
+        Type: D3D12_HIT_GROUP_TYPE_TRIANGLES,
+        AnyHitShaderImport: shadow_any_hit_name.as_ptr(),
+        ClosestHitShaderImport: ptr::null(),
+        IntersectionShaderImport: ptr::null(),
+    };
+    let shader_config = D3D12_RAYTRACING_SHADER_CONFIG {
+        MaxPayloadSizeInBytes: MAX_PAYLOAD_SIZE,
+        MaxAttributeSizeInBytes: MAX_ATTRIBUTE_SIZE,
+    };
+    let global_root_sig = D3D12_GLOBAL_ROOT_SIGNATURE {
+        pGlobalRootSignature: root_sig.as_mut_ptr(),
+    };
+    let pipeline_config = D3D12_RAYTRACING_PIPELINE_CONFIG {
+        MaxTraceRecursionDepth: MAX_TRACE_RECURSION_DEPTH,
+    };
+
+    fn subobject<T>(kind: D3D12_STATE_SUBOBJECT_TYPE, desc: &T) -> D3D12_STATE_SUBOBJECT {
+        D3D12_STATE_SUBOBJECT {
+            Type: kind,
+            pDesc: desc as *const T as *const _,
+        }
+    }
+    let subobjects = [
+        subobject(D3D12_STATE_SUBOBJECT_TYPE_DXIL_LIBRARY, &library),
+        subobject(D3D12_STATE_SUBOBJECT_TYPE_HIT_GROUP, &hit_group),
+        subobject(D3D12_STATE_SUBOBJECT_TYPE_HIT_GROUP, &shadow_hit_group),
+        subobject(
+            D3D12_STATE_SUBOBJECT_TYPE_RAYTRACING_SHADER_CONFIG,
+            &shader_config,
+        ),
+        subobject(
+            D3D12_STATE_SUBOBJECT_TYPE_GLOBAL_ROOT_SIGNATURE,
+            &global_root_sig,
+        ),
+        subobject(
+            D3D12_STATE_SUBOBJECT_TYPE_RAYTRACING_PIPELINE_CONFIG,
+            &pipeline_config,
+        ),
+    ];
+    let desc = D3D12_STATE_OBJECT_DESC {
+        Type: D3D12_STATE_OBJECT_TYPE_RAYTRACING_PIPELINE,
+        NumSubobjects: subobjects.len() as u32,
+        pSubobjects: subobjects.as_ptr(),
+    };
+
+    let device5 = check_hr_err!(unsafe { device.cast::<ID3D12Device5>() });
+    let state_object = check_hr_err! {
+        unsafe {
+            let mut state_object = StateObject::null();
+            let hr = device5.CreateStateObject(
+                &desc,
+                &ID3D12StateObject::uuidof(),
+                state_object.mut_void(),
+            );
+            device5.destroy();
+
+            (state_object, hr)
+        }
+    };
+    os_helpers::set_name(&state_object, "Raytracing Pipeline");
+
+    let properties = match unsafe { state_object.cast::<ID3D12StateObjectProperties>() } {
+        (properties, hr) if SUCCEEDED(hr) => properties,
+        (_, hr) => {
+            unsafe {
+                state_object.destroy();
+            }
+            return Err(DxError {
+                hr,
+                location: format!("{}:{}", file!(), line!()),
+                call: "ID3D12StateObject::QueryInterface(ID3D12StateObjectProperties)".to_string(),
+            });
+        }
+    };
+
+    Ok(RtPipeline {
+        state_object,
+        properties,
+    })
+}
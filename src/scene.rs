@@ -0,0 +1,177 @@
+//! What each frame draws: the triangle, rasterized, drawn by a mesh shader, or raytraced
+
+use crate::camera::{self, OrbitCamera};
+use crate::commands;
+use crate::constant_buffers::ConstantBufferRing;
+use crate::depth;
+use crate::mesh_shader::MeshShaderTriangle;
+use crate::msaa::MsaaTarget;
+use crate::pix;
+use crate::raytrace::{CameraConstants, Raytracer};
+use crate::triangle::{Triangle, TriangleConstants};
+
+use winapi::um::d3d12::*;
+
+use std::time::Instant;
+
+const CLEAR_EVENT_COLOR: u64 = pix::pix_color(0x40, 0x80, 0xff);
+const RAYTRACE_EVENT_COLOR: u64 = pix::pix_color(0xc0, 0x40, 0xff);
+
+pub struct Scene {
+    pub triangle: Triangle,
+    /// Only set in `--mode raytrace`. When it's missing, we rasterize the triangle instead.
+    pub raytracer: Option<Raytracer>,
+    /// Only set in `--mode mesh`. It draws in place of the triangle.
+    pub mesh_shader: Option<MeshShaderTriangle>,
+    pub camera: OrbitCamera,
+    /// Set with `--msaa`. The triangle draws into this, and it resolves into the back buffer.
+    pub msaa: Option<MsaaTarget>,
+    constants: ConstantBufferRing,
+    start_time: Instant,
+    /// Seconds into the animation to draw, in place of the time since we started.
+    /// `--hash-frame` sets this, so the same frame comes out the same on every run.
+    pub fixed_time: Option<f32>,
+    clear_color: [f32; 4],
+}
+
+impl Scene {
+    pub fn new(
+        triangle: Triangle,
+        raytracer: Option<Raytracer>,
+        mesh_shader: Option<MeshShaderTriangle>,
+        msaa: Option<MsaaTarget>,
+        constants: ConstantBufferRing,
+        clear_color: [f32; 4],
+    ) -> Self {
+        Scene {
+            triangle,
+            raytracer,
+            mesh_shader,
+            camera: OrbitCamera::default(),
+            msaa,
+            constants,
+            start_time: Instant::now(),
+            fixed_time: None,
+            clear_color,
+        }
+    }
+
+    /// Start a frame that renders with `frame_index`'s constants.
+    /// Only call this once the GPU is done with the last frame that used the same index.
+    pub fn begin_frame(&mut self, frame_index: u32) {
+        self.constants.begin_frame(frame_index);
+    }
+
+    /// Record the whole frame into `target`, which starts and ends in PRESENT
+    pub fn record(
+        &mut self,
+        cmd_list: &d3d12::GraphicsCommandList,
+        target: d3d12::Resource,
+        rtv: d3d12::CpuDescriptor,
+        dsv: d3d12::CpuDescriptor,
+        width: u32,
+        height: u32,
+    ) {
+        let view_proj = self.camera.view_proj(width, height);
+
+        if let Some(raytracer) = &self.raytracer {
+            // A camera that can't be inverted can't be looked through either, so fall back to no transform
+            let inv_view_proj = camera::inverse(&view_proj).unwrap_or(camera::IDENTITY);
+            let camera_constants = self.constants.write(&CameraConstants { inv_view_proj });
+
+            pix::pix_begin_event(cmd_list, RAYTRACE_EVENT_COLOR, "Raytrace");
+            raytracer.render(cmd_list, target, self.clear_color, camera_constants);
+            pix::pix_end_event(cmd_list);
+            return;
+        }
+
+        // With MSAA, draw into the multisampled target and resolve it at the end
+        let (draw_target, draw_rtv, draw_state) = match &self.msaa {
+            Some(msaa) => (
+                msaa.texture(),
+                msaa.rtv(),
+                D3D12_RESOURCE_STATE_RESOLVE_SOURCE,
+            ),
+            None => (target, rtv, D3D12_RESOURCE_STATE_PRESENT),
+        };
+
+        pix::pix_begin_event(cmd_list, CLEAR_EVENT_COLOR, "Clear");
+        commands::transition(
+            cmd_list,
+            draw_target,
+            draw_state,
+            D3D12_RESOURCE_STATE_RENDER_TARGET,
+        );
+
+        cmd_list.clear_render_target_view(draw_rtv, self.clear_color, &[]);
+        cmd_list.clear_depth_stencil_view(
+            dsv,
+            d3d12::ClearFlags::DEPTH,
+            depth::DEPTH_CLEAR_VALUE,
+            0,
+            &[],
+        );
+        pix::pix_end_event(cmd_list);
+
+        // A quarter turn per second
+        let time = self
+            .fixed_time
+            .unwrap_or_else(|| self.start_time.elapsed().as_secs_f32());
+        let angle = time * std::f32::consts::FRAC_PI_2;
+        let triangle_constants = self
+            .constants
+            .write(&TriangleConstants { view_proj, angle });
+        match &self.mesh_shader {
+            Some(mesh_shader) => {
+                mesh_shader.draw(cmd_list, draw_rtv, width, height, triangle_constants)
+            }
+            None => self
+                .triangle
+                .draw(cmd_list, draw_rtv, width, height, triangle_constants),
+        }
+
+        commands::transition(
+            cmd_list,
+            draw_target,
+            D3D12_RESOURCE_STATE_RENDER_TARGET,
+            draw_state,
+        );
+
+        if let Some(msaa) = &self.msaa {
+            msaa.resolve(cmd_list, target);
+        }
+    }
+
+    /// Like `record`, for another window's `target`, writing constants into that window's `constants` instead.
+    /// The caller starts `constants` on the right frame.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_view(
+        &mut self,
+        constants: &mut ConstantBufferRing,
+        cmd_list: &d3d12::GraphicsCommandList,
+        target: d3d12::Resource,
+        rtv: d3d12::CpuDescriptor,
+        dsv: d3d12::CpuDescriptor,
+        width: u32,
+        height: u32,
+    ) {
+        std::mem::swap(&mut self.constants, constants);
+        self.record(cmd_list, target, rtv, dsv, width, height);
+        std::mem::swap(&mut self.constants, constants);
+    }
+
+    /// Release everything the scene holds on the GPU. Nothing in flight can still be using it.
+    pub fn destroy(&mut self) {
+        if let Some(raytracer) = &mut self.raytracer {
+            raytracer.destroy();
+        }
+        if let Some(mesh_shader) = &mut self.mesh_shader {
+            mesh_shader.destroy();
+        }
+        if let Some(msaa) = &mut self.msaa {
+            msaa.destroy();
+        }
+        self.triangle.destroy();
+        self.constants.destroy();
+    }
+}
@@ -0,0 +1,223 @@
+//! Back buffer screenshots, read back through a buffer and saved as PNG, or hashed for golden-image tests
+
+use crate::buffers;
+use crate::commands;
+use crate::os_helpers::DxError;
+
+use winapi::shared::dxgiformat::*;
+use winapi::shared::winerror::*;
+use winapi::um::d3d12::*;
+
+use std::mem::zeroed;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A copy of a back buffer on its way to the CPU.
+/// The copy is only readable once the command list that recorded it has finished.
+pub struct Screenshot {
+    readback: d3d12::Resource,
+    footprint: D3D12_PLACED_SUBRESOURCE_FOOTPRINT,
+    /// Bytes of pixels in each row, not counting the padding out to `RowPitch`
+    row_size: u64,
+    /// Size of `readback`. The last row isn't padded, so this is less than `RowPitch * height`.
+    total_bytes: u64,
+    width: u32,
+    height: u32,
+}
+
+impl Screenshot {
+    /// Record a copy of `back_buffer` into a new readback buffer.
+    /// `back_buffer` has to be in PRESENT, and is left there.
+    pub fn record(
+        device: &d3d12::Device,
+        cmd_list: &d3d12::GraphicsCommandList,
+        back_buffer: d3d12::Resource,
+    ) -> Result<Self, DxError> {
+        let desc = unsafe { back_buffer.GetDesc() };
+
+        // Buffer rows are padded out to D3D12_TEXTURE_DATA_PITCH_ALIGNMENT, which the footprint accounts for
+        let mut footprint: D3D12_PLACED_SUBRESOURCE_FOOTPRINT = unsafe { zeroed() };
+        let mut row_size = 0;
+        let mut total_bytes = 0;
+        unsafe {
+            device.GetCopyableFootprints(
+                &desc,
+                0,
+                1,
+                0,
+                &mut footprint,
+                std::ptr::null_mut(),
+                &mut row_size,
+                &mut total_bytes,
+            );
+        }
+
+        let readback = buffers::create_buffer(
+            device,
+            total_bytes,
+            D3D12_HEAP_TYPE_READBACK,
+            D3D12_RESOURCE_FLAG_NONE,
+            D3D12_RESOURCE_STATE_COPY_DEST,
+        )?;
+
+        let mut dst: D3D12_TEXTURE_COPY_LOCATION = unsafe { zeroed() };
+        dst.pResource = readback.as_mut_ptr();
+        dst.Type = D3D12_TEXTURE_COPY_TYPE_PLACED_FOOTPRINT;
+        unsafe {
+            *dst.u.PlacedFootprint_mut() = footprint;
+        }
+
+        let mut src: D3D12_TEXTURE_COPY_LOCATION = unsafe { zeroed() };
+        src.pResource = back_buffer.as_mut_ptr();
+        src.Type = D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX;
+        unsafe {
+            *src.u.SubresourceIndex_mut() = 0;
+        }
+
+        commands::transition(
+            cmd_list,
+            back_buffer,
+            D3D12_RESOURCE_STATE_PRESENT,
+            D3D12_RESOURCE_STATE_COPY_SOURCE,
+        );
+        unsafe {
+            cmd_list.CopyTextureRegion(&dst, 0, 0, 0, &src, std::ptr::null());
+        }
+        commands::transition(
+            cmd_list,
+            back_buffer,
+            D3D12_RESOURCE_STATE_COPY_SOURCE,
+            D3D12_RESOURCE_STATE_PRESENT,
+        );
+
+        Ok(Screenshot {
+            readback,
+            footprint,
+            row_size,
+            total_bytes,
+            width: desc.Width as u32,
+            height: desc.Height,
+        })
+    }
+
+    /// The copied pixels, in the back buffer's format, with rows packed tightly together
+    fn read_pixels(self) -> Result<Vec<u8>, String> {
+        let row_pitch = self.footprint.Footprint.RowPitch as usize;
+        let row_bytes = self.row_size as usize;
+        let size = self.total_bytes as usize;
+
+        let (mapped, hr) = self.readback.map(0, Some(0..size));
+        if FAILED(hr) {
+            unsafe {
+                self.readback.destroy();
+            }
+            return Err(format!(
+                "Couldn't map the screenshot: {}",
+                crate::os_helpers::hr_string(hr)
+            ));
+        }
+        let bytes = unsafe { std::slice::from_raw_parts(mapped as *const u8, size) };
+
+        // Copy out row by row, skipping the padding at the end of each row
+        let mut pixels = Vec::with_capacity(row_bytes * self.height as usize);
+        for row in 0..self.height as usize {
+            let start = row * row_pitch;
+            pixels.extend_from_slice(&bytes[start..start + row_bytes]);
+        }
+
+        self.readback.unmap(0, Some(0..0));
+        unsafe {
+            self.readback.destroy();
+        }
+
+        Ok(pixels)
+    }
+
+    /// CRC-32 of the copied pixels, ignoring row padding, so it only changes when the image does
+    pub fn hash(self) -> Result<u32, String> {
+        Ok(crc32(&self.read_pixels()?))
+    }
+
+    /// Convert the copied pixels to RGBA8 and write them to `path` as a PNG
+    pub fn save(self, path: &Path) -> Result<(), String> {
+        let format = self.footprint.Footprint.Format;
+        let (width, height) = (self.width, self.height);
+        let mut pixels = self.read_pixels()?;
+
+        match format {
+            // Offscreen targets behind sRGB views are typeless, and hold the same bytes
+            DXGI_FORMAT_R8G8B8A8_UNORM | DXGI_FORMAT_R8G8B8A8_TYPELESS => {}
+            DXGI_FORMAT_B8G8R8A8_UNORM | DXGI_FORMAT_B8G8R8A8_TYPELESS => {
+                for pixel in pixels.chunks_mut(4) {
+                    pixel.swap(0, 2);
+                }
+            }
+            DXGI_FORMAT_R10G10B10A2_UNORM => {
+                for pixel in pixels.chunks_mut(4) {
+                    let packed = u32::from_le_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+                    let channel = |shift: u32| ((packed >> shift) & 0x3ff) >> 2;
+                    pixel[0] = channel(0) as u8;
+                    pixel[1] = channel(10) as u8;
+                    pixel[2] = channel(20) as u8;
+                    pixel[3] = (((packed >> 30) & 0x3) * 0x55) as u8;
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "Screenshots don't support swapchain format {}",
+                    format
+                ))
+            }
+        }
+
+        image::save_buffer(path, &pixels, width, height, image::ColorType::Rgba8)
+            .map_err(|err| format!("Couldn't write {}: {}", path.display(), err))
+    }
+}
+
+/// A new screenshot filename in the working directory, e.g. "screenshot-1602702000123.png"
+pub fn timestamped_path() -> PathBuf {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis())
+        .unwrap_or(0);
+    PathBuf::from(format!("screenshot-{}.png", millis))
+}
+
+/// The usual CRC-32 (IEEE 802.3, as in zlib and PNG), so hashes can be checked with other tools
+fn crc32(bytes: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = i as u32;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = if crc & 1 != 0 {
+                    0xedb8_8320 ^ (crc >> 1)
+                } else {
+                    crc >> 1
+                };
+                bit += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+
+    !bytes.iter().fold(!0, |crc, &byte| {
+        TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_matches_the_standard_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+}
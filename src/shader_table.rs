@@ -0,0 +1,161 @@
+//! Shader binding tables: the records DispatchRays reads to find each shader
+
+use crate::buffers::{self, align_up};
+use crate::d3d12_ext::*;
+use crate::os_helpers::{self, DxError};
+use crate::rt_pipeline;
+
+use winapi::shared::winerror::*;
+
+/// Where one region of a shader table lives, in the terms `D3D12_DISPATCH_RAYS_DESC` wants
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ShaderTableRegion {
+    pub start_address: u64,
+    pub size_in_bytes: u64,
+    pub stride_in_bytes: u64,
+}
+
+/// The raygen, miss, and hit group regions of one shader table buffer
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ShaderTableLayout {
+    pub raygen: ShaderTableRegion,
+    pub miss: ShaderTableRegion,
+    pub hit_group: ShaderTableRegion,
+}
+
+impl ShaderTableLayout {
+    /// Lay out one raygen record, then `miss_count` miss records and `hit_group_count` hit group records,
+    /// each record holding only a shader identifier.
+    /// Records are padded to the record alignment, and every region starts on the table alignment.
+    /// Addresses are offsets from `base_address`.
+    pub fn new(base_address: u64, miss_count: u64, hit_group_count: u64) -> Self {
+        let stride = align_up(
+            D3D12_SHADER_IDENTIFIER_SIZE_IN_BYTES as u64,
+            D3D12_RAYTRACING_SHADER_RECORD_BYTE_ALIGNMENT,
+        );
+
+        let mut next_start = base_address;
+        let mut region = |count: u64| {
+            let region = ShaderTableRegion {
+                start_address: next_start,
+                size_in_bytes: count * stride,
+                stride_in_bytes: stride,
+            };
+            next_start = align_up(
+                next_start + region.size_in_bytes,
+                D3D12_RAYTRACING_SHADER_TABLE_BYTE_ALIGNMENT,
+            );
+            region
+        };
+
+        ShaderTableLayout {
+            raygen: region(1),
+            miss: region(miss_count),
+            hit_group: region(hit_group_count),
+        }
+    }
+
+    /// Bytes from the start of the first region to the end of the last
+    pub fn total_size(&self) -> u64 {
+        self.hit_group.start_address + self.hit_group.size_in_bytes - self.raygen.start_address
+    }
+}
+
+/// Copy the identifiers for `raygen`, `misses`, and `hit_groups` into a new upload buffer.
+/// Misses and hit groups get a record each, in order, so a ray type's index picks its record.
+/// Upload heaps are fine here: the GPU reads each record a handful of times per dispatch.
+pub fn build_shader_table(
+    device: &d3d12::Device,
+    props: &ID3D12StateObjectProperties,
+    raygen: &str,
+    misses: &[&str],
+    hit_groups: &[&str],
+) -> Result<(d3d12::Resource, ShaderTableLayout), DxError> {
+    let offsets = ShaderTableLayout::new(0, misses.len() as u64, hit_groups.len() as u64);
+
+    // Each export, and where its record starts
+    let record = |region: ShaderTableRegion, index: usize| {
+        (region.start_address + index as u64 * region.stride_in_bytes) as usize
+    };
+    let mut records = vec![(raygen, record(offsets.raygen, 0))];
+    records.extend(
+        misses
+            .iter()
+            .enumerate()
+            .map(|(index, &miss)| (miss, record(offsets.miss, index))),
+    );
+    records.extend(
+        hit_groups
+            .iter()
+            .enumerate()
+            .map(|(index, &hit_group)| (hit_group, record(offsets.hit_group, index))),
+    );
+
+    let mut bytes = vec![0u8; offsets.total_size() as usize];
+    for (export, start) in records {
+        let identifier = match rt_pipeline::shader_identifier(props, export) {
+            Some(identifier) => identifier,
+            None => {
+                return Err(DxError {
+                    hr: E_INVALIDARG,
+                    location: format!("{}:{}", file!(), line!()),
+                    call: format!(
+                        "ID3D12StateObjectProperties::GetShaderIdentifier({:?})",
+                        export
+                    ),
+                });
+            }
+        };
+        bytes[start..start + identifier.len()].copy_from_slice(&identifier);
+    }
+
+    let table = buffers::create_upload_buffer(device, bytes.len() as u64)?;
+    os_helpers::set_name(&table, "Shader Table");
+    if let Err(err) = buffers::upload_bytes(&table, &bytes) {
+        unsafe {
+            table.destroy();
+        }
+        return Err(err);
+    }
+
+    // Upload buffers start at least 64 KiB aligned, so the regions stay aligned
+    let layout = ShaderTableLayout::new(
+        table.gpu_virtual_address(),
+        misses.len() as u64,
+        hit_groups.len() as u64,
+    );
+
+    Ok((table, layout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_record_per_region() {
+        let layout = ShaderTableLayout::new(0, 1, 1);
+
+        assert_eq!(layout.raygen.stride_in_bytes, 32);
+        assert_eq!(layout.raygen.start_address, 0);
+        assert_eq!(layout.raygen.size_in_bytes, 32);
+        assert_eq!(layout.miss.start_address, 64);
+        assert_eq!(layout.miss.size_in_bytes, 32);
+        assert_eq!(layout.hit_group.start_address, 128);
+        assert_eq!(layout.hit_group.size_in_bytes, 32);
+        assert_eq!(layout.total_size(), 160);
+    }
+
+    #[test]
+    fn two_miss_and_hit_group_records() {
+        let layout = ShaderTableLayout::new(0, 2, 2);
+
+        assert_eq!(layout.miss.stride_in_bytes, 32);
+        assert_eq!(layout.hit_group.stride_in_bytes, 32);
+        assert_eq!(layout.miss.start_address, 64);
+        assert_eq!(layout.miss.size_in_bytes, 64);
+        assert_eq!(layout.hit_group.start_address, 128);
+        assert_eq!(layout.hit_group.size_in_bytes, 64);
+        assert_eq!(layout.total_size(), 192);
+    }
+}
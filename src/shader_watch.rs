@@ -0,0 +1,53 @@
+//! Watch `--shader-dir` for edits, so shaders can reload without a restart
+
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver};
+use std::time::Duration;
+
+/// Editors often save with several writes in a row, so wait for them to settle
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+pub struct ShaderWatcher {
+    // Dropping the watcher stops it, so it has to live as long as we're reading `events`
+    _watcher: RecommendedWatcher,
+    events: Receiver<DebouncedEvent>,
+}
+
+impl ShaderWatcher {
+    pub fn new(dir: &Path) -> Result<Self, String> {
+        let (sender, events) = channel();
+        let mut watcher: RecommendedWatcher = Watcher::new(sender, DEBOUNCE)
+            .map_err(|err| format!("Couldn't watch {}: {}", dir.display(), err))?;
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .map_err(|err| format!("Couldn't watch {}: {}", dir.display(), err))?;
+
+        Ok(ShaderWatcher {
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    /// Whether any file in the directory changed since the last call. This never blocks.
+    pub fn changed(&self) -> bool {
+        let mut changed = false;
+        for event in self.events.try_iter() {
+            match event {
+                DebouncedEvent::Write(path)
+                | DebouncedEvent::Create(path)
+                | DebouncedEvent::Rename(_, path) => {
+                    log::debug!("Shader changed: {}", path.display());
+                    changed = true;
+                }
+                DebouncedEvent::Error(err, path) => {
+                    log::error!("Shader watcher error on {:?}: {}", path, err);
+                }
+                _ => {}
+            }
+        }
+
+        changed
+    }
+}
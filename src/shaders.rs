@@ -0,0 +1,331 @@
+//! Shader loading: compiled at runtime through DXC's dxcompiler.dll, or precompiled from disk
+
+use crate::caps;
+use crate::check_hr_only;
+use crate::d3d12_ext::{D3D_SHADER_MODEL_6_3, D3D_SHADER_MODEL_6_5};
+
+use winapi::shared::guiddef::REFIID;
+use winapi::shared::minwindef::LPVOID;
+use winapi::shared::winerror::*;
+use winapi::um::d3d12::{D3D_SHADER_MODEL, D3D_SHADER_MODEL_6_0};
+use winapi::um::libloaderapi::{GetProcAddress, LoadLibraryW};
+use winapi::Interface;
+
+use std::fs;
+use std::io;
+use std::mem::transmute;
+use std::path::Path;
+use std::ptr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+/// The parts of dxcapi.h that we need. winapi doesn't ship it at all.
+#[allow(
+    non_camel_case_types,
+    non_snake_case,
+    non_upper_case_globals,
+    dead_code
+)]
+mod dxc {
+    use winapi::ctypes::c_void;
+    use winapi::shared::basetsd::SIZE_T;
+    use winapi::shared::guiddef::REFIID;
+    use winapi::shared::minwindef::{BOOL, LPCVOID, LPVOID, UINT};
+    use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+    use winapi::um::winnt::{HRESULT, LPCWSTR};
+    use winapi::{DEFINE_GUID, RIDL};
+
+    pub const DXC_CP_UTF8: UINT = 65001;
+
+    DEFINE_GUID! {CLSID_DxcCompiler,
+    0x73e22d93, 0xe6ce, 0x47f3, 0xb5, 0xbf, 0xf0, 0x66, 0x4f, 0x39, 0xc1, 0xb0}
+
+    #[repr(C)]
+    pub struct DxcBuffer {
+        pub Ptr: LPCVOID,
+        pub Size: SIZE_T,
+        pub Encoding: UINT,
+    }
+
+    RIDL! {#[uuid(0x8ba5fb08, 0x5195, 0x40e2, 0xac, 0x58, 0x0d, 0x98, 0x9c, 0x3a, 0x01, 0x02)]
+    interface IDxcBlob(IDxcBlobVtbl): IUnknown(IUnknownVtbl) {
+        fn GetBufferPointer() -> LPVOID,
+        fn GetBufferSize() -> SIZE_T,
+    }}
+
+    RIDL! {#[uuid(0x7241d424, 0x2646, 0x4191, 0x97, 0xc0, 0x98, 0xe9, 0x6e, 0x42, 0xfc, 0x68)]
+    interface IDxcBlobEncoding(IDxcBlobEncodingVtbl): IDxcBlob(IDxcBlobVtbl) {
+        fn GetEncoding(
+            pKnown: *mut BOOL,
+            pCodePage: *mut UINT,
+        ) -> HRESULT,
+    }}
+
+    RIDL! {#[uuid(0xcedb484a, 0xd4e9, 0x445a, 0xb9, 0x91, 0xca, 0x21, 0xca, 0x15, 0x7d, 0xc2)]
+    interface IDxcOperationResult(IDxcOperationResultVtbl): IUnknown(IUnknownVtbl) {
+        fn GetStatus(
+            pStatus: *mut HRESULT,
+        ) -> HRESULT,
+        fn GetResult(
+            ppResult: *mut *mut IDxcBlob,
+        ) -> HRESULT,
+        fn GetErrorBuffer(
+            ppErrors: *mut *mut IDxcBlobEncoding,
+        ) -> HRESULT,
+    }}
+
+    RIDL! {#[uuid(0x228b4687, 0x5a6a, 0x4730, 0x90, 0x0c, 0x97, 0x02, 0xb2, 0x20, 0x3f, 0x54)]
+    interface IDxcCompiler3(IDxcCompiler3Vtbl): IUnknown(IUnknownVtbl) {
+        fn Compile(
+            pSource: *const DxcBuffer,
+            pArguments: *mut LPCWSTR,
+            argCount: UINT,
+            // Really an IDxcIncludeHandler, but we never pass one
+            pIncludeHandler: *mut IUnknown,
+            riid: REFIID,
+            ppResult: *mut LPVOID,
+        ) -> HRESULT,
+        fn Disassemble(
+            pObject: *const DxcBuffer,
+            riid: REFIID,
+            ppResult: *mut LPVOID,
+        ) -> HRESULT,
+    }}
+
+    pub type DxcCreateInstanceProc =
+        unsafe extern "system" fn(rclsid: REFIID, riid: REFIID, ppv: *mut *mut c_void) -> HRESULT;
+}
+
+use dxc::*;
+
+/// Shader stages we know how to use: raster stages, mesh shaders, compute, and libraries for DXR
+pub const SUPPORTED_STAGES: &[&str] = &["vs", "ps", "ms", "cs", "lib"];
+
+/// The highest shader model the device runs, which `default_target` builds profiles from
+static SHADER_MODEL: AtomicU32 = AtomicU32::new(D3D_SHADER_MODEL_6_0);
+
+/// Target the device's shader model by default. DXC can't compile for anything older than 6_0.
+pub fn set_shader_model(model: D3D_SHADER_MODEL) {
+    SHADER_MODEL.store(model.max(D3D_SHADER_MODEL_6_0), Ordering::Relaxed);
+}
+
+/// The profile to compile `stage` with on this device, e.g. "vs_6_5".
+/// DXR libraries need at least 6_3 and mesh shaders 6_5, so they never go lower than that.
+pub fn default_target(stage: &str) -> String {
+    let min_model = match stage {
+        "lib" => D3D_SHADER_MODEL_6_3,
+        "ms" => D3D_SHADER_MODEL_6_5,
+        _ => D3D_SHADER_MODEL_6_0,
+    };
+
+    target_at_least(stage, min_model)
+}
+
+/// Like `default_target`, but never below `min_model`.
+/// Stages paired with a mesh shader use this to match its 6_5.
+pub fn target_at_least(stage: &str, min_model: D3D_SHADER_MODEL) -> String {
+    let model = SHADER_MODEL.load(Ordering::Relaxed).max(min_model);
+
+    format!("{}_{}", stage, caps::shader_model_name(model))
+}
+
+/// A NUL-terminated UTF-16 copy of `text`, for Windows APIs
+pub fn wide(text: &str) -> Vec<u16> {
+    text.encode_utf16().chain(Some(0)).collect()
+}
+
+unsafe fn blob_bytes(blob: &IDxcBlob) -> &[u8] {
+    std::slice::from_raw_parts(blob.GetBufferPointer() as *const u8, blob.GetBufferSize())
+}
+
+/// dxcompiler.dll's `DxcCreateInstance`. The DLL is loaded on first use and stays loaded.
+static CREATE_INSTANCE: OnceLock<Result<DxcCreateInstanceProc, String>> = OnceLock::new();
+
+fn load_dxc() -> Result<DxcCreateInstanceProc, String> {
+    unsafe {
+        let module = LoadLibraryW(wide("dxcompiler.dll").as_ptr());
+        if module.is_null() {
+            return Err(
+                "Couldn't load dxcompiler.dll. Is the DXC redistributable on the PATH?".to_string(),
+            );
+        }
+
+        let create_instance = GetProcAddress(module, b"DxcCreateInstance\0".as_ptr() as *const _);
+        if create_instance.is_null() {
+            return Err("dxcompiler.dll doesn't export DxcCreateInstance".to_string());
+        }
+
+        Ok(transmute(create_instance))
+    }
+}
+
+fn create_compiler() -> Result<d3d12::WeakPtr<IDxcCompiler3>, String> {
+    let create_instance = CREATE_INSTANCE.get_or_init(load_dxc).clone()?;
+
+    unsafe {
+        let mut compiler = d3d12::WeakPtr::<IDxcCompiler3>::null();
+        let hr = create_instance(
+            &CLSID_DxcCompiler,
+            &IDxcCompiler3::uuidof() as REFIID,
+            compiler.mut_void(),
+        );
+        if FAILED(hr) {
+            return Err(format!(
+                "Couldn't create IDxcCompiler3: {}",
+                crate::os_helpers::hr_string(hr)
+            ));
+        }
+
+        Ok(compiler)
+    }
+}
+
+/// Compile HLSL `source` to DXIL, returning the compiled blob or DXC's error output.
+/// `entry` is ignored for `lib_*` targets, which export every entry point.
+pub fn compile_hlsl(source: &str, entry: &str, target: &str) -> Result<Vec<u8>, String> {
+    let stage = target.split('_').next().unwrap_or_default();
+    if !SUPPORTED_STAGES.contains(&stage) {
+        return Err(format!(
+            "Unsupported shader target \"{}\". Expected a profile for one of: {}",
+            target,
+            SUPPORTED_STAGES.join(", ")
+        ));
+    }
+
+    let compiler = create_compiler()?;
+
+    // Library targets don't have a single entry point, so skip -E for them
+    let mut args: Vec<Vec<u16>> = vec![wide("-T"), wide(target)];
+    if !target.starts_with("lib_") {
+        args.push(wide("-E"));
+        args.push(wide(entry));
+    }
+    let mut arg_ptrs: Vec<_> = args.iter().map(|arg| arg.as_ptr()).collect();
+
+    let source_buffer = DxcBuffer {
+        Ptr: source.as_ptr() as *const _,
+        Size: source.len(),
+        Encoding: DXC_CP_UTF8,
+    };
+
+    unsafe {
+        let mut result = d3d12::WeakPtr::<IDxcOperationResult>::null();
+        let hr = compiler.Compile(
+            &source_buffer,
+            arg_ptrs.as_mut_ptr(),
+            arg_ptrs.len() as u32,
+            ptr::null_mut(),
+            &IDxcOperationResult::uuidof(),
+            result.mut_void() as *mut LPVOID,
+        );
+        compiler.destroy();
+        if FAILED(hr) {
+            return Err(format!(
+                "IDxcCompiler3::Compile failed: {}",
+                crate::os_helpers::hr_string(hr)
+            ));
+        }
+
+        let mut status = S_OK;
+        result.GetStatus(&mut status);
+
+        let output = if SUCCEEDED(status) {
+            let mut blob = d3d12::WeakPtr::<IDxcBlob>::null();
+            let hr = check_hr_only!(result.GetResult(blob.mut_void() as *mut *mut _));
+            if FAILED(hr) || blob.is_null() {
+                Err(format!(
+                    "Couldn't get the compiled shader: {}",
+                    crate::os_helpers::hr_string(hr)
+                ))
+            } else {
+                let bytes = blob_bytes(&blob).to_vec();
+                blob.destroy();
+                Ok(bytes)
+            }
+        } else {
+            let mut errors = d3d12::WeakPtr::<IDxcBlobEncoding>::null();
+            result.GetErrorBuffer(errors.mut_void() as *mut *mut _);
+            if errors.is_null() {
+                Err(format!(
+                    "Compilation failed: {}",
+                    crate::os_helpers::hr_string(status)
+                ))
+            } else {
+                // DXC reports errors in UTF-8 unless told otherwise
+                let message = String::from_utf8_lossy(blob_bytes(&errors))
+                    .trim_end_matches('\0')
+                    .to_string();
+                errors.destroy();
+                Err(message)
+            }
+        };
+
+        result.destroy();
+        output
+    }
+}
+
+/// Read a precompiled shader (.cso or .dxil) from disk
+pub fn load_shader_blob(path: &Path) -> io::Result<Vec<u8>> {
+    fs::read(path)
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// Get the DXIL for shader `name`. With a `shader_dir`, this loads `<name>.cso` or `<name>.dxil` from it,
+/// or compiles `<source_name>.hlsl` from it with DXC. `<source_name>.hlsl` is how hot reloading edits shaders,
+/// so it wins over an older `.cso` or `.dxil`. Otherwise we compile the built-in `source`.
+pub fn load_or_compile(
+    shader_dir: Option<&Path>,
+    name: &str,
+    source_name: &str,
+    source: &str,
+    entry: &str,
+    target: &str,
+) -> Result<Vec<u8>, String> {
+    match shader_dir {
+        Some(dir) => {
+            let source_path = dir.join(format!("{}.hlsl", source_name));
+            let source_modified = modified_time(&source_path);
+            let candidates = [
+                dir.join(format!("{}.cso", name)),
+                dir.join(format!("{}.dxil", name)),
+            ];
+            for path in &candidates {
+                if path.exists() {
+                    // Otherwise the precompiled blob would hide every edit to the source
+                    if source_modified.is_some() && source_modified > modified_time(path) {
+                        log::info!(
+                            "{} is newer than {}, so compiling it instead",
+                            source_path.display(),
+                            path.display()
+                        );
+                        break;
+                    }
+
+                    return load_shader_blob(path)
+                        .map_err(|err| format!("Couldn't read {}: {}", path.display(), err));
+                }
+            }
+
+            if source_path.exists() {
+                let source = fs::read_to_string(&source_path)
+                    .map_err(|err| format!("Couldn't read {}: {}", source_path.display(), err))?;
+                return compile_hlsl(&source, entry, target)
+                    .map_err(|err| format!("{}:\n{}", source_path.display(), err));
+            }
+
+            Err(format!(
+                "Couldn't find {}.cso, {}.dxil, or {}.hlsl in {}",
+                name,
+                name,
+                source_name,
+                dir.display()
+            ))
+        }
+        None => compile_hlsl(source, entry, target),
+    }
+}
@@ -0,0 +1,111 @@
+//! CPU/GPU synchronization between frames
+
+use crate::check_hr_err;
+use crate::check_hr_only;
+use crate::commands;
+use crate::os_helpers::DxError;
+
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::winbase::INFINITE;
+
+/// One fence for the whole queue, counting up with every submission
+pub struct QueueFence {
+    fence: d3d12::Fence,
+    event: d3d12::Event,
+    last_value: u64,
+}
+
+impl QueueFence {
+    pub fn new(device: &d3d12::Device) -> Result<Self, DxError> {
+        let fence = check_hr_err!(device.create_fence(0));
+        let event = d3d12::Event::create(false, false);
+
+        Ok(QueueFence {
+            fence,
+            event,
+            last_value: 0,
+        })
+    }
+
+    /// Signal after `ExecuteCommandLists`, returning the value to wait on for that work
+    pub fn signal(&mut self, queue: &d3d12::CommandQueue) -> u64 {
+        self.last_value += 1;
+        check_hr_only!(queue.signal(self.fence, self.last_value));
+
+        self.last_value
+    }
+
+    /// Block until the GPU is done with everything submitted so far
+    pub fn wait_for_last_submission(&self) {
+        self.wait_for_value(self.last_value);
+    }
+
+    pub fn wait_for_value(&self, value: u64) {
+        if self.fence.get_value() < value {
+            check_hr_only!(self.fence.set_event_on_completion(self.event, value));
+            self.event.wait(INFINITE);
+        }
+    }
+
+    /// Make `queue` wait on the GPU, without blocking the CPU, for everything submitted so far
+    pub fn gpu_wait(&self, queue: &d3d12::CommandQueue) {
+        self.gpu_wait_for_value(queue, self.last_value);
+    }
+
+    pub fn gpu_wait_for_value(&self, queue: &d3d12::CommandQueue, value: u64) {
+        check_hr_only!(unsafe { queue.Wait(self.fence.as_mut_ptr(), value) });
+    }
+
+    /// Release the fence and close its event. Wait for the last submission first.
+    pub fn destroy(&mut self) {
+        unsafe {
+            self.fence.destroy();
+            CloseHandle(self.event.0);
+        }
+    }
+}
+
+/// Everything one frame in flight owns. There's one per back buffer, indexed by `GetCurrentBackBufferIndex`,
+/// so the CPU can record a frame while the GPU is still working through the ones before it.
+pub struct FrameContext {
+    pub cmd_allocator: d3d12::CommandAllocator,
+    pub cmd_list: d3d12::GraphicsCommandList,
+    /// What the queue fence will reach once this context's last frame is done
+    fence_value: u64,
+}
+
+impl FrameContext {
+    pub fn new(device: &d3d12::Device, name: &str) -> Result<Self, DxError> {
+        let (cmd_allocator, cmd_list) = commands::create_direct_command_list(device, name)?;
+
+        Ok(FrameContext {
+            cmd_allocator,
+            cmd_list,
+            fence_value: 0,
+        })
+    }
+
+    /// Wait for the GPU to finish this context's last frame, then reset its commands for recording
+    pub fn begin(&self, fence: &QueueFence) {
+        fence.wait_for_value(self.fence_value);
+
+        self.cmd_allocator.reset();
+        check_hr_only!(self
+            .cmd_list
+            .reset(self.cmd_allocator, d3d12::PipelineState::null()));
+    }
+
+    /// Close and execute the commands, remembering when they'll be done
+    pub fn submit(&mut self, queue: &d3d12::CommandQueue, fence: &mut QueueFence) {
+        check_hr_only!(self.cmd_list.close());
+        queue.execute_command_lists(&[self.cmd_list.as_list()]);
+        self.fence_value = fence.signal(queue);
+    }
+
+    pub fn destroy(&mut self) {
+        unsafe {
+            self.cmd_list.destroy();
+            self.cmd_allocator.destroy();
+        }
+    }
+}
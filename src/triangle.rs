@@ -0,0 +1,468 @@
+//! Hello triangle: the smallest raster pipeline that draws something, or whatever `--mesh` loaded
+
+use crate::accel;
+use crate::buffers;
+use crate::camera::Mat4;
+use crate::check_hr_err;
+use crate::mesh::{Mesh, Vertex};
+use crate::os_helpers::{self, DxError};
+use crate::root_sig::RootSignatureBuilder;
+use crate::shaders;
+
+use winapi::shared::dxgiformat::*;
+use winapi::shared::dxgitype::*;
+use winapi::shared::minwindef::{FALSE, TRUE};
+use winapi::shared::winerror::*;
+use winapi::um::d3d12::*;
+use winapi::um::d3dcommon::*;
+use winapi::Interface;
+
+use std::mem::{size_of, size_of_val, zeroed};
+use std::path::Path;
+use std::ptr;
+
+const TRIANGLE_HLSL: &str = r#"
+cbuffer FrameConstants : register(b0) {
+    row_major float4x4 view_proj;
+    float angle;
+};
+
+struct VsOutput {
+    float4 position : SV_Position;
+    float4 color    : COLOR;
+};
+
+VsOutput VSMain(float3 position : POSITION, float4 color : COLOR) {
+    VsOutput output;
+    float s = sin(angle);
+    float c = cos(angle);
+    float2 rotated = float2(c * position.x - s * position.y, s * position.x + c * position.y);
+    output.position = mul(view_proj, float4(rotated, position.z, 1.0));
+    output.color = color;
+    return output;
+}
+
+float4 PSMain(VsOutput input) : SV_Target {
+    return input.color;
+}
+"#;
+
+/// Matches `FrameConstants` in TRIANGLE_HLSL
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct TriangleConstants {
+    pub view_proj: Mat4,
+    /// Rotation about Z, in radians
+    pub angle: f32,
+}
+
+/// Get a shader blob, reporting compile errors through our logging
+fn triangle_shader(
+    shader_dir: Option<&Path>,
+    name: &str,
+    entry: &str,
+    target: &str,
+) -> Result<Vec<u8>, DxError> {
+    shaders::load_or_compile(shader_dir, name, "triangle", TRIANGLE_HLSL, entry, target).map_err(
+        |err| {
+            log::error!("{}", err);
+            DxError {
+                hr: E_FAIL,
+                location: format!("{}:{}", file!(), line!()),
+                call: format!("shaders::load_or_compile({:?}, {:?})", name, target),
+            }
+        },
+    )
+}
+
+/// How the triangle PSO rasterizes
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FillMode {
+    Solid,
+    Wireframe,
+}
+
+impl FillMode {
+    pub fn toggled(self) -> Self {
+        match self {
+            FillMode::Solid => FillMode::Wireframe,
+            FillMode::Wireframe => FillMode::Solid,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FillMode::Solid => "solid",
+            FillMode::Wireframe => "wireframe",
+        }
+    }
+}
+
+fn bytecode(blob: &[u8]) -> D3D12_SHADER_BYTECODE {
+    D3D12_SHADER_BYTECODE {
+        pShaderBytecode: blob.as_ptr() as *const _,
+        BytecodeLength: blob.len(),
+    }
+}
+
+/// A PSO for position + color vertices, drawing into one `rtv_format` target
+/// with `sample_count` samples, without depth.
+/// `conservative` only applies to solid fills, since D3D12 doesn't allow it with wireframe.
+#[allow(clippy::too_many_arguments)]
+pub fn create_triangle_pso(
+    device: &d3d12::Device,
+    root_sig: d3d12::RootSignature,
+    vs_blob: &[u8],
+    ps_blob: &[u8],
+    rtv_format: DXGI_FORMAT,
+    sample_count: u32,
+    fill_mode: FillMode,
+    conservative: bool,
+) -> Result<d3d12::PipelineState, DxError> {
+    let input_elements = [
+        D3D12_INPUT_ELEMENT_DESC {
+            SemanticName: b"POSITION\0".as_ptr() as *const _,
+            SemanticIndex: 0,
+            Format: DXGI_FORMAT_R32G32B32_FLOAT,
+            InputSlot: 0,
+            AlignedByteOffset: 0,
+            InputSlotClass: D3D12_INPUT_CLASSIFICATION_PER_VERTEX_DATA,
+            InstanceDataStepRate: 0,
+        },
+        D3D12_INPUT_ELEMENT_DESC {
+            SemanticName: b"COLOR\0".as_ptr() as *const _,
+            SemanticIndex: 0,
+            Format: DXGI_FORMAT_R32G32B32A32_FLOAT,
+            InputSlot: 0,
+            AlignedByteOffset: size_of::<[f32; 3]>() as u32,
+            InputSlotClass: D3D12_INPUT_CLASSIFICATION_PER_VERTEX_DATA,
+            InstanceDataStepRate: 0,
+        },
+    ];
+
+    let mut desc: D3D12_GRAPHICS_PIPELINE_STATE_DESC = unsafe { zeroed() };
+    desc.pRootSignature = root_sig.as_mut_ptr();
+    desc.VS = bytecode(vs_blob);
+    desc.PS = bytecode(ps_blob);
+    desc.InputLayout = D3D12_INPUT_LAYOUT_DESC {
+        pInputElementDescs: input_elements.as_ptr(),
+        NumElements: input_elements.len() as u32,
+    };
+
+    desc.RasterizerState = D3D12_RASTERIZER_DESC {
+        FillMode: match fill_mode {
+            FillMode::Solid => D3D12_FILL_MODE_SOLID,
+            FillMode::Wireframe => D3D12_FILL_MODE_WIREFRAME,
+        },
+        // The camera can orbit around to the triangle's back
+        CullMode: D3D12_CULL_MODE_NONE,
+        FrontCounterClockwise: FALSE,
+        DepthBias: D3D12_DEFAULT_DEPTH_BIAS as i32,
+        DepthBiasClamp: D3D12_DEFAULT_DEPTH_BIAS_CLAMP,
+        SlopeScaledDepthBias: D3D12_DEFAULT_SLOPE_SCALED_DEPTH_BIAS,
+        DepthClipEnable: TRUE,
+        MultisampleEnable: if sample_count > 1 { TRUE } else { FALSE },
+        AntialiasedLineEnable: FALSE,
+        ForcedSampleCount: 0,
+        ConservativeRaster: if conservative && fill_mode == FillMode::Solid {
+            D3D12_CONSERVATIVE_RASTERIZATION_MODE_ON
+        } else {
+            D3D12_CONSERVATIVE_RASTERIZATION_MODE_OFF
+        },
+    };
+    for target in desc.BlendState.RenderTarget.iter_mut() {
+        target.RenderTargetWriteMask = D3D12_COLOR_WRITE_ENABLE_ALL as u8;
+    }
+    desc.DepthStencilState.DepthEnable = FALSE;
+    desc.DepthStencilState.StencilEnable = FALSE;
+
+    desc.SampleMask = u32::MAX;
+    desc.PrimitiveTopologyType = D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE;
+    desc.NumRenderTargets = 1;
+    desc.RTVFormats[0] = rtv_format;
+    desc.SampleDesc = DXGI_SAMPLE_DESC {
+        Count: sample_count,
+        Quality: 0,
+    };
+
+    let pso = check_hr_err! {
+        unsafe {
+            let mut pso = d3d12::PipelineState::null();
+            let hr = device.CreateGraphicsPipelineState(
+                &desc,
+                &ID3D12PipelineState::uuidof(),
+                pso.mut_void(),
+            );
+
+            (pso, hr)
+        }
+    };
+    let name = match fill_mode {
+        FillMode::Solid => "Triangle PSO",
+        FillMode::Wireframe => "Triangle Wireframe PSO",
+    };
+    os_helpers::set_name(&pso, name);
+
+    Ok(pso)
+}
+
+/// The root signature, and a PSO for each fill mode.
+/// PSOs can't change their rasterizer state, so toggling the fill mode switches between two.
+struct Pipeline {
+    root_sig: d3d12::RootSignature,
+    solid_pso: d3d12::PipelineState,
+    wireframe_pso: d3d12::PipelineState,
+}
+
+impl Pipeline {
+    fn pso(&self, fill_mode: FillMode) -> d3d12::PipelineState {
+        match fill_mode {
+            FillMode::Solid => self.solid_pso,
+            FillMode::Wireframe => self.wireframe_pso,
+        }
+    }
+
+    unsafe fn destroy(&self) {
+        self.wireframe_pso.destroy();
+        self.solid_pso.destroy();
+        self.root_sig.destroy();
+    }
+}
+
+/// Load the triangle's shaders, and build its root signature and PSOs around them
+fn create_pipeline(
+    device: &d3d12::Device,
+    shader_dir: Option<&Path>,
+    rtv_format: DXGI_FORMAT,
+    sample_count: u32,
+    conservative: bool,
+) -> Result<Pipeline, DxError> {
+    let vs_target = shaders::default_target("vs");
+    let ps_target = shaders::default_target("ps");
+    let vs_blob = triangle_shader(shader_dir, "triangle_vs", "VSMain", &vs_target)?;
+    let ps_blob = triangle_shader(shader_dir, "triangle_ps", "PSMain", &ps_target)?;
+
+    let root_sig = RootSignatureBuilder::new()
+        .add_cbv(0)
+        .flags(d3d12::RootSignatureFlags::ALLOW_IA_INPUT_LAYOUT)
+        .build(device, "Triangle Root Signature")?;
+    let create_pso = |fill_mode| {
+        create_triangle_pso(
+            device,
+            root_sig,
+            &vs_blob,
+            &ps_blob,
+            rtv_format,
+            sample_count,
+            fill_mode,
+            conservative,
+        )
+    };
+    let solid_pso = match create_pso(FillMode::Solid) {
+        Ok(pso) => pso,
+        Err(err) => {
+            unsafe {
+                root_sig.destroy();
+            }
+            return Err(err);
+        }
+    };
+    match create_pso(FillMode::Wireframe) {
+        Ok(wireframe_pso) => Ok(Pipeline {
+            root_sig,
+            solid_pso,
+            wireframe_pso,
+        }),
+        Err(err) => {
+            unsafe {
+                solid_pso.destroy();
+                root_sig.destroy();
+            }
+            Err(err)
+        }
+    }
+}
+
+pub struct Triangle {
+    pipeline: Pipeline,
+    vertex_buffer: d3d12::Resource,
+    vertex_count: u32,
+    /// `u32` indices into `vertex_buffer`, three per triangle
+    index_buffer: d3d12::Resource,
+    index_count: u32,
+    /// Hold the mesh until the copies into the vertex and index buffers finish.
+    /// Empty after `release_upload_buffers`.
+    upload_buffers: Vec<d3d12::Resource>,
+    /// What the pipeline draws into, so `reload` can build it the same way
+    rtv_format: DXGI_FORMAT,
+    sample_count: u32,
+    conservative: bool,
+    /// Which PSO `draw` binds. Both are always ready, so this can change any frame.
+    pub fill_mode: FillMode,
+}
+
+fn as_bytes<T: Copy>(items: &[T]) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(items.as_ptr() as *const u8, size_of_val(items)) }
+}
+
+impl Triangle {
+    /// Build the pipeline, and record copying `mesh` into GPU memory into `cmd_list`.
+    /// Call `release_upload_buffers` once those copies finish.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &d3d12::Device,
+        shader_dir: Option<&Path>,
+        rtv_format: DXGI_FORMAT,
+        sample_count: u32,
+        fill_mode: FillMode,
+        conservative: bool,
+        mesh: &Mesh,
+        cmd_list: &d3d12::GraphicsCommandList,
+    ) -> Result<Self, DxError> {
+        let pipeline = create_pipeline(device, shader_dir, rtv_format, sample_count, conservative)?;
+
+        // Both buffers are BLAS inputs too, for the DXR path
+        let (vertex_buffer, vertex_upload) = match buffers::create_default_buffer_with_data(
+            device,
+            cmd_list,
+            as_bytes(&mesh.vertices),
+            D3D12_RESOURCE_STATE_VERTEX_AND_CONSTANT_BUFFER
+                | D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE,
+        ) {
+            Ok(buffers) => buffers,
+            Err(err) => {
+                unsafe {
+                    pipeline.destroy();
+                }
+                return Err(err);
+            }
+        };
+        os_helpers::set_name(&vertex_buffer, "Triangle Vertices");
+
+        let (index_buffer, index_upload) = match buffers::create_default_buffer_with_data(
+            device,
+            cmd_list,
+            as_bytes(&mesh.indices),
+            D3D12_RESOURCE_STATE_INDEX_BUFFER | D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE,
+        ) {
+            Ok(buffers) => buffers,
+            Err(err) => {
+                unsafe {
+                    vertex_upload.destroy();
+                    vertex_buffer.destroy();
+                    pipeline.destroy();
+                }
+                return Err(err);
+            }
+        };
+        os_helpers::set_name(&index_buffer, "Triangle Indices");
+
+        Ok(Triangle {
+            pipeline,
+            vertex_buffer,
+            vertex_count: mesh.vertices.len() as u32,
+            index_buffer,
+            index_count: mesh.indices.len() as u32,
+            upload_buffers: vec![vertex_upload, index_upload],
+            rtv_format,
+            sample_count,
+            conservative,
+            fill_mode,
+        })
+    }
+
+    /// Free the mesh's upload buffers, once the copies recorded by `new` have finished
+    pub fn release_upload_buffers(&mut self) {
+        for buffer in self.upload_buffers.drain(..) {
+            unsafe {
+                buffer.destroy();
+            }
+        }
+    }
+
+    /// Rebuild the pipeline from `shader_dir`. If that fails, the old pipeline stays.
+    /// Nothing in flight can still be using the old pipeline.
+    pub fn reload(&mut self, device: &d3d12::Device, shader_dir: &Path) -> Result<(), DxError> {
+        let pipeline = create_pipeline(
+            device,
+            Some(shader_dir),
+            self.rtv_format,
+            self.sample_count,
+            self.conservative,
+        )?;
+        unsafe {
+            self.pipeline.destroy();
+        }
+        self.pipeline = pipeline;
+
+        Ok(())
+    }
+
+    /// The same vertex and index buffers `draw` uses, for raytracing the same mesh
+    pub fn geometry(&self) -> accel::TriangleGeometry {
+        accel::TriangleGeometry {
+            vertex_buffer: self.vertex_buffer,
+            vertex_count: self.vertex_count,
+            vertex_stride: size_of::<Vertex>() as u64,
+            index_buffer: self.index_buffer,
+            index_count: self.index_count,
+        }
+    }
+
+    /// Draw into `rtv`, covering a `width` x `height` target.
+    /// `constants` is the GPU address of a `TriangleConstants`.
+    pub fn draw(
+        &self,
+        cmd_list: &d3d12::GraphicsCommandList,
+        rtv: d3d12::CpuDescriptor,
+        width: u32,
+        height: u32,
+        constants: u64,
+    ) {
+        let viewport = D3D12_VIEWPORT {
+            TopLeftX: 0.0,
+            TopLeftY: 0.0,
+            Width: width as f32,
+            Height: height as f32,
+            MinDepth: D3D12_MIN_DEPTH,
+            MaxDepth: D3D12_MAX_DEPTH,
+        };
+        let scissor = D3D12_RECT {
+            left: 0,
+            top: 0,
+            right: width as i32,
+            bottom: height as i32,
+        };
+        let vbv = D3D12_VERTEX_BUFFER_VIEW {
+            BufferLocation: self.vertex_buffer.gpu_virtual_address(),
+            SizeInBytes: self.vertex_count * size_of::<Vertex>() as u32,
+            StrideInBytes: size_of::<Vertex>() as u32,
+        };
+
+        cmd_list.set_graphics_root_signature(self.pipeline.root_sig);
+        cmd_list.set_pipeline_state(self.pipeline.pso(self.fill_mode));
+        cmd_list.set_graphics_root_constant_buffer_view(0, constants);
+        cmd_list.set_index_buffer(
+            self.index_buffer.gpu_virtual_address(),
+            self.index_count * size_of::<u32>() as u32,
+            DXGI_FORMAT_R32_UINT,
+        );
+        unsafe {
+            cmd_list.OMSetRenderTargets(1, &rtv, FALSE, ptr::null());
+            cmd_list.RSSetViewports(1, &viewport);
+            cmd_list.RSSetScissorRects(1, &scissor);
+            cmd_list.IASetPrimitiveTopology(D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST);
+            cmd_list.IASetVertexBuffers(0, 1, &vbv);
+            cmd_list.DrawIndexedInstanced(self.index_count, 1, 0, 0, 0);
+        }
+    }
+
+    pub fn destroy(&mut self) {
+        self.release_upload_buffers();
+        unsafe {
+            self.index_buffer.destroy();
+            self.vertex_buffer.destroy();
+            self.pipeline.destroy();
+        }
+    }
+}
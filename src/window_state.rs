@@ -0,0 +1,156 @@
+//! Remembering where the window was, so the next run opens it in the same place
+//!
+//! The state lives in `%APPDATA%\itsy-bitsy\window.toml`.
+
+use crate::status;
+
+use serde::{Deserialize, Serialize};
+
+use winapi::shared::windef::RECT;
+use winapi::um::winuser::{
+    GetMonitorInfoW, MonitorFromRect, MONITORINFO, MONITOR_DEFAULTTONEAREST,
+};
+
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::window::Window;
+
+use std::fs;
+use std::mem::{size_of, zeroed};
+use std::path::PathBuf;
+
+const STATE_DIR_NAME: &str = "itsy-bitsy";
+const STATE_FILE_NAME: &str = "window.toml";
+
+/// The window's outer position and inner size, in physical pixels
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub struct WindowState {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+fn state_path() -> Option<PathBuf> {
+    let app_data = std::env::var_os("APPDATA")?;
+    Some(
+        PathBuf::from(app_data)
+            .join(STATE_DIR_NAME)
+            .join(STATE_FILE_NAME),
+    )
+}
+
+/// What the last run saved. `None` on the first run, or if the file can't be read.
+pub fn load() -> Option<WindowState> {
+    let path = state_path()?;
+    let text = fs::read_to_string(&path).ok()?;
+    match toml::from_str(&text) {
+        Ok(state) => Some(state),
+        Err(err) => {
+            log::warn!("Ignoring {}: {}", path.display(), err);
+            None
+        }
+    }
+}
+
+/// Write `state` for the next run. Failing to is only worth a warning.
+pub fn save(state: &WindowState) {
+    let path = match state_path() {
+        Some(path) => path,
+        None => return,
+    };
+
+    let result = toml::to_string(state)
+        .map_err(|err| err.to_string())
+        .and_then(|text| {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+            }
+            fs::write(&path, text).map_err(|err| err.to_string())
+        });
+    if let Err(err) = result {
+        status!(
+            "⚠️ Couldn't save the window position to {}: {}",
+            path.display(),
+            err
+        );
+    }
+}
+
+/// Where `window` is now. `None` while it's minimized, since there's nothing worth restoring then.
+pub fn current(window: &Window) -> Option<WindowState> {
+    let position = window.outer_position().ok()?;
+    let size = window.inner_size();
+    if size.width == 0 || size.height == 0 {
+        return None;
+    }
+
+    Some(WindowState {
+        x: position.x,
+        y: position.y,
+        width: size.width,
+        height: size.height,
+    })
+}
+
+/// The work area of the monitor nearest `rect`, which leaves out the taskbar.
+/// Monitors that were unplugged since `rect` was saved fall back to the closest one still here.
+fn nearest_work_area(rect: &RECT) -> Option<RECT> {
+    unsafe {
+        let monitor = MonitorFromRect(rect, MONITOR_DEFAULTTONEAREST);
+        let mut info: MONITORINFO = zeroed();
+        info.cbSize = size_of::<MONITORINFO>() as u32;
+        if GetMonitorInfoW(monitor, &mut info) == 0 {
+            return None;
+        }
+        Some(info.rcWork)
+    }
+}
+
+/// Move `window` to where `state` says, clamped so all of it lands on a monitor's work area.
+/// The size only changes if `restore_size` is set.
+pub fn restore(window: &Window, state: &WindowState, restore_size: bool) {
+    // The borders and title bar, which the saved size leaves out
+    let inner = window.inner_size();
+    let outer = window.outer_size();
+    let frame_width = outer.width.saturating_sub(inner.width);
+    let frame_height = outer.height.saturating_sub(inner.height);
+
+    let (width, height) = if restore_size {
+        (state.width, state.height)
+    } else {
+        (inner.width, inner.height)
+    };
+    let saved = RECT {
+        left: state.x,
+        top: state.y,
+        right: state.x + (width + frame_width) as i32,
+        bottom: state.y + (height + frame_height) as i32,
+    };
+    let work_area = match nearest_work_area(&saved) {
+        Some(work_area) => work_area,
+        None => return,
+    };
+
+    let work_width = (work_area.right - work_area.left).max(0) as u32;
+    let work_height = (work_area.bottom - work_area.top).max(0) as u32;
+    let outer_width = (width + frame_width).min(work_width);
+    let outer_height = (height + frame_height).min(work_height);
+
+    let x = state
+        .x
+        .min(work_area.right - outer_width as i32)
+        .max(work_area.left);
+    let y = state
+        .y
+        .min(work_area.bottom - outer_height as i32)
+        .max(work_area.top);
+
+    if restore_size {
+        let size = PhysicalSize::new(
+            outer_width.saturating_sub(frame_width).max(1),
+            outer_height.saturating_sub(frame_height).max(1),
+        );
+        window.set_inner_size(size);
+    }
+    window.set_outer_position(PhysicalPosition::new(x, y));
+}